@@ -0,0 +1,444 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{
+    amount::{Amount, AmountError},
+    block::Block,
+    transaction::Transaction,
+};
+
+/// Represents an account created on the blockchain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Account {
+    /// Public key the account was created with
+    pub public_key: String,
+    pub balance: Amount,
+    /// Number of transfers sent by the account
+    pub nonce: u64,
+}
+
+/// Represents the accounts after applying the blocks of a blockchain, wherever they are stored
+pub trait Accounts {
+    /// Get an account, `None` if it was never created
+    fn account(&self, account_id: &str) -> Option<Account>;
+
+    /// Check if an account was created
+    fn account_exists(&self, account_id: &str) -> bool {
+        self.account(account_id).is_some()
+    }
+
+    /// Get the public key an account was created with
+    fn public_key(&self, account_id: &str) -> Option<String> {
+        self.account(account_id).map(|account| account.public_key)
+    }
+
+    /// Get the nonce the next transfer sent by an account must have
+    fn next_nonce(&self, account_id: &str) -> u64 {
+        self.account(account_id).map_or(0, |account| account.nonce)
+    }
+
+    /// Get the balance of an account, 0 if it was never created
+    fn balance(&self, account_id: &str) -> Amount {
+        self.account(account_id)
+            .map_or(Amount::ZERO, |account| account.balance)
+    }
+}
+
+/// Represents the accounts touched by some blocks: `None` for an account that no longer exists
+pub type AccountChanges = HashMap<String, Option<Account>>;
+
+/// Represents the state of the accounts after applying the blocks of a blockchain, in memory
+///
+/// It is updated as blocks are added, so balances and existence checks don't need to walk the chain.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountState {
+    accounts: HashMap<String, Account>,
+}
+
+impl AccountState {
+    /// Create an empty account state
+    pub fn new() -> AccountState {
+        AccountState::default()
+    }
+
+    /// Rebuild the account state by applying the given blocks in order
+    pub fn from_blocks(blocks: &[Block]) -> AccountState {
+        let mut account_state = AccountState::new();
+        for block in blocks {
+            account_state.apply_block(block);
+        }
+        account_state
+    }
+
+    /// Apply the transactions of a block
+    pub fn apply_block(&mut self, block: &Block) {
+        let mut overlay = AccountOverlay::new(self);
+        overlay.apply_block(block);
+        let changes = overlay.into_changes();
+        self.apply_changes(changes);
+    }
+
+    /// Apply a single transaction
+    pub fn apply_transaction(&mut self, transaction: &Transaction) {
+        let mut overlay = AccountOverlay::new(self);
+        overlay.apply_transaction(transaction);
+        let changes = overlay.into_changes();
+        self.apply_changes(changes);
+    }
+
+    /// Get every account with its id, in no particular order
+    pub fn accounts(&self) -> impl Iterator<Item = (String, Account)> + '_ {
+        self.accounts
+            .iter()
+            .map(|(account_id, account)| (account_id.clone(), account.clone()))
+    }
+
+    /// Write the changes computed by an `AccountOverlay`
+    pub fn apply_changes(&mut self, changes: AccountChanges) {
+        for (account_id, account) in changes {
+            match account {
+                Some(account) => self.accounts.insert(account_id, account),
+                None => self.accounts.remove(&account_id),
+            };
+        }
+    }
+}
+
+impl Accounts for AccountState {
+    fn account(&self, account_id: &str) -> Option<Account> {
+        self.accounts.get(account_id).cloned()
+    }
+}
+
+/// Represents accounts with blocks applied or reverted on top of them, leaving them untouched
+///
+/// It is used to get the accounts of a competing branch from the ones of the best chain, and
+/// to compute the changes to write when a block is added.
+pub struct AccountOverlay<'a> {
+    base: &'a dyn Accounts,
+    changes: AccountChanges,
+}
+
+impl<'a> AccountOverlay<'a> {
+    /// Create an overlay without changes on top of the given accounts
+    pub fn new(base: &'a dyn Accounts) -> Self {
+        AccountOverlay {
+            base,
+            changes: AccountChanges::new(),
+        }
+    }
+
+    /// Apply the transactions of a block, then credit their fees and the block reward to the
+    /// block producer
+    pub fn apply_block(&mut self, block: &Block) {
+        for transaction in &block.transactions {
+            self.apply_transaction(transaction);
+        }
+        if let Some(producer) = &block.header.producer {
+            self.credit(producer, block.producer_credit());
+        }
+    }
+
+    /// Undo the transactions of a block, which must be the last applied one
+    pub fn revert_block(&mut self, block: &Block) {
+        // The producer may be created by the block, so its fees and reward are taken back first
+        if let Some(producer) = &block.header.producer {
+            self.debit(producer, block.producer_credit());
+        }
+        for transaction in block.transactions.iter().rev() {
+            self.revert_transaction(transaction);
+        }
+    }
+
+    /// Apply a single transaction, the fee of a transfer is only taken from its sender
+    ///
+    /// A coinbase changes nothing on its own, its reward is credited with the block.
+    pub fn apply_transaction(&mut self, transaction: &Transaction) {
+        match transaction {
+            Transaction::AccountCreation(account_creation) => {
+                self.changes.insert(
+                    account_creation.account_id.clone(),
+                    Some(Account {
+                        public_key: account_creation.public_key.clone(),
+                        balance: account_creation.initial_balance,
+                        nonce: 0,
+                    }),
+                );
+                if let Some(faucet) = &account_creation.faucet {
+                    self.debit(faucet, Ok(account_creation.initial_balance));
+                }
+            }
+            Transaction::Transfer(transfer) => {
+                self.debit(
+                    &transfer.from_account,
+                    transfer.amount.checked_add(transfer.fee),
+                );
+                self.update(&transfer.from_account, |account| account.nonce += 1);
+                self.credit(&transfer.to_account, Ok(transfer.amount));
+            }
+            Transaction::Coinbase(_) => {}
+        }
+    }
+
+    /// Undo a single transaction
+    fn revert_transaction(&mut self, transaction: &Transaction) {
+        match transaction {
+            Transaction::AccountCreation(account_creation) => {
+                self.changes
+                    .insert(account_creation.account_id.clone(), None);
+                if let Some(faucet) = &account_creation.faucet {
+                    self.credit(faucet, Ok(account_creation.initial_balance));
+                }
+            }
+            Transaction::Transfer(transfer) => {
+                self.debit(&transfer.to_account, Ok(transfer.amount));
+                self.credit(
+                    &transfer.from_account,
+                    transfer.amount.checked_add(transfer.fee),
+                );
+                self.update(&transfer.from_account, |account| account.nonce -= 1);
+            }
+            Transaction::Coinbase(_) => {}
+        }
+    }
+
+    /// Update an existing account, accounts that were never created are left as is
+    fn update(&mut self, account_id: &str, update: impl FnOnce(&mut Account)) {
+        if let Some(mut account) = self.account(account_id) {
+            update(&mut account);
+            self.changes.insert(account_id.to_string(), Some(account));
+        }
+    }
+
+    /// Add to the balance of an existing account
+    ///
+    /// Blocks are validated before they are applied, so the amounts can't overflow.
+    fn credit(&mut self, account_id: &str, amount: Result<Amount, AmountError>) {
+        self.update(account_id, |account| {
+            account.balance = amount
+                .and_then(|amount| account.balance.checked_add(amount))
+                .expect("Validated block should not overflow a balance");
+        });
+    }
+
+    /// Take from the balance of an existing account, see `credit`
+    fn debit(&mut self, account_id: &str, amount: Result<Amount, AmountError>) {
+        self.update(account_id, |account| {
+            account.balance = amount
+                .and_then(|amount| account.balance.checked_sub(amount))
+                .expect("Validated block should not overdraw a balance");
+        });
+    }
+
+    /// Get the accounts touched by the applied and reverted blocks
+    pub fn into_changes(self) -> AccountChanges {
+        self.changes
+    }
+}
+
+impl Accounts for AccountOverlay<'_> {
+    fn account(&self, account_id: &str) -> Option<Account> {
+        match self.changes.get(account_id) {
+            Some(account) => account.clone(),
+            None => self.base.account(account_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::genesis::GenesisConfig;
+    use crate::keys::test_utils::{keypair, network_id};
+
+    #[test]
+    fn test_apply_transaction() {
+        let mut account_state = AccountState::new();
+        account_state.apply_transaction(&Transaction::account_creation(
+            "account_1".to_string(),
+            Amount::new(100),
+            keypair("account_1").public_key(),
+        ));
+        account_state.apply_transaction(&Transaction::account_creation(
+            "account_2".to_string(),
+            Amount::new(500),
+            keypair("account_2").public_key(),
+        ));
+        account_state.apply_transaction(&Transaction::transfer(
+            &network_id(),
+            "account_1".to_string(),
+            "account_2".to_string(),
+            Amount::new(50),
+            Amount::ZERO,
+            0,
+            &keypair("account_1"),
+        ));
+
+        assert!(account_state.account_exists("account_1"));
+        assert!(!account_state.account_exists("Does not exist"));
+        assert_eq!(account_state.balance("account_1"), Amount::new(50));
+        assert_eq!(account_state.balance("account_2"), Amount::new(550));
+        assert_eq!(account_state.balance("Does not exist"), Amount::new(0));
+        assert_eq!(account_state.next_nonce("account_1"), 1);
+    }
+
+    #[test]
+    fn test_overlay() {
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let block_1 = Block::new(
+            &genesis,
+            vec![
+                Transaction::account_creation(
+                    "account_1".to_string(),
+                    Amount::new(100),
+                    keypair("account_1").public_key(),
+                ),
+                Transaction::account_creation(
+                    "account_2".to_string(),
+                    Amount::new(100),
+                    keypair("account_2").public_key(),
+                ),
+            ],
+            0,
+        );
+        let block_2 = Block::new(
+            &block_1,
+            vec![
+                Transaction::transfer(
+                    &network_id(),
+                    "account_1".to_string(),
+                    "account_2".to_string(),
+                    Amount::new(30),
+                    Amount::ZERO,
+                    0,
+                    &keypair("account_1"),
+                ),
+                Transaction::account_creation(
+                    "account_3".to_string(),
+                    Amount::new(100),
+                    keypair("account_3").public_key(),
+                ),
+            ],
+            0,
+        );
+        let account_state = AccountState::from_blocks(&[genesis, block_1.clone()]);
+
+        let mut overlay = AccountOverlay::new(&account_state);
+        overlay.apply_block(&block_2);
+        assert_eq!(overlay.balance("account_1"), Amount::new(70));
+        assert_eq!(overlay.next_nonce("account_1"), 1);
+        assert!(overlay.account_exists("account_3"));
+        // The base accounts are untouched
+        assert_eq!(account_state.balance("account_1"), Amount::new(100));
+
+        overlay.revert_block(&block_2);
+        overlay.revert_block(&block_1);
+        assert!(!overlay.account_exists("account_1"));
+        assert!(!overlay.account_exists("account_3"));
+
+        let mut reverted = account_state.clone();
+        reverted.apply_changes(overlay.into_changes());
+        assert_eq!(reverted, AccountState::new());
+    }
+
+    #[test]
+    fn test_overlay_fees() {
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let block_1 = Block::new(
+            &genesis,
+            vec![Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            )],
+            0,
+        );
+        let block_2 = Block::with_producer(
+            &block_1,
+            Some("producer".to_string()),
+            vec![
+                Transaction::account_creation(
+                    "producer".to_string(),
+                    Amount::ZERO,
+                    keypair("producer").public_key(),
+                ),
+                Transaction::transfer(
+                    &network_id(),
+                    "account_1".to_string(),
+                    "producer".to_string(),
+                    Amount::new(30),
+                    Amount::new(5),
+                    0,
+                    &keypair("account_1"),
+                ),
+            ],
+            0,
+        );
+        let account_state = AccountState::from_blocks(&[genesis, block_1]);
+
+        let mut overlay = AccountOverlay::new(&account_state);
+        overlay.apply_block(&block_2);
+        assert_eq!(overlay.balance("account_1"), Amount::new(65));
+        assert_eq!(overlay.balance("producer"), Amount::new(35));
+
+        // The fees are taken back before the producer creation is undone
+        overlay.revert_block(&block_2);
+        assert_eq!(overlay.balance("account_1"), Amount::new(100));
+        assert!(!overlay.account_exists("producer"));
+    }
+
+    #[test]
+    fn test_overlay_funded_account_creation() {
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let block_1 = Block::new(
+            &genesis,
+            vec![Transaction::account_creation(
+                "faucet".to_string(),
+                Amount::new(100),
+                keypair("faucet").public_key(),
+            )],
+            0,
+        );
+        let block_2 = Block::new(
+            &block_1,
+            vec![Transaction::funded_account_creation(
+                &network_id(),
+                "account_1".to_string(),
+                Amount::new(40),
+                keypair("account_1").public_key(),
+                "faucet".to_string(),
+                &keypair("faucet"),
+            )],
+            0,
+        );
+        let account_state = AccountState::from_blocks(&[genesis, block_1]);
+
+        let mut overlay = AccountOverlay::new(&account_state);
+        overlay.apply_block(&block_2);
+        assert_eq!(overlay.balance("faucet"), Amount::new(60));
+        assert_eq!(overlay.balance("account_1"), Amount::new(40));
+
+        overlay.revert_block(&block_2);
+        assert_eq!(overlay.balance("faucet"), Amount::new(100));
+        assert!(!overlay.account_exists("account_1"));
+    }
+
+    #[test]
+    fn test_from_blocks() {
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let block = Block::new(
+            &genesis,
+            vec![Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            )],
+            0,
+        );
+
+        let account_state = AccountState::from_blocks(&[genesis, block]);
+
+        assert!(account_state.account_exists("account_1"));
+        assert_eq!(account_state.balance("account_1"), Amount::new(100));
+    }
+}
@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+
+/// Represents an amount of funds: a balance, the amount or fee of a transfer, or a block reward
+///
+/// It can't be negative, and its arithmetic is checked: an operation that would overflow or go
+/// below zero returns an error instead of wrapping. It is serialized as a plain number.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Amount(u64);
+
+/// Represents the reason an amount can't be computed
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AmountError {
+    /// The result is larger than the maximum amount
+    #[error("amount overflows")]
+    Overflow,
+    /// The result is below zero
+    #[error("amount underflows")]
+    Underflow,
+    /// A signed value is negative
+    #[error("amount {0} is negative")]
+    Negative(i64),
+}
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX: Amount = Amount(u64::MAX);
+
+    /// Create an amount
+    pub const fn new(value: u64) -> Amount {
+        Amount(value)
+    }
+
+    /// Get the amount as a number
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    /// Check if the amount is 0
+    pub const fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Add two amounts, failing when the result overflows
+    pub fn checked_add(self, other: Amount) -> Result<Amount, AmountError> {
+        self.0
+            .checked_add(other.0)
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+
+    /// Subtract an amount, failing when the result is below zero
+    pub fn checked_sub(self, other: Amount) -> Result<Amount, AmountError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Amount)
+            .ok_or(AmountError::Underflow)
+    }
+
+    /// Multiply the amount by a factor, failing when the result overflows
+    pub fn checked_mul(self, factor: u64) -> Result<Amount, AmountError> {
+        self.0
+            .checked_mul(factor)
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+
+    /// Add amounts, failing when the total overflows
+    pub fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Result<Amount, AmountError> {
+        amounts
+            .into_iter()
+            .try_fold(Amount::ZERO, Amount::checked_add)
+    }
+
+    /// Add two amounts, stopping at the maximum amount
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_add(other.0))
+    }
+
+    /// Multiply the amount by a factor, stopping at the maximum amount
+    pub fn saturating_mul(self, factor: u64) -> Amount {
+        Amount(self.0.saturating_mul(factor))
+    }
+}
+
+impl From<u64> for Amount {
+    fn from(value: u64) -> Self {
+        Amount(value)
+    }
+}
+
+impl From<Amount> for u64 {
+    fn from(amount: Amount) -> Self {
+        amount.0
+    }
+}
+
+impl TryFrom<i64> for Amount {
+    type Error = AmountError;
+
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        u64::try_from(value)
+            .map(Amount)
+            .map_err(|_| AmountError::Negative(value))
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn amount() -> impl Strategy<Value = Amount> {
+        any::<u64>().prop_map(Amount::new)
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let amount = Amount::new(100);
+
+        assert_eq!(amount.checked_add(Amount::new(50)), Ok(Amount::new(150)));
+        assert_eq!(
+            Amount::MAX.checked_add(Amount::new(1)),
+            Err(AmountError::Overflow)
+        );
+        assert_eq!(amount.checked_sub(amount), Ok(Amount::ZERO));
+        assert_eq!(
+            amount.checked_sub(Amount::new(101)),
+            Err(AmountError::Underflow)
+        );
+        assert_eq!(amount.checked_mul(3), Ok(Amount::new(300)));
+        assert_eq!(Amount::MAX.checked_mul(2), Err(AmountError::Overflow));
+        assert_eq!(
+            Amount::checked_sum([amount, amount, amount]),
+            Ok(Amount::new(300))
+        );
+        assert_eq!(
+            Amount::checked_sum([amount, Amount::MAX]),
+            Err(AmountError::Overflow)
+        );
+        assert_eq!(Amount::checked_sum([]), Ok(Amount::ZERO));
+    }
+
+    #[test]
+    fn test_try_from() {
+        assert_eq!(Amount::try_from(0i64), Ok(Amount::ZERO));
+        assert_eq!(Amount::try_from(i64::MAX), Ok(Amount::new(i64::MAX as u64)));
+        assert_eq!(Amount::try_from(-1i64), Err(AmountError::Negative(-1)));
+    }
+
+    #[test]
+    fn test_serde() {
+        assert_eq!(serde_json::to_string(&Amount::new(100)).unwrap(), "100");
+        assert_eq!(
+            serde_json::from_str::<Amount>("100").unwrap(),
+            Amount::new(100)
+        );
+        assert!(serde_json::from_str::<Amount>("-1").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn test_checked_add_matches_integers(a in amount(), b in amount()) {
+            let sum = a.value() as u128 + b.value() as u128;
+            match a.checked_add(b) {
+                Ok(total) => prop_assert_eq!(total.value() as u128, sum),
+                Err(error) => {
+                    prop_assert_eq!(error, AmountError::Overflow);
+                    prop_assert!(sum > u64::MAX as u128);
+                }
+            }
+            prop_assert_eq!(a.checked_add(b), b.checked_add(a));
+            prop_assert_eq!(a.saturating_add(b).value() as u128, sum.min(u64::MAX as u128));
+        }
+
+        #[test]
+        fn test_checked_sub_matches_integers(a in amount(), b in amount()) {
+            match a.checked_sub(b) {
+                Ok(difference) => {
+                    prop_assert!(a >= b);
+                    prop_assert_eq!(difference.checked_add(b), Ok(a));
+                }
+                Err(error) => {
+                    prop_assert_eq!(error, AmountError::Underflow);
+                    prop_assert!(a < b);
+                }
+            }
+        }
+
+        #[test]
+        fn test_checked_mul_matches_integers(a in amount(), factor in any::<u64>()) {
+            let product = a.value() as u128 * factor as u128;
+            match a.checked_mul(factor) {
+                Ok(total) => prop_assert_eq!(total.value() as u128, product),
+                Err(error) => {
+                    prop_assert_eq!(error, AmountError::Overflow);
+                    prop_assert!(product > u64::MAX as u128);
+                }
+            }
+            prop_assert_eq!(
+                a.saturating_mul(factor).value() as u128,
+                product.min(u64::MAX as u128)
+            );
+        }
+
+        #[test]
+        fn test_checked_sum_matches_integers(amounts in prop::collection::vec(amount(), 0..8)) {
+            let sum: u128 = amounts.iter().map(|amount| amount.value() as u128).sum();
+            match Amount::checked_sum(amounts) {
+                Ok(total) => prop_assert_eq!(total.value() as u128, sum),
+                Err(error) => {
+                    prop_assert_eq!(error, AmountError::Overflow);
+                    prop_assert!(sum > u64::MAX as u128);
+                }
+            }
+        }
+
+        #[test]
+        fn test_try_from_rejects_negative_values(value in any::<i64>()) {
+            match Amount::try_from(value) {
+                Ok(amount) => prop_assert_eq!(amount.value() as i128, value as i128),
+                Err(error) => {
+                    prop_assert_eq!(error, AmountError::Negative(value));
+                    prop_assert!(value < 0);
+                }
+            }
+        }
+    }
+}
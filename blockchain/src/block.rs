@@ -1,149 +1,526 @@
 use chrono::Utc;
-use serde_json::json;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use crate::transaction::Transaction;
+use crate::{
+    amount::{Amount, AmountError},
+    encoding::Encode,
+    genesis::GenesisConfig,
+    merkle::{merkle_proof, merkle_root, MerkleProof},
+    proof_of_work::meets_difficulty,
+    transaction::Transaction,
+    validation::BlockRuleError,
+};
 
-/// Represents a block in the blockchain
+/// Time a block can be created after the current time of the node receiving it, in milliseconds
 ///
-/// Note: As this implementation is simple and do not use cryptography, there is no 'nonce' field
-#[derive(Debug, Clone)]
-pub struct Block {
+/// It allows for clocks that are slightly ahead, without letting a producer forge timestamps far
+/// in the future to lower the difficulty of the next blocks.
+pub const MAX_FUTURE_DRIFT: i64 = 2 * 60 * 1_000;
+
+/// Maximum size of the canonical encoding of a block, in bytes
+///
+/// Blocks are gossiped and synced whole, so the network limits are derived from it.
+pub const MAX_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Represents the header of a block
+///
+/// The block hash only covers the header: the transactions are committed to by the
+/// `merkle_root`, so a transaction can be proven to be in a block with the header alone.
+///
+/// The fees of the block transactions, and the reward minted by its coinbase, are credited to the
+/// `producer` account, which must exist once the transactions are applied. They are burned when
+/// the block has no producer.
+///
+/// With proof-of-work, the `nonce` is searched so the hash has at least `difficulty`
+/// leading zero bits. Without it, the difficulty is 0 and any nonce is accepted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHeader {
     pub id: i64,
     pub created_at: i64,
-    pub transactions: Vec<Transaction>,
     pub previous_hash: String,
+    /// Root of the Merkle tree over the hashes of the block transactions
+    pub merkle_root: String,
+    /// Account credited with the fees and the reward of the block
+    pub producer: Option<String>,
+    pub difficulty: u32,
+    pub nonce: u64,
+}
+
+/// Represents a block in the blockchain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Block {
+    pub header: BlockHeader,
+    pub transactions: Vec<Transaction>,
     pub hash: String,
 }
 
+impl BlockHeader {
+    /// Calculate the hash of the header as a string
+    ///
+    /// It is the SHA-256 of the canonical encoding of the header, see the `encoding` module.
+    pub fn hash(&self) -> String {
+        hex::encode(Sha256::digest(self.to_bytes()))
+    }
+}
+
 impl Block {
-    /// Create a new block with the given transactions and the previous block
-    pub fn new(previous_block: &Block, transactions: Vec<Transaction>) -> Block {
-        let previous_hash = previous_block.hash.clone();
-        let created_at = Utc::now().timestamp_millis();
-        let id = previous_block.id + 1;
-        let hash = Self::hash(id, created_at, transactions.clone(), previous_hash.clone());
+    /// Create a new block with the given transactions and the previous block,
+    /// mining it until its hash meets the difficulty
+    ///
+    /// The block has no producer, so the fees of its transactions are burned.
+    pub fn new(previous_block: &Block, transactions: Vec<Transaction>, difficulty: u32) -> Block {
+        Self::with_producer(previous_block, None, transactions, difficulty)
+    }
 
-        Block {
-            id,
+    /// Create a new block crediting the fees of its transactions to the producer account
+    pub fn with_producer(
+        previous_block: &Block,
+        producer: Option<String>,
+        transactions: Vec<Transaction>,
+        difficulty: u32,
+    ) -> Block {
+        let mut block = Self::unsealed(previous_block, producer, transactions, difficulty);
+        block.seal(&AtomicBool::new(false));
+        block
+    }
+
+    /// Create a new block following the previous block, without searching its nonce
+    ///
+    /// Its hash only meets the difficulty once it is sealed, see `seal`.
+    pub fn unsealed(
+        previous_block: &Block,
+        producer: Option<String>,
+        transactions: Vec<Transaction>,
+        difficulty: u32,
+    ) -> Block {
+        // Blocks are created after the previous block, even when mined within the same millisecond
+        let created_at = Utc::now()
+            .timestamp_millis()
+            .max(previous_block.header.created_at.saturating_add(1));
+        let header = BlockHeader {
+            id: previous_block.header.id + 1,
             created_at,
+            previous_hash: previous_block.hash.clone(),
+            merkle_root: Self::merkle_root(&transactions),
+            producer,
+            difficulty,
+            nonce: 0,
+        };
+
+        Block {
+            hash: header.hash(),
+            header,
             transactions,
-            previous_hash,
-            hash,
         }
     }
 
-    /// Create a new genesis block
-    pub fn new_genesis() -> Block {
-        let id = 0;
-        let created_at = Utc::now().timestamp_millis();
-        let transactions = vec![];
-        let previous_hash = "000".to_string();
-        let hash = Self::hash(id, created_at, transactions.clone(), previous_hash.clone());
+    /// Search the nonce from the current one until the hash meets the difficulty, and return
+    /// whether it was found
+    ///
+    /// Once every nonce was tried, `created_at` moves on to the current time, or by a
+    /// millisecond, and the search starts again from 0. The search stops as soon as `cancelled`
+    /// is set, leaving the block unsealed.
+    pub fn seal(&mut self, cancelled: &AtomicBool) -> bool {
+        while !meets_difficulty(&self.hash, self.header.difficulty) {
+            if cancelled.load(Ordering::Relaxed) {
+                return false;
+            }
+            self.header.nonce = match self.header.nonce.checked_add(1) {
+                Some(nonce) => nonce,
+                None => {
+                    self.header.created_at = Utc::now()
+                        .timestamp_millis()
+                        .max(self.header.created_at.saturating_add(1));
+                    0
+                }
+            };
+            self.hash = self.header.hash();
+        }
+        true
+    }
+
+    /// Create the genesis block described by the configuration
+    ///
+    /// As it has no previous block, its `previous_hash` carries the chain id.
+    /// It is not mined: its difficulty is the one of the first block.
+    pub fn new_genesis(config: &GenesisConfig) -> Block {
+        let transactions = config.transactions();
+        let header = BlockHeader {
+            id: 0,
+            created_at: config.timestamp,
+            previous_hash: config.chain_id.clone(),
+            merkle_root: Self::merkle_root(&transactions),
+            producer: None,
+            difficulty: config
+                .proof_of_work
+                .as_ref()
+                .map_or(0, |proof_of_work| proof_of_work.initial_difficulty),
+            nonce: 0,
+        };
 
         Block {
-            id,
-            created_at,
+            hash: header.hash(),
+            header,
             transactions,
-            previous_hash,
-            hash,
         }
     }
 
-    /// Check if the block is valid
-    pub fn is_valid(&self, previous_block: &Block) -> bool {
-        self.previous_hash == previous_block.hash
-            && self.hash
-                == Self::hash(
-                    self.id,
-                    self.created_at,
-                    self.transactions.clone(),
-                    self.previous_hash.clone(),
-                )
-            && self.id == previous_block.id + 1
+    /// Check that the block can follow the previous block, returning the first rule it breaks
+    ///
+    /// Whether the difficulty is the expected one depends on the previous blocks, and whether the
+    /// block is created too far in the future depends on the current time, which are checked by
+    /// the blockchain.
+    pub fn validate(&self, previous_block: &Block) -> Result<(), BlockRuleError> {
+        let expected_id = previous_block.header.id + 1;
+        if self.header.id != expected_id {
+            return Err(BlockRuleError::IdMismatch {
+                id: self.header.id,
+                expected: expected_id,
+            });
+        }
+        if self.header.previous_hash != previous_block.hash {
+            return Err(BlockRuleError::PreviousHashMismatch {
+                previous_hash: self.header.previous_hash.clone(),
+                expected: previous_block.hash.clone(),
+            });
+        }
+        let expected_hash = self.header.hash();
+        if self.hash != expected_hash {
+            return Err(BlockRuleError::HashMismatch {
+                hash: self.hash.clone(),
+                expected: expected_hash,
+            });
+        }
+        let expected_root = Self::merkle_root(&self.transactions);
+        if self.header.merkle_root != expected_root {
+            return Err(BlockRuleError::MerkleRootMismatch {
+                merkle_root: self.header.merkle_root.clone(),
+                expected: expected_root,
+            });
+        }
+        if !meets_difficulty(&self.hash, self.header.difficulty) {
+            return Err(BlockRuleError::InsufficientWork(self.header.difficulty));
+        }
+        if self.header.created_at <= previous_block.header.created_at {
+            return Err(BlockRuleError::TimestampNotIncreasing {
+                created_at: self.header.created_at,
+                previous: previous_block.header.created_at,
+            });
+        }
+        self.validate_size()
+    }
+
+    /// Check that the block encodes to at most `MAX_BLOCK_SIZE` bytes
+    pub fn validate_size(&self) -> Result<(), BlockRuleError> {
+        let size = self.to_bytes().len();
+        if size > MAX_BLOCK_SIZE {
+            return Err(BlockRuleError::TooLarge {
+                size,
+                max: MAX_BLOCK_SIZE,
+            });
+        }
+        Ok(())
     }
 
-    /// Given the block data, calculate the hash as a string
-    fn hash(
-        id: i64,
-        created_at: i64,
-        transactions: Vec<Transaction>,
-        previous_hash: String,
-    ) -> String {
-        let data = json!({
-            "id": id,
-            "created_at": created_at,
-            "transactions": transactions,
-            "previous_hash": previous_hash,
-        });
-
-        let mut hasher = Sha256::new();
-        hasher.update(data.to_string().as_bytes());
-        hasher
-            .finalize()
-            .as_slice()
-            .to_owned()
+    /// Check that the block is not created more than `MAX_FUTURE_DRIFT` after the given time
+    pub fn validate_timestamp(&self, now: i64) -> Result<(), BlockRuleError> {
+        let latest = now.saturating_add(MAX_FUTURE_DRIFT);
+        if self.header.created_at > latest {
+            return Err(BlockRuleError::TimestampInFuture {
+                created_at: self.header.created_at,
+                latest,
+            });
+        }
+        Ok(())
+    }
+
+    /// Build the proof that a transaction is part of the block
+    ///
+    /// It is verified against the header with
+    /// `proof.verify(&transaction.hash(), &header.merkle_root)`.
+    pub fn inclusion_proof(&self, transaction_id: &str) -> Option<MerkleProof> {
+        let index = self
+            .transactions
             .iter()
-            .fold(String::new(), |acc, byte| acc + &format!("{:02x}", byte))
+            .position(|transaction| transaction.id() == transaction_id)?;
+        merkle_proof(&Self::transaction_hashes(&self.transactions), index)
+    }
+
+    /// Get the sum of the fees paid by the block transactions
+    pub fn fees(&self) -> Result<Amount, AmountError> {
+        Amount::checked_sum(self.transactions.iter().map(Transaction::fee))
+    }
+
+    /// Get the reward minted by the coinbase starting the block, 0 without one
+    pub fn reward(&self) -> Amount {
+        match self.transactions.first() {
+            Some(Transaction::Coinbase(coinbase)) => coinbase.amount,
+            _ => Amount::ZERO,
+        }
+    }
+
+    /// Get the amount credited to the block producer: the fees plus the reward
+    pub fn producer_credit(&self) -> Result<Amount, AmountError> {
+        self.fees()?.checked_add(self.reward())
+    }
+
+    /// Calculate the Merkle root of the given transactions
+    fn merkle_root(transactions: &[Transaction]) -> String {
+        merkle_root(&Self::transaction_hashes(transactions))
+    }
+
+    fn transaction_hashes(transactions: &[Transaction]) -> Vec<String> {
+        transactions.iter().map(Transaction::hash).collect()
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::keys::test_utils::{filler_transaction, keypair};
+    use crate::merkle::EMPTY_ROOT;
 
     #[test]
     fn test_new_genesis() {
-        let block = Block::new_genesis();
+        let block = Block::new_genesis(&GenesisConfig::default());
 
-        assert_eq!(block.id, 0);
+        assert_eq!(block.header.id, 0);
         assert_eq!(block.transactions.len(), 0);
-        assert_eq!(block.previous_hash, "000");
+        assert_eq!(block.header.previous_hash, "b-local");
+        assert_eq!(block.header.merkle_root, EMPTY_ROOT);
         assert_eq!(block.hash.len(), 64);
+        assert_eq!(
+            block.hash,
+            Block::new_genesis(&GenesisConfig::default()).hash
+        );
     }
 
     #[test]
     fn test_new() {
-        let genesis = Block::new_genesis();
-        let block = Block::new(&genesis, vec![]);
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let block = Block::new(&genesis, vec![], 0);
 
-        assert_eq!(block.id, 1);
+        assert_eq!(block.header.id, 1);
         assert_eq!(block.transactions.len(), 0);
-        assert_eq!(block.previous_hash, genesis.hash);
+        assert_eq!(block.header.previous_hash, genesis.hash);
         assert_eq!(block.hash.len(), 64);
     }
 
     #[test]
-    fn test_is_valid() {
-        let genesis = Block::new_genesis();
-        let mut block = Block::new(&genesis, vec![]);
+    fn test_validate() {
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let mut block = Block::new(&genesis, vec![], 0);
 
         // Valid block
-        assert!(block.is_valid(&genesis));
+        assert_eq!(block.validate(&genesis), Ok(()));
 
         // Invalid id
-        block.id = 2;
-        assert_eq!(block.is_valid(&genesis), false);
+        block.header.id = 2;
+        assert_eq!(
+            block.validate(&genesis),
+            Err(BlockRuleError::IdMismatch { id: 2, expected: 1 })
+        );
 
         // Invalid hash
-        block = Block::new(&genesis, vec![]);
+        block = Block::new(&genesis, vec![], 0);
         block.hash = "invalid".to_string();
-        assert_eq!(block.is_valid(&genesis), false);
+        assert_eq!(
+            block.validate(&genesis),
+            Err(BlockRuleError::HashMismatch {
+                hash: "invalid".to_string(),
+                expected: block.header.hash()
+            })
+        );
 
         // Invalid previous hash
-        block = Block::new(&genesis, vec![]);
-        block.previous_hash = "invalid".to_string();
-        assert_eq!(block.is_valid(&genesis), false);
+        block = Block::new(&genesis, vec![], 0);
+        block.header.previous_hash = "invalid".to_string();
+        assert_eq!(
+            block.validate(&genesis),
+            Err(BlockRuleError::PreviousHashMismatch {
+                previous_hash: "invalid".to_string(),
+                expected: genesis.hash.clone()
+            })
+        );
+
+        // Transactions not matching the Merkle root
+        block = Block::new(&genesis, vec![], 0);
+        block.transactions.push(Transaction::account_creation(
+            "account_id".to_string(),
+            Amount::new(100),
+            keypair("account_id").public_key(),
+        ));
+        assert!(matches!(
+            block.validate(&genesis),
+            Err(BlockRuleError::MerkleRootMismatch { .. })
+        ));
+
+        // Not enough work
+        block = Block::new(&genesis, vec![], 0);
+        block.header.difficulty = 256;
+        block.hash = block.header.hash();
+        assert_eq!(
+            block.validate(&genesis),
+            Err(BlockRuleError::InsufficientWork(256))
+        );
+
+        // Not created after the previous block
+        for created_at in [genesis.header.created_at, genesis.header.created_at - 1] {
+            block = Block::new(&genesis, vec![], 0);
+            block.header.created_at = created_at;
+            block.hash = block.header.hash();
+            assert_eq!(
+                block.validate(&genesis),
+                Err(BlockRuleError::TimestampNotIncreasing {
+                    created_at,
+                    previous: genesis.header.created_at
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_size() {
+        let blockchain = Blockchain::default();
+        let genesis = blockchain.tip();
+
+        let block = Block::new(
+            genesis,
+            vec![filler_transaction(&blockchain, MAX_BLOCK_SIZE)],
+            0,
+        );
+        assert_eq!(block.to_bytes().len(), MAX_BLOCK_SIZE);
+        assert_eq!(block.validate(genesis), Ok(()));
+
+        let block = Block::new(
+            genesis,
+            vec![filler_transaction(&blockchain, MAX_BLOCK_SIZE + 1)],
+            0,
+        );
+        assert_eq!(
+            block.validate(genesis),
+            Err(BlockRuleError::TooLarge {
+                size: MAX_BLOCK_SIZE + 1,
+                max: MAX_BLOCK_SIZE
+            })
+        );
+    }
+
+    #[test]
+    fn test_new_after_previous_block() {
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let mut previous_block = Block::new(&genesis, vec![], 0);
+        previous_block.header.created_at = i64::MAX - 1;
+
+        // Blocks are created after the previous block, even when its clock is ahead
+        let block = Block::new(&previous_block, vec![], 0);
+        assert_eq!(block.header.created_at, i64::MAX);
+    }
+
+    #[test]
+    fn test_validate_timestamp() {
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let mut block = Block::new(&genesis, vec![], 0);
+        let now = block.header.created_at;
+        assert_eq!(block.validate_timestamp(now), Ok(()));
+
+        // Clocks slightly ahead are accepted
+        block.header.created_at = now + MAX_FUTURE_DRIFT;
+        assert_eq!(block.validate_timestamp(now), Ok(()));
+
+        block.header.created_at = now + MAX_FUTURE_DRIFT + 1;
+        assert_eq!(
+            block.validate_timestamp(now),
+            Err(BlockRuleError::TimestampInFuture {
+                created_at: now + MAX_FUTURE_DRIFT + 1,
+                latest: now + MAX_FUTURE_DRIFT
+            })
+        );
+        assert!(block.validate_timestamp(i64::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_new_with_proof_of_work() {
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let block = Block::new(&genesis, vec![], 8);
+
+        assert!(block.hash.starts_with("00"));
+        assert_eq!(block.header.difficulty, 8);
+        assert_eq!(block.validate(&genesis), Ok(()));
+
+        // Changing the nonce invalidates the work
+        let mut invalid_block = block.clone();
+        invalid_block.header.nonce += 1;
+        assert!(invalid_block.validate(&genesis).is_err());
+    }
+
+    #[test]
+    fn test_seal() {
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let mut block = Block::unsealed(&genesis, None, vec![], 8);
+        assert_eq!(block.header.nonce, 0);
+        assert_eq!(block.hash, block.header.hash());
+
+        // The search stops once cancelled
+        let mut cancelled = block.clone();
+        cancelled.header.difficulty = 256;
+        assert!(!cancelled.seal(&AtomicBool::new(true)));
+        assert_eq!(cancelled.header.nonce, 0);
+
+        // Past the last nonce, the search starts again later
+        block.header.nonce = u64::MAX;
+        block.hash = block.header.hash();
+        while meets_difficulty(&block.hash, 8) {
+            block.header.created_at += 1;
+            block.hash = block.header.hash();
+        }
+        let created_at = block.header.created_at;
+        assert!(block.seal(&AtomicBool::new(false)));
+        assert!(block.header.created_at > created_at);
+        assert!(block.header.nonce < u64::MAX);
+        assert_eq!(block.validate(&genesis), Ok(()));
+    }
+
+    #[test]
+    fn test_inclusion_proof() {
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let transactions: Vec<Transaction> = (0..5)
+            .map(|i| {
+                let account_id = format!("account_{i}");
+                let public_key = keypair(&account_id).public_key();
+                Transaction::account_creation(account_id, Amount::new(100), public_key)
+            })
+            .collect();
+        let block = Block::new(&genesis, transactions.clone(), 0);
+
+        for transaction in &transactions {
+            let proof = block.inclusion_proof(transaction.id()).unwrap();
+            assert!(proof.verify(&transaction.hash(), &block.header.merkle_root));
+            assert!(!proof.verify(&transactions[0].hash(), &genesis.header.merkle_root));
+        }
+        assert_eq!(block.inclusion_proof("unknown"), None);
     }
 
     #[test]
     fn test_hash() {
-        let hash = Block::hash(1, 2, vec![], "000".to_string());
+        let header = BlockHeader {
+            id: 1,
+            created_at: 2,
+            previous_hash: "000".to_string(),
+            merkle_root: EMPTY_ROOT.to_string(),
+            producer: None,
+            difficulty: 0,
+            nonce: 0,
+        };
+        let hash = header.hash();
 
         assert_eq!(hash.len(), 64);
         assert_eq!(
             hash,
-            "44959c0b295fde2d95df31b99e7f51f2b4727d64fd9fa654211ad27421577bb1"
+            "fe1bb0d1194ccb52f19457fc10dfe814c76baaf6471db176285f325cf4c75ab9"
         )
     }
 }
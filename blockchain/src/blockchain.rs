@@ -1,52 +1,795 @@
-use crate::{block::Block, transaction::Transaction};
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicBool;
+
+use crate::{
+    account_state::{Account, AccountChanges, AccountOverlay, AccountState, Accounts},
+    amount::Amount,
+    block::{Block, BlockHeader},
+    genesis::GenesisConfig,
+    merkle::MerkleProof,
+    proof_of_work::{work, ProofOfWork},
+    reward::BlockReward,
+    snapshot::{Snapshot, SnapshotError},
+    store::{ChainStore, MemoryStore, StoreError},
+    supply::SupplyPolicy,
+    transaction::Transaction,
+    validation::{BlockError, BlockRuleError, ChainError, TransactionError, TransactionValidator},
+};
 
 /// Represents a blockchain
-#[derive(Debug, Clone)]
+///
+/// Its blocks and accounts are kept in a `ChainStore`. The best chain is the stored branch
+/// with the most work.
+#[derive(Debug)]
 pub struct Blockchain {
-    pub blocks: Vec<Block>,
+    store: Box<dyn ChainStore>,
+    /// Last block of the best chain, every new block is checked against it
+    tip: Block,
+    /// Hash of the genesis block
+    network_id: String,
+    proof_of_work: Option<ProofOfWork>,
+    block_reward: Option<BlockReward>,
+    supply_policy: SupplyPolicy,
+}
+
+/// Represents how the best chain changed after appending a block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainUpdate {
+    /// The block extends the best chain
+    Extended,
+    /// The block was stored in a branch with less work than the best chain
+    SideBranch,
+    /// The branch of the block has more work than the best chain, which switched to it
+    Reorganized {
+        /// Blocks that left the best chain
+        orphaned: Vec<Block>,
+        /// Blocks that joined the best chain, ending with the appended block
+        connected: Vec<Block>,
+    },
 }
 
 impl Blockchain {
-    // Create a new blockchain with a genesis block
-    pub fn new() -> Blockchain {
-        let blocks = vec![Block::new_genesis()];
-        Blockchain { blocks }
+    /// Create a new blockchain kept in memory, with the genesis block described by the configuration
+    ///
+    /// Panics if the configuration is not valid, see `GenesisConfig::validate`.
+    pub fn new(genesis: &GenesisConfig) -> Blockchain {
+        Blockchain::open(genesis, Box::new(MemoryStore::new()))
+            .expect("Genesis configuration should be valid")
     }
 
-    /// Given a list of transactions, add a new block to the blockchain
-    pub fn add_block(&mut self, transactions: Vec<Transaction>) {
-        let previous_block = self
-            .blocks
+    /// Open the blockchain kept in the store, or start a new one if the store is empty
+    ///
+    /// The stored blockchain is fully re-validated, so a tampered store is rejected with the
+    /// first block and rule that failed.
+    pub fn open(
+        genesis: &GenesisConfig,
+        mut store: Box<dyn ChainStore>,
+    ) -> Result<Blockchain, StoreError> {
+        genesis.validate()?;
+        let genesis_block = genesis.block();
+        match store.block_by_height(0) {
+            Some(block) if block != genesis_block => return Err(StoreError::GenesisMismatch),
+            Some(_) => {}
+            None => {
+                let mut accounts = AccountOverlay::new(&*store);
+                accounts.apply_block(&genesis_block);
+                let changes = accounts.into_changes();
+                store.insert_best_chain(
+                    std::slice::from_ref(&genesis_block),
+                    work(genesis_block.header.difficulty),
+                    changes,
+                );
+            }
+        }
+
+        let blockchain = Blockchain {
+            tip: store.tip().expect("Store should have the genesis block"),
+            store,
+            network_id: genesis_block.hash,
+            proof_of_work: genesis.proof_of_work.clone(),
+            block_reward: genesis.block_reward.clone(),
+            supply_policy: genesis.supply_policy.clone(),
+        };
+        blockchain.validate()?;
+        Ok(blockchain)
+    }
+
+    /// Bootstrap a blockchain in an empty store from a snapshot, trusted to have the given hash
+    ///
+    /// The blocks before the snapshot are not stored, the blocks following it are appended
+    /// and validated as usual.
+    pub fn from_snapshot(
+        genesis: &GenesisConfig,
+        snapshot: &Snapshot,
+        trusted_hash: &str,
+        mut store: Box<dyn ChainStore>,
+    ) -> Result<Blockchain, SnapshotError> {
+        genesis.validate()?;
+        if store.block_by_height(0).is_some() {
+            return Err(SnapshotError::StoreNotEmpty);
+        }
+        let genesis_block = genesis.block();
+        if snapshot.network_id != genesis_block.hash {
+            return Err(SnapshotError::NetworkMismatch);
+        }
+        let hash = snapshot.hash();
+        if hash != trusted_hash {
+            return Err(SnapshotError::HashMismatch {
+                expected: trusted_hash.to_string(),
+                actual: hash,
+            });
+        }
+
+        // Only the last block is committed to by the hash, the others must be linked to it
+        let retarget_window = genesis.proof_of_work.as_ref().map_or(1, |proof_of_work| {
+            proof_of_work.retarget_interval as usize + 1
+        });
+        let (Some(first), Some(last)) = (snapshot.blocks.first(), snapshot.block()) else {
+            return Err(SnapshotError::MissingBlocks {
+                count: 0,
+                expected: retarget_window,
+            });
+        };
+        let expected = retarget_window.min(last.header.id as usize + 1);
+        if snapshot.blocks.len() < expected {
+            return Err(SnapshotError::MissingBlocks {
+                count: snapshot.blocks.len(),
+                expected,
+            });
+        }
+        let invalid_block = |rule| BlockError::InvalidBlock {
+            block_id: first.header.id,
+            rule,
+        };
+        match first.header.id {
+            0 if *first != genesis_block => return Err(SnapshotError::NetworkMismatch),
+            0 => {}
+            1 => first.validate(&genesis_block).map_err(invalid_block)?,
+            _ if first.hash != first.header.hash() => {
+                return Err(invalid_block(BlockRuleError::HashMismatch {
+                    hash: first.hash.clone(),
+                    expected: first.header.hash(),
+                })
+                .into())
+            }
+            _ => {}
+        }
+        for pair in snapshot.blocks.windows(2) {
+            pair[1]
+                .validate(&pair[0])
+                .map_err(|rule| BlockError::InvalidBlock {
+                    block_id: pair[1].header.id,
+                    rule,
+                })?;
+        }
+
+        // Walk the total work back from the last block
+        let mut total_works = Vec::new();
+        let mut total_work = snapshot.total_work;
+        for block in snapshot.blocks.iter().rev() {
+            total_works.push(total_work);
+            total_work = total_work
+                .checked_sub(work(block.header.difficulty))
+                .ok_or(SnapshotError::InvalidTotalWork)?;
+        }
+        total_works.reverse();
+
+        // The best chain is stored at consecutive heights, the genesis block is stored apart
+        // from the snapshot blocks when the ones between them are missing
+        if first.header.id != 0 {
+            store.insert_best_chain(
+                std::slice::from_ref(&genesis_block),
+                work(genesis_block.header.difficulty),
+                AccountChanges::new(),
+            );
+        }
+        for (block, total_work) in snapshot.blocks.iter().zip(total_works) {
+            store.insert_block(block, total_work);
+        }
+        let changes = snapshot
+            .accounts
+            .iter()
+            .map(|(account_id, account)| (account_id.clone(), Some(account.clone())))
+            .collect();
+        store.set_snapshot_height(last.header.id);
+        store.set_best_chain(&snapshot.blocks, changes);
+
+        Ok(Blockchain {
+            tip: last.clone(),
+            store,
+            network_id: genesis_block.hash,
+            proof_of_work: genesis.proof_of_work.clone(),
+            block_reward: genesis.block_reward.clone(),
+            supply_policy: genesis.supply_policy.clone(),
+        })
+    }
+
+    /// Take a snapshot of the accounts after the best chain block at the given height
+    ///
+    /// `None` if the height is past the tip, or before the snapshot the blockchain was
+    /// bootstrapped from.
+    pub fn snapshot(&self, height: i64) -> Option<Snapshot> {
+        if height < self.store.snapshot_height().unwrap_or(0) {
+            return None;
+        }
+        let block = self.store.block_by_height(height)?;
+
+        // Revert the blocks following the snapshot height
+        let mut overlay = AccountOverlay::new(&*self.store);
+        for later_height in (height + 1..=self.tip.header.id).rev() {
+            overlay.revert_block(
+                &self
+                    .store
+                    .block_by_height(later_height)
+                    .expect("Best chain should have every height up to its tip"),
+            );
+        }
+        let changes = overlay.into_changes();
+        let mut accounts = self.store.accounts();
+        for (account_id, account) in changes {
+            match account {
+                Some(account) => accounts.insert(account_id, account),
+                None => accounts.remove(&account_id),
+            };
+        }
+
+        let retarget_window = self.proof_of_work.as_ref().map_or(1, |proof_of_work| {
+            proof_of_work.retarget_interval as usize + 1
+        });
+        Some(Snapshot {
+            network_id: self.network_id.clone(),
+            blocks: self.ancestors(&block, retarget_window),
+            total_work: self.stored_total_work(&block.hash),
+            accounts,
+        })
+    }
+
+    /// Given a list of transactions, add a new block to the blockchain and return it
+    ///
+    /// The block has no producer, so the fees of its transactions are burned.
+    pub fn add_block(&mut self, transactions: Vec<Transaction>) -> Result<&Block, BlockError> {
+        self.add_block_with_producer(None, transactions)
+    }
+
+    /// Add a new block crediting the fees of its transactions to the producer, and return it
+    ///
+    /// On a chain with a block reward, a coinbase minting the reward for the producer is put first.
+    /// The block is validated like a block received from a peer: its transactions must apply in
+    /// order, and the producer must be an account once they are applied. The blockchain is left
+    /// unchanged when it is refused.
+    pub fn add_block_with_producer(
+        &mut self,
+        producer: Option<String>,
+        transactions: Vec<Transaction>,
+    ) -> Result<&Block, BlockError> {
+        let mut block = self.block_template(producer, transactions)?;
+        block.seal(&AtomicBool::new(false));
+        self.validate_header(&block, &self.tip)?;
+        self.extend(block);
+        Ok(&self.tip)
+    }
+
+    /// Build the next block of the best chain crediting the fees of its transactions to the
+    /// producer, without sealing it
+    ///
+    /// On a chain with a block reward, a coinbase minting the reward for the producer is put first.
+    /// The transactions and the producer are validated like those of a block received from a peer,
+    /// so once sealed with `Block::seal`, the block can be added with `append_block`.
+    pub fn block_template(
+        &self,
+        producer: Option<String>,
+        transactions: Vec<Transaction>,
+    ) -> Result<Block, BlockError> {
+        let transactions = match &self.block_reward {
+            Some(block_reward) => {
+                let coinbase = Transaction::coinbase(block_reward.reward(self.tip.header.id + 1));
+                std::iter::once(coinbase).chain(transactions).collect()
+            }
+            None => transactions,
+        };
+        let block = Block::unsealed(&self.tip, producer, transactions, self.next_difficulty());
+        block
+            .validate_size()
+            .map_err(|rule| BlockError::InvalidBlock {
+                block_id: block.header.id,
+                rule,
+            })?;
+        self.validate_content(&block, &*self.store)?;
+        Ok(block)
+    }
+
+    /// Append a block received from a peer
+    ///
+    /// The block can extend the best chain or any known branch. When its branch gets more work
+    /// than the best chain, the best chain switches to it.
+    pub fn append_block(&mut self, block: Block) -> Result<ChainUpdate, BlockError> {
+        if self.store.contains_block(&block.hash) {
+            return Err(BlockError::AlreadyKnown {
+                block_id: block.header.id,
+            });
+        }
+        // Only checked on arrival, as the stored blocks are checked again after the clock moved on
+        block
+            .validate_timestamp(chrono::Utc::now().timestamp_millis())
+            .map_err(|rule| BlockError::InvalidBlock {
+                block_id: block.header.id,
+                rule,
+            })?;
+        if block.header.previous_hash == self.tip.hash {
+            self.validate_block(&block, &self.tip, &*self.store)?;
+            self.extend(block);
+            return Ok(ChainUpdate::Extended);
+        }
+        let Some(parent) = self.store.block_by_hash(&block.header.previous_hash) else {
+            return Err(BlockError::UnknownParent {
+                block_id: block.header.id,
+            });
+        };
+
+        // The block starts or extends a competing branch, walk it back to the best chain
+        let mut branch = Vec::new();
+        let mut fork = parent;
+        while !self.is_in_best_chain(&fork) {
+            let previous = self.stored_block(&fork.header.previous_hash);
+            branch.push(fork);
+            fork = previous;
+        }
+        branch.reverse();
+        let orphaned: Vec<Block> = (fork.header.id + 1..=self.tip.header.id)
+            .map(|height| {
+                self.store
+                    .block_by_height(height)
+                    .expect("Best chain should have every height up to its tip")
+            })
+            .collect();
+
+        // Validate the block against the accounts of its branch
+        let mut accounts = AccountOverlay::new(&*self.store);
+        for orphaned_block in orphaned.iter().rev() {
+            accounts.revert_block(orphaned_block);
+        }
+        for branch_block in &branch {
+            accounts.apply_block(branch_block);
+        }
+        self.validate_block(&block, branch.last().unwrap_or(&fork), &accounts)?;
+        accounts.apply_block(&block);
+        let changes = accounts.into_changes();
+
+        let total_work =
+            self.stored_total_work(&block.header.previous_hash) + work(block.header.difficulty);
+        if total_work <= self.total_work() {
+            self.store.insert_block(&block, total_work);
+            return Ok(ChainUpdate::SideBranch);
+        }
+
+        branch.push(block);
+        self.store.insert_best_chain(&branch, total_work, changes);
+        self.tip = branch
             .last()
-            .expect("Blockchain should have at least one block");
-        let block = Block::new(previous_block, transactions);
-        self.blocks.push(block);
-    }
-
-    /// Check if the blockchain is valid
-    pub fn is_valid(&self) -> bool {
-        // Loop through all blocks
-        self.blocks.iter().enumerate().all(|(i, block)| {
-            // Skip genesis block
-            if i == 0 {
-                block.id == 0
-            // Check if the block is valid
-            } else {
-                let previous_block = &self
-                    .blocks
-                    .get(i - 1)
-                    .expect("Blockchain should have at least one block");
-                block.is_valid(previous_block)
+            .expect("Branch should end with the block")
+            .clone();
+
+        Ok(ChainUpdate::Reorganized {
+            orphaned,
+            connected: branch,
+        })
+    }
+
+    /// Add a validated block to the end of the best chain
+    fn extend(&mut self, block: Block) {
+        let mut accounts = AccountOverlay::new(&*self.store);
+        accounts.apply_block(&block);
+        let changes = accounts.into_changes();
+
+        let total_work = self.total_work() + work(block.header.difficulty);
+        self.store
+            .insert_best_chain(std::slice::from_ref(&block), total_work, changes);
+        self.tip = block;
+    }
+
+    /// Check that a block can follow the previous block, with the accounts of its branch
+    fn validate_block(
+        &self,
+        block: &Block,
+        previous_block: &Block,
+        accounts: &dyn Accounts,
+    ) -> Result<(), BlockError> {
+        self.validate_header(block, previous_block)?;
+        self.validate_content(block, accounts)
+    }
+
+    /// Check that the transactions of a block apply in order on top of the accounts, and can
+    /// credit its producer
+    fn validate_content(&self, block: &Block, accounts: &dyn Accounts) -> Result<(), BlockError> {
+        let transactions = self.validate_coinbase(block)?;
+        let mut validator = TransactionValidator::with_supply_policy(
+            &self.network_id,
+            accounts,
+            &self.supply_policy,
+        );
+        transactions
+            .iter()
+            .try_for_each(|transaction| validator.validate(transaction))
+            .map_err(|error| BlockError::InvalidTransaction {
+                block_id: block.header.id,
+                error,
+            })?;
+        let Some(producer) = &block.header.producer else {
+            return Ok(());
+        };
+        if !validator.account_exists(producer) {
+            return Err(BlockError::UnknownProducer {
+                block_id: block.header.id,
+                producer: producer.clone(),
+            });
+        }
+        block
+            .producer_credit()
+            .and_then(|credit| validator.credit(producer, credit))
+            .map_err(|_| BlockError::ProducerBalanceOverflow {
+                block_id: block.header.id,
+                producer: producer.clone(),
+            })
+    }
+
+    /// Check that a block can follow the previous block, without checking its transactions
+    fn validate_header(&self, block: &Block, previous_block: &Block) -> Result<(), BlockError> {
+        block
+            .validate(previous_block)
+            .map_err(|rule| BlockError::InvalidBlock {
+                block_id: block.header.id,
+                rule,
+            })?;
+        let expected = self.difficulty_after(previous_block);
+        if block.header.difficulty != expected {
+            return Err(BlockError::InvalidDifficulty {
+                block_id: block.header.id,
+                difficulty: block.header.difficulty,
+                expected,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check that a block of a chain with a reward starts with a coinbase minting the reward of
+    /// its height, and get the transactions following it
+    ///
+    /// Any other coinbase is refused with the transactions, see `TransactionValidator::validate`.
+    fn validate_coinbase<'b>(&self, block: &'b Block) -> Result<&'b [Transaction], BlockError> {
+        let Some(block_reward) = &self.block_reward else {
+            return Ok(&block.transactions);
+        };
+        let block_id = block.header.id;
+        let Some((Transaction::Coinbase(coinbase), transactions)) =
+            block.transactions.split_first()
+        else {
+            return Err(BlockError::MissingCoinbase { block_id });
+        };
+
+        let expected = block_reward.reward(block_id);
+        if coinbase.amount != expected {
+            return Err(BlockError::InvalidReward {
+                block_id,
+                reward: coinbase.amount,
+                expected,
+            });
+        }
+        Ok(transactions)
+    }
+
+    /// Get the total work of the best chain
+    pub fn total_work(&self) -> u128 {
+        self.stored_total_work(&self.tip.hash)
+    }
+
+    /// Check if a block is known, in the best chain or in a competing branch
+    pub fn contains_block(&self, hash: &str) -> bool {
+        self.store.contains_block(hash)
+    }
+
+    /// Check if a block is part of the best chain
+    fn is_in_best_chain(&self, block: &Block) -> bool {
+        self.store
+            .block_by_height(block.header.id)
+            .is_some_and(|best_block| best_block.hash == block.hash)
+    }
+
+    /// Get a block the store must have, such as the previous block of a stored one
+    fn stored_block(&self, hash: &str) -> Block {
+        self.store
+            .block_by_hash(hash)
+            .expect("Previous block of a stored block should be stored")
+    }
+
+    fn stored_total_work(&self, hash: &str) -> u128 {
+        self.store
+            .total_work(hash)
+            .expect("Stored block should have a total work")
+    }
+
+    /// Get the identity of the network the blockchain belongs to: the hash of its genesis block
+    ///
+    /// Nodes built from different genesis configurations can't exchange blocks.
+    pub fn network_id(&self) -> &str {
+        &self.network_id
+    }
+
+    /// Get the last block of the blockchain
+    pub fn tip(&self) -> &Block {
+        &self.tip
+    }
+
+    /// Get the height of the blockchain: the id of its last block
+    pub fn height(&self) -> i64 {
+        self.tip.header.id
+    }
+
+    /// Get the block of the best chain with the given id
+    pub fn block_by_height(&self, height: i64) -> Option<Block> {
+        self.store.block_by_height(height)
+    }
+
+    /// Get a known block, in the best chain or in a competing branch
+    pub fn block_by_hash(&self, hash: &str) -> Option<Block> {
+        self.store.block_by_hash(hash)
+    }
+
+    /// Get the difficulty the next block must meet, 0 without proof-of-work
+    pub fn next_difficulty(&self) -> u32 {
+        self.difficulty_after(&self.tip)
+    }
+
+    /// Get the difficulty of the block following the given block
+    fn difficulty_after(&self, previous_block: &Block) -> u32 {
+        self.proof_of_work.as_ref().map_or(0, |proof_of_work| {
+            // Only the blocks of the retarget window are needed
+            let count = proof_of_work.retarget_interval as usize + 1;
+            proof_of_work.next_difficulty(&self.ancestors(previous_block, count))
+        })
+    }
+
+    /// Get at most `count` blocks of the branch ending with the given block, ending with it
+    fn ancestors(&self, block: &Block, count: usize) -> Vec<Block> {
+        let mut ancestors = vec![block.clone()];
+        while ancestors.len() < count {
+            let oldest = ancestors.last().expect("Ancestors should not be empty");
+            if oldest.header.id == 0 {
+                break;
             }
+            // A blockchain bootstrapped from a snapshot misses the blocks before it
+            let Some(previous) = self.store.block_by_hash(&oldest.header.previous_hash) else {
+                break;
+            };
+            ancestors.push(previous);
+        }
+        ancestors.reverse();
+        ancestors
+    }
+
+    /// Get at most `count` blocks, starting from the block with the given id
+    pub fn get_blocks(&self, from_id: i64, count: usize) -> Vec<Block> {
+        (from_id.max(0)..=self.tip.header.id)
+            .take(count)
+            .map_while(|height| self.store.block_by_height(height))
+            .collect()
+    }
+
+    /// Get the header of the best chain block including a transaction, and the proof of its inclusion
+    ///
+    /// A light client only needs the header and the proof to check that the transaction was included.
+    pub fn inclusion_proof(&self, transaction_id: &str) -> Option<(BlockHeader, MerkleProof)> {
+        (0..=self.tip.header.id).rev().find_map(|height| {
+            let block = self.store.block_by_height(height)?;
+            block
+                .inclusion_proof(transaction_id)
+                .map(|proof| (block.header, proof))
         })
     }
 
+    /// Check the whole best chain, returning the first block and rule that failed
+    ///
+    /// The blocks are replayed from the genesis block on new accounts, checking their
+    /// transactions as when they were appended, and the replayed accounts must be the stored
+    /// ones. The blocks before a snapshot are missing, so the transactions of a chain
+    /// bootstrapped from one are not checked again.
+    pub fn validate(&self) -> Result<(), ChainError> {
+        let genesis = self
+            .store
+            .block_by_height(0)
+            .ok_or(ChainError::MissingBlock { block_id: 0 })?;
+        if genesis.hash != self.network_id {
+            return Err(ChainError::GenesisMismatch {
+                hash: genesis.hash,
+                network_id: self.network_id.clone(),
+            });
+        }
+
+        // Check every block against the previous one, from the snapshot if bootstrapped from one
+        let snapshot_height = self.store.snapshot_height();
+        let mut accounts = snapshot_height
+            .is_none()
+            .then(|| AccountState::from_blocks(std::slice::from_ref(&genesis)));
+        let start = snapshot_height.unwrap_or(0);
+        let mut previous_block = self
+            .store
+            .block_by_height(start)
+            .ok_or(ChainError::MissingBlock { block_id: start })?;
+        for height in start + 1..=self.tip.header.id {
+            let block = self
+                .store
+                .block_by_height(height)
+                .ok_or(ChainError::MissingBlock { block_id: height })?;
+            if block.header.id != height {
+                return Err(ChainError::InvalidBlock(BlockError::InvalidBlock {
+                    block_id: height,
+                    rule: BlockRuleError::IdMismatch {
+                        id: block.header.id,
+                        expected: height,
+                    },
+                }));
+            }
+            match &mut accounts {
+                Some(accounts) => {
+                    self.validate_block(&block, &previous_block, accounts)?;
+                    accounts.apply_block(&block);
+                }
+                None => self.validate_header(&block, &previous_block)?,
+            }
+            previous_block = block;
+        }
+
+        if previous_block != self.tip {
+            return Err(ChainError::TipMismatch {
+                hash: previous_block.hash,
+                tip: self.tip.hash.clone(),
+            });
+        }
+        let Some(accounts) = accounts else {
+            return Ok(());
+        };
+        let replayed: BTreeMap<String, Account> = accounts.accounts().collect();
+        let stored = self.store.accounts();
+        match replayed
+            .keys()
+            .chain(stored.keys())
+            .find(|account_id| replayed.get(*account_id) != stored.get(*account_id))
+        {
+            Some(account_id) => Err(ChainError::AccountMismatch {
+                account_id: account_id.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that the transactions can be applied in order on top of the blockchain
+    pub fn validate_transactions(
+        &self,
+        transactions: &[Transaction],
+    ) -> Result<(), TransactionError> {
+        let mut validator = self.transaction_validator();
+        transactions
+            .iter()
+            .try_for_each(|transaction| validator.validate(transaction))
+    }
+
     /// Check if an account exists in the blockchain
     pub fn account_exists(&self, account_id: &str) -> bool {
-        // Loop through all transactions of all blocks
-        // and check if there is an account creation transaction
-        // with the given account_id
-        self.blocks.iter().any(|block| {
+        self.store.account_exists(account_id)
+    }
+
+    /// Get the balance of an account
+    pub fn get_account_balance(&self, account_id: &str) -> Amount {
+        self.store.balance(account_id)
+    }
+
+    /// Get the reward minted by the coinbase of every block, `None` if blocks have no coinbase
+    pub fn block_reward(&self) -> Option<&BlockReward> {
+        self.block_reward.as_ref()
+    }
+
+    /// Get the policy deciding who can fund the initial balance of new accounts
+    pub fn supply_policy(&self) -> &SupplyPolicy {
+        &self.supply_policy
+    }
+
+    /// Get the accounts at the tip of the blockchain
+    pub fn account_state(&self) -> &dyn Accounts {
+        &*self.store
+    }
+
+    /// Create a validator of the transactions following the tip, enforcing the supply policy
+    pub fn transaction_validator(&self) -> TransactionValidator<'_> {
+        TransactionValidator::with_supply_policy(
+            &self.network_id,
+            self.account_state(),
+            &self.supply_policy,
+        )
+    }
+}
+
+impl Default for Blockchain {
+    fn default() -> Self {
+        Self::new(&GenesisConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::genesis::{GenesisAllocation, GenesisError};
+    use crate::keys::test_utils::{keypair, network_id};
+    use crate::redb_store::RedbStore;
+    use crate::snapshot::SnapshotError;
+
+    /// Open a blockchain whose store has a genesis block and a tampered block
+    fn open_tampered(tamper: impl FnOnce(&mut Block)) -> Result<Blockchain, StoreError> {
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let mut block = Block::new(&genesis, vec![], 0);
+        tamper(&mut block);
+        open_stored(vec![genesis, block], AccountChanges::new())
+    }
+
+    /// Open a blockchain whose store has the given best chain and accounts, without checking them
+    fn open_stored(blocks: Vec<Block>, accounts: AccountChanges) -> Result<Blockchain, StoreError> {
+        let mut store = MemoryStore::new();
+        for (total_work, block) in blocks.iter().enumerate() {
+            store.insert_block(block, total_work as u128 + 1);
+        }
+        store.set_best_chain(&blocks, accounts);
+        Blockchain::open(&GenesisConfig::default(), Box::new(store))
+    }
+
+    /// Get the reason a stored chain is not valid
+    fn chain_error(error: &StoreError) -> &ChainError {
+        let StoreError::InvalidChain(error) = error else {
+            panic!("unexpected error {error:?}");
+        };
+        error
+    }
+
+    /// Reference balance calculation, walking every transaction of every block
+    fn fold_account_balance(blocks: &[Block], account_id: &str) -> Amount {
+        blocks.iter().fold(Amount::ZERO, |balance, block| {
+            block
+                .transactions
+                .iter()
+                .fold(balance, |balance, transaction| match transaction {
+                    Transaction::AccountCreation(account_creation) => {
+                        if account_creation.account_id == account_id {
+                            balance
+                                .checked_add(account_creation.initial_balance)
+                                .unwrap()
+                        } else if account_creation.faucet.as_deref() == Some(account_id) {
+                            balance
+                                .checked_sub(account_creation.initial_balance)
+                                .unwrap()
+                        } else {
+                            balance
+                        }
+                    }
+                    Transaction::Transfer(transfer) => {
+                        if transfer.from_account == account_id {
+                            balance.checked_sub(transfer.amount).unwrap()
+                        } else if transfer.to_account == account_id {
+                            balance.checked_add(transfer.amount).unwrap()
+                        } else {
+                            balance
+                        }
+                    }
+                    Transaction::Coinbase(coinbase) => {
+                        if block.header.producer.as_deref() == Some(account_id) {
+                            balance.checked_add(coinbase.amount).unwrap()
+                        } else {
+                            balance
+                        }
+                    }
+                })
+        })
+    }
+
+    /// Reference existence check, walking every transaction of every block
+    fn fold_account_exists(blocks: &[Block], account_id: &str) -> bool {
+        blocks.iter().any(|block| {
             block
                 .transactions
                 .iter()
@@ -59,114 +802,1294 @@ impl Blockchain {
         })
     }
 
-    /// Get the balance of an account
-    pub fn get_account_balance(&self, account_id: &str) -> i64 {
-        // Loop through all transactions of all blocks
-        self.blocks.iter().fold(0, |balance, block| {
-            balance
-                + block.transactions.iter().fold(
-                    0,
-                    |block_balance, transaction| match transaction {
-                        Transaction::AccountCreation(account_creation) => {
-                            // Add initial balance at account creation
-                            if account_creation.account_id == account_id {
-                                block_balance + account_creation.initial_balance
-                            } else {
-                                block_balance
-                            }
-                        }
-                        Transaction::Transfer(transfer) => {
-                            // Subtract amount if account is the sender
-                            if transfer.from_account == account_id {
-                                block_balance - transfer.amount
-                            // Add amount if account is the receiver
-                            } else if transfer.to_account == account_id {
-                                block_balance + transfer.amount
-                            } else {
-                                block_balance
-                            }
-                        }
-                    },
-                )
-        })
-    }
-}
+    #[test]
+    fn test_new() {
+        let blockchain = Blockchain::default();
 
-#[cfg(test)]
-mod test {
-    use super::*;
+        assert_eq!(blockchain.height(), 0);
+        assert_eq!(blockchain.block_by_height(0).unwrap().header.id, 0);
+    }
 
     #[test]
-    fn test_new() {
-        let blockchain = Blockchain::new();
+    fn test_new_with_allocations() {
+        let genesis = GenesisConfig {
+            allocations: vec![GenesisAllocation {
+                account_id: "account_id".to_string(),
+                balance: Amount::new(100),
+                public_key: keypair("account_id").public_key(),
+            }],
+            ..GenesisConfig::default()
+        };
+        let blockchain = Blockchain::new(&genesis);
 
-        assert_eq!(blockchain.blocks.len(), 1);
-        assert_eq!(blockchain.blocks[0].id, 0);
+        assert!(blockchain.validate().is_ok());
+        assert_eq!(
+            blockchain.get_account_balance("account_id"),
+            Amount::new(100)
+        );
+        assert_eq!(
+            blockchain.network_id(),
+            Blockchain::new(&genesis).network_id()
+        );
+        assert_ne!(blockchain.network_id(), Blockchain::default().network_id());
     }
 
     #[test]
     fn test_add_block() {
-        let mut blockchain = Blockchain::new();
-        blockchain.add_block(vec![]);
+        let mut blockchain = Blockchain::default();
+        blockchain.add_block(vec![]).unwrap();
 
-        assert_eq!(blockchain.blocks.len(), 2);
-        assert_eq!(blockchain.blocks[1].id, 1);
+        assert_eq!(blockchain.height(), 1);
+        assert_eq!(blockchain.block_by_height(1).unwrap().header.id, 1);
         assert_eq!(
-            blockchain.blocks[1].previous_hash,
-            blockchain.blocks[0].hash
+            blockchain.block_by_height(1).unwrap().header.previous_hash,
+            blockchain.block_by_height(0).unwrap().hash
         );
     }
 
     #[test]
-    fn test_is_valid() {
+    fn test_block_template() {
+        let genesis = GenesisConfig {
+            proof_of_work: Some(ProofOfWork {
+                initial_difficulty: 8,
+                target_block_time: 60_000,
+                retarget_interval: 2,
+            }),
+            ..GenesisConfig::default()
+        };
+        let mut blockchain = Blockchain::new(&genesis);
+        let transaction = Transaction::account_creation(
+            "account_id".to_string(),
+            Amount::new(100),
+            keypair("account_id").public_key(),
+        );
+
+        // Sealed apart from the blockchain
+        let mut block = blockchain
+            .block_template(Some("account_id".to_string()), vec![transaction.clone()])
+            .unwrap();
+        assert_eq!(block.header.difficulty, 8);
+        assert!(block.seal(&AtomicBool::new(false)));
+        assert_eq!(blockchain.append_block(block), Ok(ChainUpdate::Extended));
+        assert_eq!(
+            blockchain.tip().transactions,
+            std::slice::from_ref(&transaction)
+        );
+
+        // The transactions and the producer are checked before sealing
+        assert!(matches!(
+            blockchain.block_template(None, vec![transaction]),
+            Err(BlockError::InvalidTransaction { block_id: 2, .. })
+        ));
+        assert_eq!(
+            blockchain.block_template(Some("unknown".to_string()), vec![]),
+            Err(BlockError::UnknownProducer {
+                block_id: 2,
+                producer: "unknown".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_inclusion_proof() {
+        let mut blockchain = Blockchain::default();
+        let transaction = Transaction::account_creation(
+            "account_id".to_string(),
+            Amount::new(100),
+            keypair("account_id").public_key(),
+        );
+        blockchain.add_block(vec![transaction.clone()]).unwrap();
+        blockchain.add_block(vec![]).unwrap();
+
+        let (header, proof) = blockchain.inclusion_proof(transaction.id()).unwrap();
+        assert_eq!(header, blockchain.block_by_height(1).unwrap().header);
+        assert!(proof.verify(&transaction.hash(), &header.merkle_root));
+        assert_eq!(blockchain.inclusion_proof("unknown"), None);
+    }
+
+    #[test]
+    fn test_validate() {
         // Valid
-        let mut blockchain = Blockchain::new();
-        blockchain.add_block(vec![]);
-        assert!(blockchain.is_valid());
+        let mut blockchain = Blockchain::default();
+        blockchain.add_block(vec![]).unwrap();
+        assert_eq!(blockchain.validate(), Ok(()));
+        assert!(open_tampered(|_| {}).is_ok());
 
-        // Invalid block id
-        blockchain.blocks[1].id = 2;
-        assert_eq!(blockchain.is_valid(), false);
+        // Invalid block id, the block is not at the height it claims
+        let error = open_tampered(|block| {
+            block.header.id = 2;
+            block.hash = block.header.hash();
+        })
+        .unwrap_err();
+        assert_eq!(
+            chain_error(&error),
+            &ChainError::InvalidBlock(BlockError::InvalidBlock {
+                block_id: 1,
+                rule: BlockRuleError::IdMismatch { id: 2, expected: 1 }
+            })
+        );
+        assert_eq!(
+            error.to_string(),
+            "stored chain is not valid: block #1 does not extend the blockchain: id is 2, expected 1"
+        );
 
         // Invalid block hash
-        blockchain = Blockchain::new();
-        blockchain.add_block(vec![]);
-        blockchain.blocks[1].hash = "invalid".to_string();
-        assert_eq!(blockchain.is_valid(), false);
+        let error = open_tampered(|block| block.hash = "invalid".to_string()).unwrap_err();
+        let StoreError::InvalidChain(ChainError::InvalidBlock(BlockError::InvalidBlock {
+            block_id: 1,
+            rule: BlockRuleError::HashMismatch { expected, .. },
+        })) = &error
+        else {
+            panic!("unexpected error {error:?}");
+        };
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "stored chain is not valid: block #1 does not extend the blockchain: hash is invalid, expected {expected}"
+            )
+        );
 
         // Invalid previous hash
-        blockchain = Blockchain::new();
-        blockchain.add_block(vec![]);
-        blockchain.blocks[1].previous_hash = "invalid".to_string();
-        assert_eq!(blockchain.is_valid(), false);
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        assert_eq!(
+            chain_error(
+                &open_tampered(|block| block.header.previous_hash = "invalid".to_string())
+                    .unwrap_err()
+            ),
+            &ChainError::InvalidBlock(BlockError::InvalidBlock {
+                block_id: 1,
+                rule: BlockRuleError::PreviousHashMismatch {
+                    previous_hash: "invalid".to_string(),
+                    expected: genesis.hash.clone()
+                }
+            })
+        );
+
+        // Transfer overdrawing its sender, in blocks with valid hashes
+        let block_1 = Block::new(
+            &genesis,
+            ["account_1", "account_2"]
+                .map(|account_id| {
+                    Transaction::account_creation(
+                        account_id.to_string(),
+                        Amount::new(100),
+                        keypair(account_id).public_key(),
+                    )
+                })
+                .to_vec(),
+            0,
+        );
+        let block_2 = Block::new(
+            &block_1,
+            vec![Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(1_000),
+                Amount::ZERO,
+                0,
+                &keypair("account_1"),
+            )],
+            0,
+        );
+        let blocks = vec![genesis, block_1.clone(), block_2];
+        let error = open_stored(blocks.clone(), AccountChanges::new()).unwrap_err();
+        assert!(
+            matches!(
+                &error,
+                StoreError::InvalidChain(ChainError::InvalidBlock(
+                    BlockError::InvalidTransaction {
+                        block_id: 2,
+                        error: TransactionError::InsufficientFunds { .. }
+                    }
+                ))
+            ),
+            "unexpected error {error:?}"
+        );
+
+        // Stored balance not matching the blocks
+        let blocks = &blocks[..2];
+        let accounts: AccountChanges = AccountState::from_blocks(blocks)
+            .accounts()
+            .map(|(account_id, account)| (account_id, Some(account)))
+            .collect();
+        assert!(open_stored(blocks.to_vec(), accounts.clone()).is_ok());
+        let mut tampered = accounts;
+        tampered
+            .get_mut("account_2")
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .balance = Amount::new(1_000);
+        let error = open_stored(blocks.to_vec(), tampered).unwrap_err();
+        assert_eq!(
+            chain_error(&error),
+            &ChainError::AccountMismatch {
+                account_id: "account_2".to_string()
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "stored chain is not valid: stored account 'account_2' does not match the blocks of the best chain"
+        );
+    }
+
+    #[test]
+    fn test_append_block() {
+        let mut blockchain = Blockchain::default();
+        let mut peer_blockchain = Blockchain::default();
+        peer_blockchain
+            .add_block(vec![Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            )])
+            .unwrap();
+        let block = peer_blockchain.block_by_height(1).unwrap();
+
+        // Invalid hash
+        let mut invalid_block = block.clone();
+        invalid_block.hash = "invalid".to_string();
+        assert_eq!(
+            blockchain.append_block(invalid_block),
+            Err(BlockError::InvalidBlock {
+                block_id: 1,
+                rule: BlockRuleError::HashMismatch {
+                    hash: "invalid".to_string(),
+                    expected: block.header.hash()
+                }
+            })
+        );
+
+        // Created too far in the future
+        let mut future_block = block.clone();
+        future_block.header.created_at += 60 * 60 * 1_000;
+        future_block.hash = future_block.header.hash();
+        assert!(matches!(
+            blockchain.append_block(future_block),
+            Err(BlockError::InvalidBlock {
+                block_id: 1,
+                rule: BlockRuleError::TimestampInFuture { .. }
+            })
+        ));
+
+        // Valid
+        assert_eq!(
+            blockchain.append_block(block.clone()),
+            Ok(ChainUpdate::Extended)
+        );
+        assert_eq!(blockchain.tip().hash, block.hash);
+        assert_eq!(
+            blockchain.get_account_balance("account_1"),
+            Amount::new(100)
+        );
+
+        // Already appended
+        assert_eq!(
+            blockchain.append_block(block),
+            Err(BlockError::AlreadyKnown { block_id: 1 })
+        );
+
+        // Invalid transaction, refused whether the block is added or appended
+        let transactions = vec![Transaction::account_creation(
+            "account_1".to_string(),
+            Amount::new(100),
+            keypair("account_1").public_key(),
+        )];
+        let error = BlockError::InvalidTransaction {
+            block_id: 2,
+            error: TransactionError::AccountAlreadyExists("account_1".to_string()),
+        };
+        assert_eq!(
+            peer_blockchain.add_block(transactions.clone()),
+            Err(error.clone())
+        );
+        assert_eq!(peer_blockchain.height(), 1);
+        assert_eq!(
+            blockchain.append_block(Block::new(blockchain.tip(), transactions, 0)),
+            Err(error)
+        );
+    }
+
+    #[test]
+    fn test_fees() {
+        let mut blockchain = Blockchain::default();
+        let mut peer_blockchain = Blockchain::default();
+        let accounts: Vec<Transaction> = ["account_1", "account_2", "producer"]
+            .into_iter()
+            .map(|account_id| {
+                Transaction::account_creation(
+                    account_id.to_string(),
+                    Amount::new(100),
+                    keypair(account_id).public_key(),
+                )
+            })
+            .collect();
+        // The producer can be created by the block crediting it
+        blockchain
+            .add_block_with_producer(Some("producer".to_string()), accounts.clone())
+            .unwrap();
+        peer_blockchain.add_block(accounts).unwrap();
+
+        // The fee is taken from the sender and credited to the producer
+        let block = blockchain
+            .add_block_with_producer(
+                Some("producer".to_string()),
+                vec![Transaction::transfer(
+                    &network_id(),
+                    "account_1".to_string(),
+                    "account_2".to_string(),
+                    Amount::new(30),
+                    Amount::new(5),
+                    0,
+                    &keypair("account_1"),
+                )],
+            )
+            .unwrap()
+            .clone();
+        assert_eq!(block.fees(), Ok(Amount::new(5)));
+        assert_eq!(blockchain.get_account_balance("account_1"), Amount::new(65));
+        assert_eq!(
+            blockchain.get_account_balance("account_2"),
+            Amount::new(130)
+        );
+        assert_eq!(blockchain.get_account_balance("producer"), Amount::new(105));
+
+        // Without a producer, the fee is burned
+        blockchain
+            .add_block(vec![Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(30),
+                Amount::new(5),
+                1,
+                &keypair("account_1"),
+            )])
+            .unwrap();
+        assert_eq!(blockchain.get_account_balance("account_1"), Amount::new(30));
+        assert_eq!(blockchain.get_account_balance("producer"), Amount::new(105));
+
+        // The producer must exist
+        let block = Block::with_producer(
+            peer_blockchain.tip(),
+            Some("unknown".to_string()),
+            vec![],
+            0,
+        );
+        assert_eq!(
+            peer_blockchain.append_block(block),
+            Err(BlockError::UnknownProducer {
+                block_id: 2,
+                producer: "unknown".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_balance_overflow() {
+        let mut blockchain = Blockchain::default();
+        blockchain
+            .add_block(
+                [("rich", Amount::MAX), ("account_1", Amount::new(100))]
+                    .into_iter()
+                    .map(|(account_id, balance)| {
+                        Transaction::account_creation(
+                            account_id.to_string(),
+                            balance,
+                            keypair(account_id).public_key(),
+                        )
+                    })
+                    .collect(),
+            )
+            .unwrap();
+
+        // The recipient can't receive more than the maximum amount
+        let transfer = |to_account: &str, fee| {
+            Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                to_account.to_string(),
+                Amount::new(10),
+                Amount::new(fee),
+                0,
+                &keypair("account_1"),
+            )
+        };
+        let error = BlockError::InvalidTransaction {
+            block_id: 2,
+            error: TransactionError::BalanceOverflow("rich".to_string()),
+        };
+        let block = Block::new(blockchain.tip(), vec![transfer("rich", 0)], 0);
+        assert_eq!(blockchain.append_block(block), Err(error.clone()));
+        assert_eq!(blockchain.add_block(vec![transfer("rich", 0)]), Err(error));
+
+        // Nor can the producer be credited with more
+        let transactions = vec![
+            Transaction::account_creation(
+                "account_2".to_string(),
+                Amount::ZERO,
+                keypair("account_2").public_key(),
+            ),
+            transfer("account_2", 1),
+        ];
+        let error = BlockError::ProducerBalanceOverflow {
+            block_id: 2,
+            producer: "rich".to_string(),
+        };
+        let block = Block::with_producer(
+            blockchain.tip(),
+            Some("rich".to_string()),
+            transactions.clone(),
+            0,
+        );
+        assert_eq!(blockchain.append_block(block), Err(error.clone()));
+        assert_eq!(
+            blockchain.add_block_with_producer(Some("rich".to_string()), transactions),
+            Err(error)
+        );
+        assert_eq!(blockchain.height(), 1);
+        assert_eq!(blockchain.get_account_balance("rich"), Amount::MAX);
+    }
+
+    #[test]
+    fn test_block_reward() {
+        let genesis = GenesisConfig {
+            block_reward: Some(BlockReward {
+                initial_reward: Amount::new(50),
+                halving_interval: 2,
+            }),
+            ..GenesisConfig::default()
+        };
+        let mut blockchain = Blockchain::new(&genesis);
+        let producer_creation = Transaction::account_creation(
+            "producer".to_string(),
+            Amount::ZERO,
+            keypair("producer").public_key(),
+        );
+
+        // The coinbase comes first, and its reward is credited with the fees
+        let block = blockchain
+            .add_block_with_producer(Some("producer".to_string()), vec![producer_creation])
+            .unwrap()
+            .clone();
+        assert!(matches!(
+            &block.transactions[0],
+            Transaction::Coinbase(coinbase) if coinbase.amount == Amount::new(50)
+        ));
+        assert_eq!(block.reward(), Amount::new(50));
+        assert_eq!(blockchain.get_account_balance("producer"), Amount::new(50));
+
+        // Without a producer, the reward is burned
+        blockchain.add_block(vec![]).unwrap();
+        assert_eq!(blockchain.get_account_balance("producer"), Amount::new(50));
+
+        let block = blockchain
+            .add_block_with_producer(Some("producer".to_string()), vec![])
+            .unwrap()
+            .clone();
+        assert_eq!(block.reward(), Amount::new(25));
+        assert_eq!(blockchain.get_account_balance("producer"), Amount::new(75));
+        assert_eq!(
+            blockchain.get_account_balance("producer"),
+            fold_account_balance(&blockchain.get_blocks(0, 4), "producer")
+        );
+
+        // Blocks must start with a coinbase minting the reward of their height
+        let producer = Some("producer".to_string());
+        let block = Block::with_producer(blockchain.tip(), producer.clone(), vec![], 0);
+        assert_eq!(
+            blockchain.append_block(block),
+            Err(BlockError::MissingCoinbase { block_id: 4 })
+        );
+        let block = Block::with_producer(
+            blockchain.tip(),
+            producer.clone(),
+            vec![Transaction::coinbase(Amount::new(50))],
+            0,
+        );
+        assert_eq!(
+            blockchain.append_block(block),
+            Err(BlockError::InvalidReward {
+                block_id: 4,
+                reward: Amount::new(50),
+                expected: Amount::new(25)
+            })
+        );
+        let second_coinbase = Transaction::coinbase(Amount::new(25));
+        let block = Block::with_producer(
+            blockchain.tip(),
+            producer,
+            vec![
+                Transaction::coinbase(Amount::new(25)),
+                second_coinbase.clone(),
+            ],
+            0,
+        );
+        assert_eq!(
+            blockchain.append_block(block),
+            Err(BlockError::InvalidTransaction {
+                block_id: 4,
+                error: TransactionError::UnexpectedCoinbase(second_coinbase.id().to_string())
+            })
+        );
+
+        // Chains without a block reward have no coinbase
+        let mut blockchain = Blockchain::default();
+        let coinbase = Transaction::coinbase(Amount::new(50));
+        let block = Block::new(blockchain.tip(), vec![coinbase.clone()], 0);
+        assert_eq!(
+            blockchain.append_block(block),
+            Err(BlockError::InvalidTransaction {
+                block_id: 1,
+                error: TransactionError::UnexpectedCoinbase(coinbase.id().to_string())
+            })
+        );
+        assert!(blockchain
+            .add_block(vec![])
+            .unwrap()
+            .transactions
+            .is_empty());
+    }
+
+    #[test]
+    fn test_supply_policy() {
+        let genesis = GenesisConfig {
+            allocations: vec![GenesisAllocation {
+                account_id: "faucet".to_string(),
+                balance: Amount::new(100),
+                public_key: keypair("faucet").public_key(),
+            }],
+            supply_policy: SupplyPolicy::Restricted {
+                faucets: vec!["faucet".to_string()],
+            },
+            ..GenesisConfig::default()
+        };
+        let mut blockchain = Blockchain::new(&genesis);
+
+        // New accounts start at zero, or are funded by a faucet
+        blockchain
+            .add_block(vec![
+                Transaction::account_creation(
+                    "account_1".to_string(),
+                    Amount::ZERO,
+                    keypair("account_1").public_key(),
+                ),
+                Transaction::funded_account_creation(
+                    &genesis.network_id(),
+                    "account_2".to_string(),
+                    Amount::new(40),
+                    keypair("account_2").public_key(),
+                    "faucet".to_string(),
+                    &keypair("faucet"),
+                ),
+            ])
+            .unwrap();
+        assert_eq!(blockchain.height(), 1);
+        assert_eq!(blockchain.get_account_balance("faucet"), Amount::new(60));
+        assert_eq!(blockchain.get_account_balance("account_2"), Amount::new(40));
+        let blocks = blockchain.get_blocks(0, usize::MAX);
+        for account_id in ["faucet", "account_1", "account_2"] {
+            assert_eq!(
+                blockchain.get_account_balance(account_id),
+                fold_account_balance(&blocks, account_id)
+            );
+        }
+
+        // Blocks minting an initial balance are refused
+        let block = Block::new(
+            blockchain.tip(),
+            vec![Transaction::account_creation(
+                "account_3".to_string(),
+                Amount::new(10),
+                keypair("account_3").public_key(),
+            )],
+            0,
+        );
+        assert_eq!(
+            blockchain.append_block(block),
+            Err(BlockError::InvalidTransaction {
+                block_id: 2,
+                error: TransactionError::UnfundedInitialBalance {
+                    account_id: "account_3".to_string(),
+                    initial_balance: Amount::new(10)
+                }
+            })
+        );
+
+        // Only the faucets can fund account creations
+        assert_eq!(
+            blockchain.validate_transactions(&[Transaction::funded_account_creation(
+                &genesis.network_id(),
+                "account_3".to_string(),
+                Amount::new(10),
+                keypair("account_3").public_key(),
+                "account_2".to_string(),
+                &keypair("account_2"),
+            )]),
+            Err(TransactionError::UnauthorizedFaucet(
+                "account_2".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_proof_of_work() {
+        let genesis = GenesisConfig {
+            proof_of_work: Some(ProofOfWork {
+                initial_difficulty: 8,
+                target_block_time: 60_000,
+                retarget_interval: 2,
+            }),
+            ..GenesisConfig::default()
+        };
+        let mut blockchain = Blockchain::new(&genesis);
+        let mut peer_blockchain = Blockchain::new(&genesis);
+        for _ in 0..3 {
+            peer_blockchain.add_block(vec![]).unwrap();
+        }
+        assert!(peer_blockchain.validate().is_ok());
+        assert!(peer_blockchain
+            .block_by_height(1)
+            .unwrap()
+            .hash
+            .starts_with("00"));
+
+        // Blocks mined much faster than the target block time
+        assert_eq!(peer_blockchain.next_difficulty(), 9);
+        peer_blockchain.add_block(vec![]).unwrap();
+        assert_eq!(peer_blockchain.tip().header.difficulty, 9);
+        assert!(peer_blockchain.validate().is_ok());
+
+        // Block mined with the wrong difficulty
+        let block = Block::new(blockchain.tip(), vec![], 10);
+        assert_eq!(
+            blockchain.append_block(block),
+            Err(BlockError::InvalidDifficulty {
+                block_id: 1,
+                difficulty: 10,
+                expected: 8
+            })
+        );
+
+        for block in peer_blockchain.get_blocks(1, 4) {
+            assert_eq!(blockchain.append_block(block), Ok(ChainUpdate::Extended));
+        }
+
+        // A block forged with an easier difficulty is not valid
+        let block = Block::new(&peer_blockchain.block_by_height(3).unwrap(), vec![], 8);
+        assert_eq!(
+            blockchain.append_block(block),
+            Err(BlockError::InvalidDifficulty {
+                block_id: 4,
+                difficulty: 8,
+                expected: 9
+            })
+        );
+    }
+
+    #[test]
+    fn test_fork_choice() {
+        let mut blockchain = Blockchain::default();
+        let mut peer_blockchain = Blockchain::default();
+        let block_a1 = blockchain
+            .add_block(vec![Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            )])
+            .unwrap()
+            .clone();
+        let block_b1 = peer_blockchain
+            .add_block(vec![Transaction::account_creation(
+                "account_2".to_string(),
+                Amount::new(100),
+                keypair("account_2").public_key(),
+            )])
+            .unwrap()
+            .clone();
+        let block_b2 = peer_blockchain.add_block(vec![]).unwrap().clone();
+
+        // Same work as the best chain: the first block seen is kept
+        assert_eq!(
+            blockchain.append_block(block_b1.clone()),
+            Ok(ChainUpdate::SideBranch)
+        );
+        assert_eq!(blockchain.tip().hash, block_a1.hash);
+        assert!(blockchain.contains_block(&block_b1.hash));
+
+        // More work than the best chain
+        assert_eq!(
+            blockchain.append_block(block_b2.clone()),
+            Ok(ChainUpdate::Reorganized {
+                orphaned: vec![block_a1.clone()],
+                connected: vec![block_b1, block_b2.clone()],
+            })
+        );
+        assert_eq!(blockchain.tip().hash, block_b2.hash);
+        assert_eq!(blockchain.total_work(), 3);
+        assert!(blockchain.validate().is_ok());
+        assert!(!blockchain.account_exists("account_1"));
+        assert!(blockchain.account_exists("account_2"));
+
+        // Blocks of the orphaned branch are validated against that branch
+        assert_eq!(
+            blockchain.append_block(Block::new(
+                &block_a1,
+                vec![Transaction::account_creation(
+                    "account_1".to_string(),
+                    Amount::new(100),
+                    keypair("account_1").public_key()
+                )],
+                0
+            )),
+            Err(BlockError::InvalidTransaction {
+                block_id: 2,
+                error: TransactionError::AccountAlreadyExists("account_1".to_string())
+            })
+        );
+        let block_a2 = Block::new(&block_a1, vec![], 0);
+        let block_a3 = Block::new(&block_a2, vec![], 0);
+        assert_eq!(
+            blockchain.append_block(block_a2),
+            Ok(ChainUpdate::SideBranch)
+        );
+        assert!(matches!(
+            blockchain.append_block(block_a3.clone()),
+            Ok(ChainUpdate::Reorganized { .. })
+        ));
+        assert_eq!(blockchain.tip().hash, block_a3.hash);
+        assert!(blockchain.account_exists("account_1"));
+        assert!(!blockchain.account_exists("account_2"));
+
+        // Unknown previous block
+        assert_eq!(
+            Blockchain::default().append_block(block_b2),
+            Err(BlockError::UnknownParent { block_id: 2 })
+        );
+    }
+
+    #[test]
+    fn test_open() {
+        let path = std::env::temp_dir().join(format!("b-blockchain-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let open = || {
+            Blockchain::open(
+                &GenesisConfig::default(),
+                Box::new(RedbStore::open(&path).unwrap()),
+            )
+        };
+
+        let mut blockchain = open().unwrap();
+        let mut peer_blockchain = Blockchain::default();
+        blockchain
+            .add_block(vec![Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            )])
+            .unwrap();
+        let block_b1 = peer_blockchain
+            .add_block(vec![Transaction::account_creation(
+                "account_2".to_string(),
+                Amount::new(100),
+                keypair("account_2").public_key(),
+            )])
+            .unwrap()
+            .clone();
+        let block_b2 = peer_blockchain.add_block(vec![]).unwrap().clone();
+        blockchain.append_block(block_b1).unwrap();
+        blockchain.append_block(block_b2.clone()).unwrap();
+        drop(blockchain);
+
+        // The competing branches are reloaded, and the best chain is the same
+        let mut blockchain = open().unwrap();
+        assert_eq!(
+            blockchain.get_blocks(0, 10),
+            peer_blockchain.get_blocks(0, 10)
+        );
+        assert_eq!(blockchain.tip().hash, block_b2.hash);
+        assert_eq!(blockchain.total_work(), 3);
+        assert!(blockchain.account_exists("account_2"));
+        assert!(!blockchain.account_exists("account_1"));
+
+        blockchain.add_block(vec![]).unwrap();
+        drop(blockchain);
+        assert_eq!(open().unwrap().height(), 3);
+
+        // Store of another chain
+        let genesis = GenesisConfig {
+            chain_id: "other".to_string(),
+            ..GenesisConfig::default()
+        };
+        assert!(matches!(
+            Blockchain::open(&genesis, Box::new(RedbStore::open(&path).unwrap())),
+            Err(StoreError::GenesisMismatch)
+        ));
+        std::fs::remove_file(path).unwrap();
+
+        // Rules that can't be applied, the store is left empty
+        let genesis = GenesisConfig {
+            proof_of_work: Some(ProofOfWork {
+                initial_difficulty: 1,
+                target_block_time: 1_000,
+                retarget_interval: 0,
+            }),
+            ..GenesisConfig::default()
+        };
+        let error = Blockchain::open(&genesis, Box::new(MemoryStore::new())).unwrap_err();
+        assert!(matches!(
+            error,
+            StoreError::InvalidGenesis(GenesisError::InvalidProofOfWork)
+        ));
+        assert_eq!(
+            error.to_string(),
+            "invalid genesis configuration: proof-of-work target block time and retarget interval must be strictly positive"
+        );
+    }
+
+    #[test]
+    fn test_get_blocks() {
+        let mut blockchain = Blockchain::default();
+        blockchain.add_block(vec![]).unwrap();
+        blockchain.add_block(vec![]).unwrap();
+
+        assert_eq!(blockchain.get_blocks(1, 10).len(), 2);
+        assert_eq!(blockchain.get_blocks(1, 1)[0].header.id, 1);
+        assert_eq!(blockchain.get_blocks(0, 0).len(), 0);
+        assert_eq!(blockchain.get_blocks(3, 10).len(), 0);
+        assert_eq!(blockchain.get_blocks(-1, 1)[0].header.id, 0);
+    }
+
+    #[test]
+    fn test_validate_transactions() {
+        let blockchain = Blockchain::default();
+
+        assert_eq!(
+            blockchain.validate_transactions(&[
+                Transaction::account_creation(
+                    "account_1".to_string(),
+                    Amount::new(100),
+                    keypair("account_1").public_key()
+                ),
+                Transaction::account_creation(
+                    "account_2".to_string(),
+                    Amount::ZERO,
+                    keypair("account_2").public_key()
+                ),
+                Transaction::transfer(
+                    &network_id(),
+                    "account_1".to_string(),
+                    "account_2".to_string(),
+                    Amount::new(100),
+                    Amount::ZERO,
+                    0,
+                    &keypair("account_1")
+                ),
+            ]),
+            Ok(())
+        );
+        assert_eq!(
+            blockchain.validate_transactions(&[
+                Transaction::account_creation(
+                    "account_1".to_string(),
+                    Amount::new(100),
+                    keypair("account_1").public_key()
+                ),
+                Transaction::account_creation(
+                    "account_1".to_string(),
+                    Amount::new(100),
+                    keypair("account_1").public_key()
+                ),
+            ]),
+            Err(TransactionError::AccountAlreadyExists(
+                "account_1".to_string()
+            ))
+        );
     }
 
     #[test]
     fn test_account_exists() {
-        let mut blockchain = Blockchain::new();
-        blockchain.add_block(vec![Transaction::account_creation(
-            "account_id".to_string(),
-            100,
-        )]);
+        let mut blockchain = Blockchain::default();
+        blockchain
+            .add_block(vec![Transaction::account_creation(
+                "account_id".to_string(),
+                Amount::new(100),
+                keypair("account_id").public_key(),
+            )])
+            .unwrap();
 
-        assert_eq!(blockchain.account_exists("account_id"), true);
-        assert_eq!(blockchain.account_exists("Does not exist"), false);
+        assert!(blockchain.account_exists("account_id"));
+        assert!(!blockchain.account_exists("Does not exist"));
     }
 
     #[test]
     fn test_get_account_balance() {
-        let mut blockchain = Blockchain::new();
-        blockchain.add_block(vec![
-            Transaction::account_creation("account_1".to_string(), 100),
-            Transaction::account_creation("account_2".to_string(), 500),
-        ]);
-        blockchain.add_block(vec![Transaction::transfer(
+        let mut blockchain = Blockchain::default();
+        blockchain
+            .add_block(vec![
+                Transaction::account_creation(
+                    "account_1".to_string(),
+                    Amount::new(100),
+                    keypair("account_1").public_key(),
+                ),
+                Transaction::account_creation(
+                    "account_2".to_string(),
+                    Amount::new(500),
+                    keypair("account_2").public_key(),
+                ),
+            ])
+            .unwrap();
+        blockchain
+            .add_block(vec![Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(50),
+                Amount::ZERO,
+                0,
+                &keypair("account_1"),
+            )])
+            .unwrap();
+
+        assert_eq!(blockchain.get_account_balance("account_1"), Amount::new(50));
+        assert_eq!(
+            blockchain.get_account_balance("account_2"),
+            Amount::new(550)
+        );
+        assert_eq!(blockchain.account_state().next_nonce("account_1"), 1);
+        assert_eq!(blockchain.account_state().next_nonce("account_2"), 0);
+    }
+
+    #[test]
+    fn test_append_block_with_replayed_transfer() {
+        let mut blockchain = Blockchain::default();
+        let transfer = Transaction::transfer(
+            &network_id(),
             "account_1".to_string(),
             "account_2".to_string(),
-            50,
-        )]);
+            Amount::new(50),
+            Amount::ZERO,
+            0,
+            &keypair("account_1"),
+        );
+        blockchain
+            .add_block(vec![
+                Transaction::account_creation(
+                    "account_1".to_string(),
+                    Amount::new(100),
+                    keypair("account_1").public_key(),
+                ),
+                Transaction::account_creation(
+                    "account_2".to_string(),
+                    Amount::ZERO,
+                    keypair("account_2").public_key(),
+                ),
+                transfer.clone(),
+            ])
+            .unwrap();
+        assert_eq!(
+            blockchain.append_block(Block::new(blockchain.tip(), vec![transfer], 0)),
+            Err(BlockError::InvalidTransaction {
+                block_id: 2,
+                error: TransactionError::InvalidNonce {
+                    account_id: "account_1".to_string(),
+                    nonce: 0,
+                    expected: 1
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_account_state_consistency() {
+        let accounts: Vec<String> = (0..5).map(|i| format!("account_{i}")).collect();
+        let mut blockchain = Blockchain::default();
+        blockchain
+            .add_block(
+                accounts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, account)| {
+                        Transaction::account_creation(
+                            account.clone(),
+                            Amount::new(1_000 + 100 * i as u64),
+                            keypair(account).public_key(),
+                        )
+                    })
+                    .collect(),
+            )
+            .unwrap();
+        for round in 0..20 {
+            let transactions = (0..accounts.len())
+                .map(|i| {
+                    let from_account = &accounts[(i + round) % accounts.len()];
+                    Transaction::transfer(
+                        &network_id(),
+                        from_account.clone(),
+                        accounts[(i + round + 1) % accounts.len()].clone(),
+                        Amount::new((round * 7 + i) as u64 % 13 + 1),
+                        Amount::ZERO,
+                        round as u64,
+                        &keypair(from_account),
+                    )
+                })
+                .collect();
+            blockchain.add_block(transactions).unwrap();
+
+            let blocks = blockchain.get_blocks(0, usize::MAX);
+            for account in accounts.iter().chain([&"unknown".to_string()]) {
+                assert_eq!(
+                    blockchain.get_account_balance(account),
+                    fold_account_balance(&blocks, account)
+                );
+                assert_eq!(
+                    blockchain.account_exists(account),
+                    fold_account_exists(&blocks, account)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_reorganized_account_state() {
+        let mut blockchain = Blockchain::default();
+        let mut peer_blockchain = Blockchain::default();
+        let creations = vec![
+            Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            ),
+            Transaction::account_creation(
+                "account_2".to_string(),
+                Amount::new(100),
+                keypair("account_2").public_key(),
+            ),
+        ];
+        blockchain.add_block(creations.clone()).unwrap();
+        peer_blockchain
+            .append_block(blockchain.tip().clone())
+            .unwrap();
+        blockchain
+            .add_block(vec![Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(30),
+                Amount::ZERO,
+                0,
+                &keypair("account_1"),
+            )])
+            .unwrap();
+        for amount in [10, 20] {
+            let nonce = peer_blockchain.account_state().next_nonce("account_2");
+            peer_blockchain
+                .add_block(vec![Transaction::transfer(
+                    &network_id(),
+                    "account_2".to_string(),
+                    "account_1".to_string(),
+                    Amount::new(amount),
+                    Amount::ZERO,
+                    nonce,
+                    &keypair("account_2"),
+                )])
+                .unwrap();
+        }
+
+        for block in peer_blockchain.get_blocks(2, 2) {
+            blockchain.append_block(block).unwrap();
+        }
+        let blocks = blockchain.get_blocks(0, usize::MAX);
+        let account_state = AccountState::from_blocks(&blocks);
+        for account in ["account_1", "account_2"] {
+            assert_eq!(
+                blockchain.account_state().account(account),
+                account_state.account(account)
+            );
+        }
+        assert_eq!(
+            blockchain.get_account_balance("account_1"),
+            Amount::new(130)
+        );
+        assert_eq!(blockchain.account_state().next_nonce("account_1"), 0);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let genesis = GenesisConfig {
+            proof_of_work: Some(ProofOfWork {
+                initial_difficulty: 4,
+                target_block_time: 60_000,
+                retarget_interval: 2,
+            }),
+            ..GenesisConfig::default()
+        };
+        let mut blockchain = Blockchain::new(&genesis);
+        blockchain
+            .add_block(vec![
+                Transaction::account_creation(
+                    "account_1".to_string(),
+                    Amount::new(100),
+                    keypair("account_1").public_key(),
+                ),
+                Transaction::account_creation(
+                    "account_2".to_string(),
+                    Amount::new(100),
+                    keypair("account_2").public_key(),
+                ),
+            ])
+            .unwrap();
+        for nonce in 0..5 {
+            blockchain
+                .add_block(vec![Transaction::transfer(
+                    &genesis.network_id(),
+                    "account_1".to_string(),
+                    "account_2".to_string(),
+                    Amount::new(10),
+                    Amount::ZERO,
+                    nonce,
+                    &keypair("account_1"),
+                )])
+                .unwrap();
+        }
+
+        // The accounts are the ones after the block at the snapshot height
+        let snapshot = blockchain.snapshot(4).unwrap();
+        let blocks = blockchain.get_blocks(0, 5);
+        assert_eq!(snapshot.block(), blocks.last());
+        assert_eq!(snapshot.blocks, blocks[2..]);
+        assert_eq!(
+            snapshot.accounts,
+            AccountState::from_blocks(&blocks).accounts().collect()
+        );
+        assert_eq!(snapshot.accounts["account_1"].nonce, 3);
+        assert_eq!(
+            blockchain.snapshot(6).unwrap().accounts["account_2"].balance,
+            Amount::new(150)
+        );
+        assert_eq!(blockchain.snapshot(7), None);
+
+        // A new node bootstraps from it, then appends the following blocks
+        let path = std::env::temp_dir().join(format!("b-snapshot-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut bootstrapped = Blockchain::from_snapshot(
+            &genesis,
+            &snapshot,
+            &snapshot.hash(),
+            Box::new(RedbStore::open(&path).unwrap()),
+        )
+        .unwrap();
+        assert_eq!(bootstrapped.height(), 4);
+        assert_eq!(
+            bootstrapped.total_work(),
+            blockchain.stored_total_work(&snapshot.block().unwrap().hash)
+        );
+        assert_eq!(bootstrapped.block_by_height(1), None);
+        assert_eq!(
+            bootstrapped.get_account_balance("account_2"),
+            Amount::new(130)
+        );
+        for block in blockchain.get_blocks(5, 2) {
+            assert_eq!(bootstrapped.append_block(block), Ok(ChainUpdate::Extended));
+        }
+        assert_eq!(bootstrapped.tip(), blockchain.tip());
+        assert_eq!(bootstrapped.total_work(), blockchain.total_work());
+        assert_eq!(
+            bootstrapped.get_account_balance("account_2"),
+            Amount::new(150)
+        );
+        assert!(bootstrapped.validate().is_ok());
+
+        // It can take snapshots from its own, and is reopened without the missing blocks
+        assert_eq!(bootstrapped.snapshot(4), Some(snapshot.clone()));
+        assert_eq!(bootstrapped.snapshot(3), None);
+        drop(bootstrapped);
+        let reopened =
+            Blockchain::open(&genesis, Box::new(RedbStore::open(&path).unwrap())).unwrap();
+        assert_eq!(reopened.tip(), blockchain.tip());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_from_snapshot_errors() {
+        let mut blockchain = Blockchain::default();
+        for _ in 0..3 {
+            blockchain.add_block(vec![]).unwrap();
+        }
+        let snapshot = blockchain.snapshot(2).unwrap();
+        let from_snapshot = |genesis: &GenesisConfig, snapshot: &Snapshot, hash: &str| {
+            Blockchain::from_snapshot(genesis, snapshot, hash, Box::new(MemoryStore::new()))
+                .unwrap_err()
+        };
+
+        assert!(matches!(
+            from_snapshot(&GenesisConfig::default(), &snapshot, "other"),
+            SnapshotError::HashMismatch { expected, actual }
+                if expected == "other" && actual == snapshot.hash()
+        ));
+        let other_genesis = GenesisConfig {
+            chain_id: "other".to_string(),
+            ..GenesisConfig::default()
+        };
+        assert!(matches!(
+            from_snapshot(&other_genesis, &snapshot, &snapshot.hash()),
+            SnapshotError::NetworkMismatch
+        ));
+
+        // Blocks that don't lead to the committed block
+        let mut forged = snapshot.clone();
+        forged
+            .blocks
+            .insert(0, Block::new(&forged.blocks[0], vec![], 0));
+        assert!(matches!(
+            from_snapshot(&GenesisConfig::default(), &forged, &forged.hash()),
+            SnapshotError::InvalidBlock(BlockError::InvalidBlock {
+                block_id: 2,
+                rule: BlockRuleError::IdMismatch { id: 2, expected: 4 }
+            })
+        ));
+        let mut forged = snapshot.clone();
+        forged.blocks.clear();
+        assert!(matches!(
+            from_snapshot(&GenesisConfig::default(), &forged, &forged.hash()),
+            SnapshotError::MissingBlocks {
+                count: 0,
+                expected: 1
+            }
+        ));
+        let mut forged = snapshot.clone();
+        forged.total_work = 0;
+        assert!(matches!(
+            from_snapshot(&GenesisConfig::default(), &forged, &forged.hash()),
+            SnapshotError::InvalidTotalWork
+        ));
+
+        let mut store = MemoryStore::new();
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        store.insert_block(&genesis, 1);
+        store.set_best_chain(&[genesis], AccountChanges::new());
+        assert!(matches!(
+            Blockchain::from_snapshot(
+                &GenesisConfig::default(),
+                &snapshot,
+                &snapshot.hash(),
+                Box::new(store)
+            ),
+            Err(SnapshotError::StoreNotEmpty)
+        ));
 
-        assert_eq!(blockchain.get_account_balance("account_1"), 50);
-        assert_eq!(blockchain.get_account_balance("account_2"), 550);
+        // Rules that can't be applied
+        let genesis = GenesisConfig {
+            block_reward: Some(BlockReward {
+                initial_reward: Amount::new(50),
+                halving_interval: 0,
+            }),
+            ..GenesisConfig::default()
+        };
+        assert!(matches!(
+            from_snapshot(&genesis, &snapshot, &snapshot.hash()),
+            SnapshotError::InvalidGenesis(GenesisError::InvalidBlockReward)
+        ));
     }
 }
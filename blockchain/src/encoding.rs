@@ -0,0 +1,724 @@
+//! Canonical binary encoding of the blockchain data
+//!
+//! Hashes, signatures and network messages are computed over this encoding, so any
+//! implementation following these rules produces the same bytes for the same value:
+//!
+//! - `u8`, `u32`, `u64`, `i64` and `u128` are written big-endian on 1, 4, 8, 8 and 16 bytes,
+//!   `i64` in two's complement
+//! - an `Amount` is written as its `u64` value
+//! - a string is its byte length as a `u32`, followed by its UTF-8 bytes
+//! - a list is its number of items as a `u32`, followed by the items
+//! - an optional value is `0` when it is missing, or `1` followed by the value
+//! - a map is its number of entries as a `u32`, followed by each key and value, in strictly
+//!   increasing key order: maps with keys out of order or duplicated are refused when decoded
+//! - a struct is its fields, in declaration order, without names or separators
+//! - an enum is the index of its variant as a `u8`, followed by the variant fields
+//!
+//! The encoding of each type is:
+//!
+//! | Type              | Encoding                                                                   |
+//! |-------------------|----------------------------------------------------------------------------|
+//! | `AccountCreation` | id, created_at, account_id, initial_balance, public_key, faucet, signature |
+//! | `Transfer`        | id, created_at, from_account, to_account, amount, fee, nonce, signature    |
+//! | `Coinbase`        | id, created_at, amount                                                     |
+//! | `Transaction`     | `0` + `AccountCreation`, `1` + `Transfer`, or `2` + `Coinbase`             |
+//! | `BlockHeader`     | id, created_at, previous_hash, merkle_root, producer, difficulty, nonce    |
+//! | `Block`           | header, transactions, hash                                                 |
+//! | `Account`         | public_key, balance, nonce                                                 |
+//! | `Snapshot`        | network_id, blocks, total_work (`u128`), accounts                          |
+//!
+//! Hashes and keys are kept as the hex strings they are everywhere else.
+
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+use crate::{
+    account_state::Account,
+    amount::Amount,
+    block::{Block, BlockHeader},
+    snapshot::Snapshot,
+    transaction::{AccountCreation, Coinbase, Transaction, Transfer},
+};
+
+/// Represents a value with a canonical binary encoding
+pub trait Encode {
+    /// Append the encoding of the value to the buffer
+    fn encode(&self, buffer: &mut Vec<u8>);
+
+    /// Get the encoding of the value
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.encode(&mut buffer);
+        buffer
+    }
+}
+
+/// Represents a value that can be read back from its canonical binary encoding
+pub trait Decode: Sized {
+    /// Read the value at the start of the decoder input
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError>;
+
+    /// Read a value from its encoding, which must span all the bytes
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut decoder = Decoder::new(bytes);
+        let value = Self::decode(&mut decoder)?;
+        if !decoder.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+        Ok(value)
+    }
+}
+
+/// Represents the reason bytes can't be decoded
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DecodeError {
+    /// The input ends in the middle of a value
+    #[error("unexpected end of input")]
+    UnexpectedEnd,
+    /// The input continues after the value
+    #[error("unexpected bytes after the value")]
+    TrailingBytes,
+    /// A string is not valid UTF-8
+    #[error("string is not valid UTF-8")]
+    InvalidUtf8,
+    /// An enum has no variant with this index
+    #[error("unknown variant {0}")]
+    InvalidVariant(u8),
+    /// A map key is not greater than the previous one, out of order or duplicated
+    #[error("map keys are not in strictly increasing order")]
+    UnorderedKeys,
+}
+
+/// Represents the remaining input of a decoding
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Decoder { bytes }
+    }
+
+    /// Check if the whole input has been read
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Read the next `N` bytes
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let bytes = self.take_slice(N)?;
+        Ok(bytes.try_into().expect("Slice should have N bytes"))
+    }
+
+    fn take_slice(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+}
+
+impl Encode for u8 {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.push(*self);
+    }
+}
+
+impl Decode for u8 {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(u8::from_be_bytes(decoder.take()?))
+    }
+}
+
+impl Encode for u32 {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Decode for u32 {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(u32::from_be_bytes(decoder.take()?))
+    }
+}
+
+impl Encode for u64 {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Decode for u64 {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(u64::from_be_bytes(decoder.take()?))
+    }
+}
+
+impl Encode for i64 {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Decode for i64 {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(i64::from_be_bytes(decoder.take()?))
+    }
+}
+
+impl Encode for Amount {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.value().encode(buffer);
+    }
+}
+
+impl Decode for Amount {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        u64::decode(decoder).map(Amount::new)
+    }
+}
+
+impl Encode for u128 {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Decode for u128 {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(u128::from_be_bytes(decoder.take()?))
+    }
+}
+
+/// Encode the length of a string, list or map
+fn encode_len(len: usize, buffer: &mut Vec<u8>) {
+    u32::try_from(len)
+        .expect("Length should fit in a u32")
+        .encode(buffer);
+}
+
+impl Encode for str {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        encode_len(self.len(), buffer);
+        buffer.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.as_str().encode(buffer);
+    }
+}
+
+impl Decode for String {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        let len = u32::decode(decoder)? as usize;
+        let bytes = decoder.take_slice(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        encode_len(self.len(), buffer);
+        for item in self {
+            item.encode(buffer);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.as_slice().encode(buffer);
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        let len = u32::decode(decoder)?;
+        // The length comes from the input, items are pushed as they are read
+        // rather than allocated up front
+        let mut items = Vec::new();
+        for _ in 0..len {
+            items.push(T::decode(decoder)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        match self {
+            None => 0u8.encode(buffer),
+            Some(value) => {
+                1u8.encode(buffer);
+                value.encode(buffer);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        match u8::decode(decoder)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(decoder)?)),
+            variant => Err(DecodeError::InvalidVariant(variant)),
+        }
+    }
+}
+
+impl<K: Encode, V: Encode> Encode for BTreeMap<K, V> {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        encode_len(self.len(), buffer);
+        for (key, value) in self {
+            key.encode(buffer);
+            value.encode(buffer);
+        }
+    }
+}
+
+impl<K: Decode + Ord, V: Decode> Decode for BTreeMap<K, V> {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        let len = u32::decode(decoder)?;
+        let mut entries = BTreeMap::new();
+        for _ in 0..len {
+            let key = K::decode(decoder)?;
+            // Each map has a single encoding, with its keys in strictly increasing order
+            if entries
+                .last_key_value()
+                .is_some_and(|(previous, _)| *previous >= key)
+            {
+                return Err(DecodeError::UnorderedKeys);
+            }
+            entries.insert(key, V::decode(decoder)?);
+        }
+        Ok(entries)
+    }
+}
+
+impl Encode for AccountCreation {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.id.encode(buffer);
+        self.created_at.encode(buffer);
+        self.account_id.encode(buffer);
+        self.initial_balance.encode(buffer);
+        self.public_key.encode(buffer);
+        self.faucet.encode(buffer);
+        self.signature.encode(buffer);
+    }
+}
+
+impl Decode for AccountCreation {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(AccountCreation {
+            id: String::decode(decoder)?,
+            created_at: i64::decode(decoder)?,
+            account_id: String::decode(decoder)?,
+            initial_balance: Amount::decode(decoder)?,
+            public_key: String::decode(decoder)?,
+            faucet: Option::decode(decoder)?,
+            signature: String::decode(decoder)?,
+        })
+    }
+}
+
+impl Encode for Transfer {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.id.encode(buffer);
+        self.created_at.encode(buffer);
+        self.from_account.encode(buffer);
+        self.to_account.encode(buffer);
+        self.amount.encode(buffer);
+        self.fee.encode(buffer);
+        self.nonce.encode(buffer);
+        self.signature.encode(buffer);
+    }
+}
+
+impl Decode for Transfer {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(Transfer {
+            id: String::decode(decoder)?,
+            created_at: i64::decode(decoder)?,
+            from_account: String::decode(decoder)?,
+            to_account: String::decode(decoder)?,
+            amount: Amount::decode(decoder)?,
+            fee: Amount::decode(decoder)?,
+            nonce: u64::decode(decoder)?,
+            signature: String::decode(decoder)?,
+        })
+    }
+}
+
+impl Encode for Coinbase {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.id.encode(buffer);
+        self.created_at.encode(buffer);
+        self.amount.encode(buffer);
+    }
+}
+
+impl Decode for Coinbase {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(Coinbase {
+            id: String::decode(decoder)?,
+            created_at: i64::decode(decoder)?,
+            amount: Amount::decode(decoder)?,
+        })
+    }
+}
+
+impl Encode for Transaction {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        match self {
+            Transaction::AccountCreation(account_creation) => {
+                0u8.encode(buffer);
+                account_creation.encode(buffer);
+            }
+            Transaction::Transfer(transfer) => {
+                1u8.encode(buffer);
+                transfer.encode(buffer);
+            }
+            Transaction::Coinbase(coinbase) => {
+                2u8.encode(buffer);
+                coinbase.encode(buffer);
+            }
+        }
+    }
+}
+
+impl Decode for Transaction {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        match u8::decode(decoder)? {
+            0 => Ok(Transaction::AccountCreation(AccountCreation::decode(
+                decoder,
+            )?)),
+            1 => Ok(Transaction::Transfer(Transfer::decode(decoder)?)),
+            2 => Ok(Transaction::Coinbase(Coinbase::decode(decoder)?)),
+            variant => Err(DecodeError::InvalidVariant(variant)),
+        }
+    }
+}
+
+impl Encode for BlockHeader {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.id.encode(buffer);
+        self.created_at.encode(buffer);
+        self.previous_hash.encode(buffer);
+        self.merkle_root.encode(buffer);
+        self.producer.encode(buffer);
+        self.difficulty.encode(buffer);
+        self.nonce.encode(buffer);
+    }
+}
+
+impl Decode for BlockHeader {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(BlockHeader {
+            id: i64::decode(decoder)?,
+            created_at: i64::decode(decoder)?,
+            previous_hash: String::decode(decoder)?,
+            merkle_root: String::decode(decoder)?,
+            producer: Option::decode(decoder)?,
+            difficulty: u32::decode(decoder)?,
+            nonce: u64::decode(decoder)?,
+        })
+    }
+}
+
+impl Encode for Block {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.header.encode(buffer);
+        self.transactions.encode(buffer);
+        self.hash.encode(buffer);
+    }
+}
+
+impl Decode for Block {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(Block {
+            header: BlockHeader::decode(decoder)?,
+            transactions: Vec::decode(decoder)?,
+            hash: String::decode(decoder)?,
+        })
+    }
+}
+
+impl Encode for Account {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.public_key.encode(buffer);
+        self.balance.encode(buffer);
+        self.nonce.encode(buffer);
+    }
+}
+
+impl Decode for Account {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(Account {
+            public_key: String::decode(decoder)?,
+            balance: Amount::decode(decoder)?,
+            nonce: u64::decode(decoder)?,
+        })
+    }
+}
+
+impl Encode for Snapshot {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.network_id.encode(buffer);
+        self.blocks.encode(buffer);
+        self.total_work.encode(buffer);
+        self.accounts.encode(buffer);
+    }
+}
+
+impl Decode for Snapshot {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(Snapshot {
+            network_id: String::decode(decoder)?,
+            blocks: Vec::decode(decoder)?,
+            total_work: u128::decode(decoder)?,
+            accounts: BTreeMap::decode(decoder)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::keys::test_utils::{keypair, network_id};
+    use crate::merkle::EMPTY_ROOT;
+
+    fn account_creation() -> Transaction {
+        Transaction::AccountCreation(AccountCreation {
+            id: "tx-1".to_string(),
+            created_at: 1,
+            account_id: "alice".to_string(),
+            initial_balance: Amount::new(100),
+            public_key: "ab".to_string(),
+            faucet: None,
+            signature: String::new(),
+        })
+    }
+
+    fn transfer() -> Transaction {
+        Transaction::Transfer(Transfer {
+            id: "tx-2".to_string(),
+            created_at: 2,
+            from_account: "alice".to_string(),
+            to_account: "bob".to_string(),
+            amount: Amount::MAX,
+            fee: Amount::new(2),
+            nonce: 3,
+            signature: "cd".to_string(),
+        })
+    }
+
+    fn coinbase() -> Transaction {
+        Transaction::Coinbase(Coinbase {
+            id: "tx-3".to_string(),
+            created_at: 3,
+            amount: Amount::new(50),
+        })
+    }
+
+    fn header() -> BlockHeader {
+        BlockHeader {
+            id: 1,
+            created_at: 2,
+            previous_hash: "000".to_string(),
+            merkle_root: EMPTY_ROOT.to_string(),
+            producer: Some("carol".to_string()),
+            difficulty: 0,
+            nonce: 0,
+        }
+    }
+
+    #[test]
+    fn test_golden_vectors() {
+        assert_eq!(
+            hex::encode(account_creation().to_bytes()),
+            concat!(
+                "00",
+                "00000004",
+                "74782d31",
+                "0000000000000001",
+                "00000005",
+                "616c696365",
+                "0000000000000064",
+                "00000002",
+                "6162",
+                "00",
+                "00000000",
+            )
+        );
+        assert_eq!(
+            hex::encode(transfer().to_bytes()),
+            concat!(
+                "01",
+                "00000004",
+                "74782d32",
+                "0000000000000002",
+                "00000005",
+                "616c696365",
+                "00000003",
+                "626f62",
+                "ffffffffffffffff",
+                "0000000000000002",
+                "0000000000000003",
+                "00000002",
+                "6364",
+            )
+        );
+        assert_eq!(
+            hex::encode(coinbase().to_bytes()),
+            concat!(
+                "02",
+                "00000004",
+                "74782d33",
+                "0000000000000003",
+                "0000000000000032",
+            )
+        );
+        let Transaction::Transfer(transfer_fields) = transfer() else {
+            unreachable!()
+        };
+        assert_eq!(
+            hex::encode(transfer_fields.signed_data("net")),
+            concat!(
+                "00000003",
+                "6e6574",
+                "00000004",
+                "74782d32",
+                "0000000000000002",
+                "00000005",
+                "616c696365",
+                "00000003",
+                "626f62",
+                "ffffffffffffffff",
+                "0000000000000002",
+                "0000000000000003",
+            )
+        );
+        assert_eq!(
+            transfer().hash(),
+            "41ce1e673a918270fc588dcd13e6c7e611ee50398b03ce5bb1898ec68d029abe"
+        );
+        assert_eq!(
+            hex::encode(header().to_bytes()),
+            concat!(
+                "0000000000000001",
+                "0000000000000002",
+                "00000003",
+                "303030",
+                "00000040",
+                "30303030303030303030303030303030",
+                "30303030303030303030303030303030",
+                "30303030303030303030303030303030",
+                "30303030303030303030303030303030",
+                "01",
+                "00000005",
+                "6361726f6c",
+                "00000000",
+                "0000000000000000",
+            )
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for transaction in [account_creation(), transfer(), coinbase()] {
+            assert_eq!(
+                Transaction::from_bytes(&transaction.to_bytes()),
+                Ok(transaction)
+            );
+        }
+
+        let account = Account {
+            public_key: "ab".to_string(),
+            balance: Amount::new(100),
+            nonce: 1,
+        };
+        assert_eq!(Account::from_bytes(&account.to_bytes()), Ok(account));
+
+        let genesis = Block::new_genesis(&Default::default());
+        let block = Block::new(
+            &genesis,
+            vec![
+                Transaction::account_creation(
+                    "account_id".to_string(),
+                    Amount::new(100),
+                    keypair("account_id").public_key(),
+                ),
+                Transaction::transfer(
+                    &network_id(),
+                    "account_id".to_string(),
+                    "other_account_id".to_string(),
+                    Amount::new(10),
+                    Amount::ZERO,
+                    0,
+                    &keypair("account_id"),
+                ),
+            ],
+            0,
+        );
+        assert_eq!(Block::from_bytes(&block.to_bytes()), Ok(block));
+
+        let mut blockchain = Blockchain::default();
+        blockchain
+            .add_block(vec![Transaction::account_creation(
+                "account_id".to_string(),
+                Amount::new(100),
+                keypair("account_id").public_key(),
+            )])
+            .unwrap();
+        let snapshot = blockchain.snapshot(1).unwrap();
+        assert_eq!(Snapshot::from_bytes(&snapshot.to_bytes()), Ok(snapshot));
+    }
+
+    #[test]
+    fn test_invalid_bytes() {
+        let bytes = transfer().to_bytes();
+
+        assert_eq!(
+            Transaction::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            Transaction::from_bytes(&[bytes.as_slice(), &[0]].concat()),
+            Err(DecodeError::TrailingBytes)
+        );
+        assert_eq!(
+            Transaction::from_bytes(&[3]),
+            Err(DecodeError::InvalidVariant(3))
+        );
+        assert_eq!(
+            String::from_bytes(&[0, 0, 0, 1, 0xff]),
+            Err(DecodeError::InvalidUtf8)
+        );
+        // Map keys must be strictly increasing
+        let map = BTreeMap::from([("a".to_string(), 1u8), ("b".to_string(), 2u8)]);
+        assert_eq!(
+            hex::encode(map.to_bytes()),
+            concat!("00000002", "00000001", "61", "01", "00000001", "62", "02")
+        );
+        for entries in [
+            concat!("00000002", "00000001", "62", "02", "00000001", "61", "01"),
+            concat!("00000002", "00000001", "61", "01", "00000001", "61", "02"),
+        ] {
+            assert_eq!(
+                BTreeMap::<String, u8>::from_bytes(&hex::decode(entries).unwrap()),
+                Err(DecodeError::UnorderedKeys)
+            );
+        }
+
+        // A length larger than the input is not allocated
+        assert_eq!(
+            Vec::<Block>::from_bytes(&[0xff, 0xff, 0xff, 0xff]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+    }
+}
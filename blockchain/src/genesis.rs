@@ -0,0 +1,323 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::{fs, io};
+use thiserror::Error;
+
+use crate::{
+    amount::Amount,
+    block::Block,
+    keys,
+    proof_of_work::ProofOfWork,
+    reward::BlockReward,
+    supply::SupplyPolicy,
+    transaction::{AccountCreation, Transaction},
+};
+
+/// Chain id of the default genesis configuration
+pub const DEFAULT_CHAIN_ID: &str = "b-local";
+
+/// Creation time of the default genesis block (2024-01-01T00:00:00Z)
+pub const DEFAULT_GENESIS_TIMESTAMP: i64 = 1_704_067_200_000;
+
+/// Represents the configuration every node of a chain shares to build the same genesis block
+///
+/// It can be loaded from a JSON file:
+///
+/// ```json
+/// {
+///   "chain_id": "b-local",
+///   "timestamp": 1704067200000,
+///   "allocations": [{ "account_id": "alice", "balance": 1000, "public_key": "<hex encoded key>" }],
+///   "proof_of_work": { "initial_difficulty": 16, "target_block_time": 10000, "retarget_interval": 10 },
+///   "block_reward": { "initial_reward": 50, "halving_interval": 100000 },
+///   "supply_policy": { "mode": "restricted", "faucets": ["alice"] }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisConfig {
+    pub chain_id: String,
+    /// Creation time of the genesis block, in milliseconds since the unix epoch
+    pub timestamp: i64,
+    /// Accounts created in the genesis block
+    #[serde(default)]
+    pub allocations: Vec<GenesisAllocation>,
+    /// Proof-of-work rules of the chain, blocks are not mined when missing
+    #[serde(default)]
+    pub proof_of_work: Option<ProofOfWork>,
+    /// Reward minted by the coinbase of every block, blocks have no coinbase when missing
+    #[serde(default)]
+    pub block_reward: Option<BlockReward>,
+    /// Who can fund the initial balance of new accounts, they mint it when missing
+    #[serde(default)]
+    pub supply_policy: SupplyPolicy,
+}
+
+/// Represents an account created with a balance in the genesis block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisAllocation {
+    pub account_id: String,
+    pub balance: Amount,
+    /// Hex encoded public key the account is bound to
+    pub public_key: String,
+}
+
+/// Represents the reason a genesis configuration can't be loaded
+#[derive(Debug, Error)]
+pub enum GenesisError {
+    /// The file can't be read
+    #[error("could not read genesis file: {0}")]
+    Io(io::Error),
+    /// The file is not a valid JSON genesis configuration
+    #[error("invalid genesis file: {0}")]
+    Parse(serde_json::Error),
+    /// An account is allocated twice
+    #[error("account '{0}' is allocated more than once")]
+    DuplicateAllocation(String),
+    /// An account is allocated with an invalid public key
+    #[error("account '{0}' is allocated an invalid public key")]
+    InvalidPublicKey(String),
+    /// The proof-of-work target block time or retarget interval is not strictly positive
+    #[error("proof-of-work target block time and retarget interval must be strictly positive")]
+    InvalidProofOfWork,
+    /// The block reward halving interval is not strictly positive
+    #[error("block reward halving interval must be strictly positive")]
+    InvalidBlockReward,
+    /// The allocations and the block rewards add up to more than the maximum amount
+    #[error("allocations and block rewards exceed the maximum supply")]
+    SupplyOverflow,
+}
+
+impl Default for GenesisConfig {
+    fn default() -> Self {
+        GenesisConfig {
+            chain_id: DEFAULT_CHAIN_ID.to_string(),
+            timestamp: DEFAULT_GENESIS_TIMESTAMP,
+            allocations: vec![],
+            proof_of_work: None,
+            block_reward: None,
+            supply_policy: SupplyPolicy::Open,
+        }
+    }
+}
+
+impl GenesisConfig {
+    /// Load a genesis configuration from a JSON file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<GenesisConfig, GenesisError> {
+        let data = fs::read_to_string(path).map_err(GenesisError::Io)?;
+        let config: GenesisConfig = serde_json::from_str(&data).map_err(GenesisError::Parse)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Check that the allocations can be applied and the proof-of-work and reward rules make sense
+    pub fn validate(&self) -> Result<(), GenesisError> {
+        if let Some(proof_of_work) = &self.proof_of_work {
+            if proof_of_work.target_block_time <= 0 || proof_of_work.retarget_interval <= 0 {
+                return Err(GenesisError::InvalidProofOfWork);
+            }
+        }
+        if let Some(block_reward) = &self.block_reward {
+            if block_reward.halving_interval <= 0 {
+                return Err(GenesisError::InvalidBlockReward);
+            }
+        }
+
+        let mut account_ids = HashSet::new();
+        for allocation in &self.allocations {
+            if !account_ids.insert(&allocation.account_id) {
+                return Err(GenesisError::DuplicateAllocation(
+                    allocation.account_id.clone(),
+                ));
+            }
+            if !keys::is_valid_public_key(&allocation.public_key) {
+                return Err(GenesisError::InvalidPublicKey(
+                    allocation.account_id.clone(),
+                ));
+            }
+        }
+
+        // Balances can't overflow as long as the total supply can't
+        let rewards = self
+            .block_reward
+            .as_ref()
+            .map_or(Amount::ZERO, BlockReward::max_supply);
+        Amount::checked_sum(self.allocations.iter().map(|allocation| allocation.balance))
+            .and_then(|allocations| allocations.checked_add(rewards))
+            .map_err(|_| GenesisError::SupplyOverflow)?;
+
+        Ok(())
+    }
+
+    /// Get the transactions creating the allocated accounts
+    ///
+    /// Their ids and creation times are derived from the configuration, so every node
+    /// builds exactly the same transactions.
+    pub fn transactions(&self) -> Vec<Transaction> {
+        self.allocations
+            .iter()
+            .map(|allocation| {
+                Transaction::AccountCreation(AccountCreation {
+                    id: format!("genesis-{}", allocation.account_id),
+                    created_at: self.timestamp,
+                    account_id: allocation.account_id.clone(),
+                    initial_balance: allocation.balance,
+                    public_key: allocation.public_key.clone(),
+                    faucet: None,
+                    signature: String::new(),
+                })
+            })
+            .collect()
+    }
+
+    /// Build the genesis block
+    pub fn block(&self) -> Block {
+        Block::new_genesis(self)
+    }
+
+    /// Get the id of the network of the chain: the hash of its genesis block
+    ///
+    /// Signatures cover it, so a transaction signed for a chain is invalid on every other chain.
+    pub fn network_id(&self) -> String {
+        self.block().hash
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Public key of the allocated account
+    const PUBLIC_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+
+    fn config() -> GenesisConfig {
+        GenesisConfig {
+            chain_id: "test".to_string(),
+            timestamp: 1_000,
+            allocations: vec![GenesisAllocation {
+                account_id: "account_id".to_string(),
+                balance: Amount::new(100),
+                public_key: PUBLIC_KEY.to_string(),
+            }],
+            proof_of_work: None,
+            block_reward: None,
+            supply_policy: SupplyPolicy::Open,
+        }
+    }
+
+    #[test]
+    fn test_block_is_deterministic() {
+        let config = config();
+
+        assert_eq!(config.block().hash, config.block().hash);
+        assert_eq!(config.block().header.created_at, 1_000);
+        assert_eq!(config.block().transactions.len(), 1);
+
+        // The chain id is part of the identity of the chain
+        let other_config = GenesisConfig {
+            chain_id: "other".to_string(),
+            ..config.clone()
+        };
+        assert_ne!(config.block().hash, other_config.block().hash);
+    }
+
+    #[test]
+    fn test_from_file() {
+        let path = std::env::temp_dir().join(format!("genesis-{}.json", uuid::Uuid::new_v4()));
+        fs::write(
+            &path,
+            format!(
+                r#"{{"chain_id":"test","timestamp":1000,"allocations":[{{"account_id":"account_id","balance":100,"public_key":"{PUBLIC_KEY}"}}]}}"#
+            ),
+        )
+        .unwrap();
+
+        let loaded = GenesisConfig::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, config());
+    }
+
+    #[test]
+    fn test_from_file_errors() {
+        let path = std::env::temp_dir().join(format!("genesis-{}.json", uuid::Uuid::new_v4()));
+        assert!(matches!(
+            GenesisConfig::from_file(&path),
+            Err(GenesisError::Io(_))
+        ));
+
+        fs::write(&path, r#"{"chain_id":"test"}"#).unwrap();
+        let result = GenesisConfig::from_file(&path);
+        assert!(matches!(result, Err(GenesisError::Parse(_))));
+
+        // Balances can't be negative
+        fs::write(
+            &path,
+            format!(
+                r#"{{"chain_id":"test","timestamp":1000,"allocations":[{{"account_id":"account_id","balance":-1,"public_key":"{PUBLIC_KEY}"}}]}}"#
+            ),
+        )
+        .unwrap();
+        let result = GenesisConfig::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(GenesisError::Parse(_))));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = config();
+        assert!(config.validate().is_ok());
+
+        config.allocations.push(config.allocations[0].clone());
+        assert!(matches!(
+            config.validate(),
+            Err(GenesisError::DuplicateAllocation(_))
+        ));
+
+        config.allocations[1].account_id = "other".to_string();
+        config.allocations[1].balance = Amount::MAX;
+        assert!(matches!(
+            config.validate(),
+            Err(GenesisError::SupplyOverflow)
+        ));
+
+        config.allocations[1].balance = Amount::new(100);
+        config.allocations[1].public_key = "invalid".to_string();
+        assert!(matches!(
+            config.validate(),
+            Err(GenesisError::InvalidPublicKey(_))
+        ));
+
+        config.allocations.pop();
+        config.proof_of_work = Some(ProofOfWork {
+            initial_difficulty: 8,
+            target_block_time: 1_000,
+            retarget_interval: 0,
+        });
+        assert!(matches!(
+            config.validate(),
+            Err(GenesisError::InvalidProofOfWork)
+        ));
+
+        config.proof_of_work = None;
+        config.block_reward = Some(BlockReward {
+            initial_reward: Amount::new(50),
+            halving_interval: 0,
+        });
+        assert!(matches!(
+            config.validate(),
+            Err(GenesisError::InvalidBlockReward)
+        ));
+
+        // The allocated balance and the rewards add up to more than the maximum amount
+        config.block_reward = Some(BlockReward {
+            initial_reward: Amount::MAX,
+            halving_interval: 10,
+        });
+        assert!(matches!(
+            config.validate(),
+            Err(GenesisError::SupplyOverflow)
+        ));
+    }
+}
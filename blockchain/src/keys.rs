@@ -0,0 +1,160 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use thiserror::Error;
+
+/// Represents the key pair owning an account
+///
+/// The public key is bound to the account when it is created, and the private key signs
+/// the transfers sending funds from it. Keys and signatures are hex encoded.
+#[derive(Debug, Clone)]
+pub struct Keypair {
+    signing_key: SigningKey,
+}
+
+/// Represents the reason a private key can't be decoded
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("private key should be 32 hex encoded bytes")]
+pub struct InvalidKeyError;
+
+impl Keypair {
+    /// Generate a new random key pair
+    pub fn generate() -> Keypair {
+        Keypair {
+            signing_key: SigningKey::generate(&mut rand::rngs::OsRng),
+        }
+    }
+
+    /// Load a key pair from its hex encoded private key
+    pub fn from_private_key(private_key: &str) -> Result<Keypair, InvalidKeyError> {
+        let bytes = hex::decode(private_key.trim()).map_err(|_| InvalidKeyError)?;
+        let bytes = bytes.try_into().map_err(|_| InvalidKeyError)?;
+
+        Ok(Keypair {
+            signing_key: SigningKey::from_bytes(&bytes),
+        })
+    }
+
+    /// Get the hex encoded private key
+    pub fn private_key(&self) -> String {
+        hex::encode(self.signing_key.to_bytes())
+    }
+
+    /// Get the hex encoded public key
+    pub fn public_key(&self) -> String {
+        hex::encode(self.signing_key.verifying_key().to_bytes())
+    }
+
+    /// Sign a message, and return the hex encoded signature
+    pub fn sign(&self, message: &[u8]) -> String {
+        hex::encode(self.signing_key.sign(message).to_bytes())
+    }
+}
+
+/// Check if a hex encoded public key is a valid Ed25519 key
+pub fn is_valid_public_key(public_key: &str) -> bool {
+    decode_public_key(public_key).is_some()
+}
+
+/// Check a hex encoded signature of a message against a hex encoded public key
+pub fn verify(public_key: &str, message: &[u8], signature: &str) -> bool {
+    let Some(verifying_key) = decode_public_key(public_key) else {
+        return false;
+    };
+    let Some(signature) = hex::decode(signature)
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+    else {
+        return false;
+    };
+
+    verifying_key.verify(message, &signature).is_ok()
+}
+
+/// Decode a hex encoded public key
+fn decode_public_key(public_key: &str) -> Option<VerifyingKey> {
+    let bytes = hex::decode(public_key).ok()?.try_into().ok()?;
+    VerifyingKey::from_bytes(&bytes).ok()
+}
+
+/// Fixtures for the tests of this crate and of the crates depending on it, with `test-utils`
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils {
+    use super::Keypair;
+    use crate::{
+        amount::Amount, block::Block, blockchain::Blockchain, encoding::Encode,
+        transaction::Transaction,
+    };
+
+    /// Get a key pair derived from the account id, so tests can sign for any account
+    pub fn keypair(account_id: &str) -> Keypair {
+        let seed: String = account_id
+            .bytes()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Keypair::from_private_key(&format!("{seed:0<64}")).unwrap()
+    }
+
+    /// Get the network id of the default genesis configuration, to sign transactions for it
+    pub fn network_id() -> String {
+        crate::genesis::GenesisConfig::default().network_id()
+    }
+
+    /// Get an account creation making the next block added to the blockchain with
+    /// `Blockchain::add_block` exactly `size` bytes long, padded with its account id
+    pub fn filler_transaction(blockchain: &Blockchain, size: usize) -> Transaction {
+        let account_creation = |account_id: String| {
+            Transaction::account_creation(account_id, Amount::ZERO, keypair("filler").public_key())
+        };
+        // The id of the block keeps the accounts of successive fillers apart
+        let account_id = (blockchain.tip().header.id + 1).to_string();
+        let overhead = Block::new(
+            blockchain.tip(),
+            vec![account_creation(account_id.clone())],
+            0,
+        )
+        .to_bytes()
+        .len();
+
+        account_creation(account_id + &"a".repeat(size - overhead))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let keypair = Keypair::generate();
+        let signature = keypair.sign(b"message");
+
+        assert!(verify(&keypair.public_key(), b"message", &signature));
+        assert!(!verify(&keypair.public_key(), b"other message", &signature));
+        assert!(!verify(
+            &Keypair::generate().public_key(),
+            b"message",
+            &signature
+        ));
+        assert!(!verify(&keypair.public_key(), b"message", "invalid"));
+        assert!(!verify("invalid", b"message", &signature));
+    }
+
+    #[test]
+    fn test_private_key() {
+        let keypair = Keypair::generate();
+        let loaded = Keypair::from_private_key(&keypair.private_key()).unwrap();
+
+        assert_eq!(loaded.public_key(), keypair.public_key());
+        assert_eq!(Keypair::from_private_key("00").err(), Some(InvalidKeyError));
+        assert_eq!(
+            Keypair::from_private_key("invalid").err(),
+            Some(InvalidKeyError)
+        );
+    }
+
+    #[test]
+    fn test_is_valid_public_key() {
+        assert!(is_valid_public_key(&Keypair::generate().public_key()));
+        assert!(!is_valid_public_key("invalid"));
+        assert!(!is_valid_public_key("00"));
+    }
+}
@@ -1,3 +1,17 @@
+pub mod account_state;
+pub mod amount;
 pub mod block;
 pub mod blockchain;
+pub mod encoding;
+pub mod genesis;
+pub mod keys;
+pub mod merkle;
+pub mod proof_of_work;
+pub mod redb_store;
+pub mod reward;
+pub mod snapshot;
+pub mod storage;
+pub mod store;
+pub mod supply;
 pub mod transaction;
+pub mod validation;
@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Root of a tree without leaves, for blocks without transactions
+pub const EMPTY_ROOT: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Represents the proof that a leaf is part of a Merkle tree
+///
+/// It lists the sibling of every node on the path from the leaf to the root.
+/// A node without sibling (the last one of a level with an odd number of nodes)
+/// is moved up as is, and has no step.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub steps: Vec<ProofStep>,
+}
+
+/// Represents a sibling on the path from a leaf to the root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofStep {
+    /// Hex encoded hash of the sibling
+    pub hash: String,
+    pub side: Side,
+}
+
+/// Represents the side of a sibling in its pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+type Node = [u8; 32];
+
+/// Calculate the root of the Merkle tree over the given leaves, as a hex string
+///
+/// Leaves and inner nodes are hashed with a different prefix, so an inner node
+/// can't be passed off as a leaf.
+pub fn merkle_root(leaves: &[String]) -> String {
+    let mut level: Vec<Node> = leaves.iter().map(|leaf| leaf_node(leaf)).collect();
+    if level.is_empty() {
+        return EMPTY_ROOT.to_string();
+    }
+
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    hex::encode(level[0])
+}
+
+/// Build the proof that the leaf at the given index is part of the tree
+pub fn merkle_proof(leaves: &[String], index: usize) -> Option<MerkleProof> {
+    if index >= leaves.len() {
+        return None;
+    }
+
+    let mut level: Vec<Node> = leaves.iter().map(|leaf| leaf_node(leaf)).collect();
+    let mut index = index;
+    let mut steps = Vec::new();
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if let Some(hash) = level.get(sibling) {
+            steps.push(ProofStep {
+                hash: hex::encode(hash),
+                side: if sibling < index {
+                    Side::Left
+                } else {
+                    Side::Right
+                },
+            });
+        }
+
+        level = next_level(&level);
+        index /= 2;
+    }
+
+    Some(MerkleProof { steps })
+}
+
+impl MerkleProof {
+    /// Check that the leaf is part of the tree with the given root
+    pub fn verify(&self, leaf: &str, root: &str) -> bool {
+        let mut node = leaf_node(leaf);
+        for step in &self.steps {
+            let Some(sibling) = hex::decode(&step.hash)
+                .ok()
+                .and_then(|bytes| Node::try_from(bytes).ok())
+            else {
+                return false;
+            };
+
+            node = match step.side {
+                Side::Left => inner_node(&sibling, &node),
+                Side::Right => inner_node(&node, &sibling),
+            };
+        }
+
+        hex::encode(node) == root
+    }
+}
+
+/// Hash the nodes of a level two by two
+fn next_level(level: &[Node]) -> Vec<Node> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => inner_node(left, right),
+            [single] => *single,
+            _ => unreachable!("Chunks should have one or two nodes"),
+        })
+        .collect()
+}
+
+fn leaf_node(leaf: &str) -> Node {
+    Sha256::new()
+        .chain_update([0])
+        .chain_update(leaf.as_bytes())
+        .finalize()
+        .into()
+}
+
+fn inner_node(left: &Node, right: &Node) -> Node {
+    Sha256::new()
+        .chain_update([1])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn leaves(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("leaf_{i}")).collect()
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        assert_eq!(merkle_root(&[]), EMPTY_ROOT);
+        assert_eq!(merkle_root(&leaves(1)), hex::encode(leaf_node("leaf_0")));
+        assert_eq!(
+            merkle_root(&leaves(3)),
+            hex::encode(inner_node(
+                &inner_node(&leaf_node("leaf_0"), &leaf_node("leaf_1")),
+                &leaf_node("leaf_2")
+            ))
+        );
+
+        // Any change in the leaves changes the root
+        let mut changed = leaves(4);
+        changed[2] = "other".to_string();
+        assert_ne!(merkle_root(&changed), merkle_root(&leaves(4)));
+        changed.swap(0, 1);
+        assert_ne!(merkle_root(&changed), merkle_root(&leaves(4)));
+    }
+
+    #[test]
+    fn test_merkle_proof() {
+        for count in 1..=9 {
+            let leaves = leaves(count);
+            let root = merkle_root(&leaves);
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = merkle_proof(&leaves, index).unwrap();
+                assert!(proof.verify(leaf, &root));
+                assert!(!proof.verify("other", &root));
+                assert!(!proof.verify(leaf, EMPTY_ROOT));
+            }
+            assert_eq!(merkle_proof(&leaves, count), None);
+        }
+    }
+
+    #[test]
+    fn test_tampered_proof() {
+        let leaves = leaves(4);
+        let root = merkle_root(&leaves);
+        let proof = merkle_proof(&leaves, 1).unwrap();
+
+        let mut tampered = proof.clone();
+        tampered.steps[0].side = Side::Right;
+        assert!(!tampered.verify("leaf_1", &root));
+
+        let mut tampered = proof.clone();
+        tampered.steps[1].hash = "invalid".to_string();
+        assert!(!tampered.verify("leaf_1", &root));
+
+        let mut tampered = proof;
+        tampered.steps.pop();
+        assert!(!tampered.verify("leaf_1", &root));
+    }
+}
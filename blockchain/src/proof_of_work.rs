@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+use crate::block::Block;
+
+/// Represents the proof-of-work rules of a chain
+///
+/// The difficulty of a block is the number of leading zero bits its hash must have.
+/// Every `retarget_interval` blocks, the difficulty is adjusted so blocks keep being
+/// created every `target_block_time` milliseconds on average.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofOfWork {
+    /// Difficulty of the first blocks, until the first retarget
+    pub initial_difficulty: u32,
+    /// Expected time between two blocks, in milliseconds
+    pub target_block_time: i64,
+    /// Number of blocks between two difficulty adjustments
+    pub retarget_interval: i64,
+}
+
+impl ProofOfWork {
+    /// Get the difficulty the block following the given blocks must meet
+    ///
+    /// On a retarget boundary, the difficulty goes up by one if the last `retarget_interval`
+    /// blocks were created more than twice as fast as expected, and goes down by one if they
+    /// were created more than twice as slow. Otherwise, it is the difficulty of the last block.
+    pub fn next_difficulty(&self, previous_blocks: &[Block]) -> u32 {
+        let Some(tip) = previous_blocks.last() else {
+            return self.initial_difficulty;
+        };
+        let next_id = tip.header.id + 1;
+        if next_id % self.retarget_interval != 0 {
+            return tip.header.difficulty;
+        }
+        // Wait for a full window of blocks before the first adjustment
+        let Some(window_start) = previous_blocks
+            .len()
+            .checked_sub(self.retarget_interval as usize + 1)
+            .map(|i| &previous_blocks[i])
+        else {
+            return tip.header.difficulty;
+        };
+
+        // Timestamps are set by the producers and the rules are configured per chain, so a
+        // large target or a forged timestamp saturates instead of overflowing
+        let elapsed = tip
+            .header
+            .created_at
+            .saturating_sub(window_start.header.created_at);
+        let expected = self
+            .target_block_time
+            .saturating_mul(self.retarget_interval);
+
+        if elapsed < expected / 2 {
+            tip.header.difficulty.saturating_add(1)
+        } else if elapsed > expected.saturating_mul(2) {
+            tip.header.difficulty.saturating_sub(1)
+        } else {
+            tip.header.difficulty
+        }
+    }
+}
+
+/// Get the expected number of hashes needed to mine a block with the given difficulty
+///
+/// Without proof-of-work every block has a difficulty of 0 and a work of 1, so the chain
+/// with the most work is the longest one. The work is capped to fit in a `u128`.
+pub fn work(difficulty: u32) -> u128 {
+    1 << difficulty.min(127)
+}
+
+/// Check if a hex encoded hash has at least `difficulty` leading zero bits
+pub fn meets_difficulty(hash: &str, difficulty: u32) -> bool {
+    leading_zero_bits(hash) >= difficulty
+}
+
+/// Count the leading zero bits of a hex encoded hash
+fn leading_zero_bits(hash: &str) -> u32 {
+    let mut count = 0;
+    for digit in hash.chars() {
+        match digit.to_digit(16) {
+            Some(0) => count += 4,
+            Some(nibble) => return count + nibble.leading_zeros() - 28,
+            None => return count,
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::genesis::GenesisConfig;
+
+    fn proof_of_work() -> ProofOfWork {
+        ProofOfWork {
+            initial_difficulty: 4,
+            target_block_time: 1_000,
+            retarget_interval: 2,
+        }
+    }
+
+    /// Build a chain whose blocks are created `block_time` milliseconds apart
+    fn blocks(count: usize, block_time: i64, difficulty: u32) -> Vec<Block> {
+        let mut blocks = vec![Block::new_genesis(&GenesisConfig::default())];
+        for _ in 0..count {
+            let mut block = Block::new(blocks.last().unwrap(), vec![], 0);
+            block.header.created_at = blocks.last().unwrap().header.created_at + block_time;
+            block.header.difficulty = difficulty;
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_leading_zero_bits() {
+        assert_eq!(leading_zero_bits("ff"), 0);
+        assert_eq!(leading_zero_bits("7f"), 1);
+        assert_eq!(leading_zero_bits("1f"), 3);
+        assert_eq!(leading_zero_bits("0f"), 4);
+        assert_eq!(leading_zero_bits("003f"), 10);
+        assert_eq!(leading_zero_bits("0000"), 16);
+
+        assert!(meets_difficulty("0f", 4));
+        assert!(!meets_difficulty("0f", 5));
+    }
+
+    #[test]
+    fn test_work() {
+        assert_eq!(work(0), 1);
+        assert_eq!(work(8), 256);
+        assert_eq!(work(256), work(127));
+    }
+
+    #[test]
+    fn test_next_difficulty() {
+        let proof_of_work = proof_of_work();
+
+        assert_eq!(proof_of_work.next_difficulty(&[]), 4);
+
+        // Not on a retarget boundary
+        assert_eq!(proof_of_work.next_difficulty(&blocks(2, 1, 4)), 4);
+
+        // Not enough blocks for a full window yet
+        assert_eq!(proof_of_work.next_difficulty(&blocks(1, 1, 4)), 4);
+
+        // Blocks created on time
+        assert_eq!(proof_of_work.next_difficulty(&blocks(3, 1_000, 4)), 4);
+
+        // Blocks created too fast
+        assert_eq!(proof_of_work.next_difficulty(&blocks(3, 100, 4)), 5);
+
+        // Blocks created too slow
+        assert_eq!(proof_of_work.next_difficulty(&blocks(3, 10_000, 4)), 3);
+        assert_eq!(proof_of_work.next_difficulty(&blocks(3, 10_000, 0)), 0);
+    }
+
+    #[test]
+    fn test_next_difficulty_overflow() {
+        // Expected window time overflows
+        let slow_target = ProofOfWork {
+            initial_difficulty: 4,
+            target_block_time: i64::MAX / 2,
+            retarget_interval: 2,
+        };
+        assert_eq!(slow_target.next_difficulty(&blocks(3, 1_000, 4)), 5);
+
+        // Elapsed time overflows
+        let mut blocks = blocks(3, 1_000, u32::MAX);
+        blocks[1].header.created_at = i64::MIN;
+        blocks[3].header.created_at = i64::MAX;
+        assert_eq!(proof_of_work().next_difficulty(&blocks), u32::MAX - 1);
+        blocks[1].header.created_at = i64::MAX;
+        blocks[3].header.created_at = i64::MIN;
+        assert_eq!(proof_of_work().next_difficulty(&blocks), u32::MAX);
+    }
+}
@@ -0,0 +1,251 @@
+use redb::{Database, ReadTransaction, ReadableTable, TableDefinition, WriteTransaction};
+use std::collections::BTreeMap;
+use std::path::Path;
+use thiserror::Error;
+
+use crate::{
+    account_state::{Account, AccountChanges, Accounts},
+    block::Block,
+    encoding::{Decode, Encode},
+    store::ChainStore,
+};
+
+/// Encoded blocks, by hash
+const BLOCKS: TableDefinition<&str, &[u8]> = TableDefinition::new("blocks");
+/// Total work of the branch ending with each block, by hash
+const TOTAL_WORK: TableDefinition<&str, u128> = TableDefinition::new("total_work");
+/// Hashes of the blocks of the best chain, by height
+const BEST_CHAIN: TableDefinition<i64, &str> = TableDefinition::new("best_chain");
+/// Encoded accounts of the best chain, by id
+const ACCOUNTS: TableDefinition<&str, &[u8]> = TableDefinition::new("accounts");
+/// Properties of the store, by name
+const METADATA: TableDefinition<&str, i64> = TableDefinition::new("metadata");
+
+/// Name of the property holding the height of the snapshot the store was bootstrapped from
+const SNAPSHOT_HEIGHT: &str = "snapshot_height";
+
+/// Represents a failure of the redb database
+#[derive(Debug, Error)]
+#[error("chain database error: {0}")]
+pub struct RedbStoreError(Box<redb::Error>);
+
+impl<E: Into<redb::Error>> From<E> for RedbStoreError {
+    fn from(error: E) -> Self {
+        RedbStoreError(Box::new(error.into()))
+    }
+}
+
+/// Represents a store kept in a redb database file, for nodes that restart
+///
+/// Every write is a transaction, so a crash leaves the store as it was after the last
+/// complete write. Blocks and accounts use the canonical encoding.
+#[derive(Debug)]
+pub struct RedbStore {
+    database: Database,
+}
+
+impl RedbStore {
+    /// Open the database at the given path, creating it and its directory if needed
+    pub fn open(path: impl AsRef<Path>) -> Result<RedbStore, RedbStoreError> {
+        let path = path.as_ref();
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let database = Database::create(path)?;
+
+        // Create the tables, so they can be read before the first block is stored
+        let transaction = database.begin_write()?;
+        transaction.open_table(BLOCKS)?;
+        transaction.open_table(TOTAL_WORK)?;
+        transaction.open_table(BEST_CHAIN)?;
+        transaction.open_table(ACCOUNTS)?;
+        transaction.open_table(METADATA)?;
+        transaction.commit()?;
+
+        Ok(RedbStore { database })
+    }
+
+    fn read<T>(&self, read: impl FnOnce(&ReadTransaction) -> Result<T, RedbStoreError>) -> T {
+        let transaction = self
+            .database
+            .begin_read()
+            .expect("Chain store should be readable");
+        read(&transaction).expect("Chain store should be readable")
+    }
+
+    fn write(&mut self, write: impl FnOnce(&WriteTransaction) -> Result<(), RedbStoreError>) {
+        let transaction = self
+            .database
+            .begin_write()
+            .expect("Chain store should be writable");
+        write(&transaction).expect("Chain store should be writable");
+        transaction
+            .commit()
+            .expect("Chain store should be writable");
+    }
+}
+
+impl Accounts for RedbStore {
+    fn account(&self, account_id: &str) -> Option<Account> {
+        self.read(|transaction| {
+            let accounts = transaction.open_table(ACCOUNTS)?;
+            let account = accounts.get(account_id)?;
+            Ok(account.map(|data| decode(data.value())))
+        })
+    }
+}
+
+impl ChainStore for RedbStore {
+    fn block_by_hash(&self, hash: &str) -> Option<Block> {
+        self.read(|transaction| {
+            let blocks = transaction.open_table(BLOCKS)?;
+            let block = blocks.get(hash)?;
+            Ok(block.map(|data| decode(data.value())))
+        })
+    }
+
+    fn block_by_height(&self, height: i64) -> Option<Block> {
+        let hash = self.read(|transaction| {
+            let best_chain = transaction.open_table(BEST_CHAIN)?;
+            let hash = best_chain.get(height)?;
+            Ok(hash.map(|hash| hash.value().to_string()))
+        })?;
+        self.block_by_hash(&hash)
+    }
+
+    fn tip(&self) -> Option<Block> {
+        let hash = self.read(|transaction| {
+            let best_chain = transaction.open_table(BEST_CHAIN)?;
+            let tip = best_chain.last()?;
+            Ok(tip.map(|(_, hash)| hash.value().to_string()))
+        })?;
+        self.block_by_hash(&hash)
+    }
+
+    fn total_work(&self, hash: &str) -> Option<u128> {
+        self.read(|transaction| {
+            let total_work = transaction.open_table(TOTAL_WORK)?;
+            let work = total_work.get(hash)?;
+            Ok(work.map(|work| work.value()))
+        })
+    }
+
+    fn accounts(&self) -> BTreeMap<String, Account> {
+        self.read(|transaction| {
+            let accounts = transaction.open_table(ACCOUNTS)?;
+            let mut all = BTreeMap::new();
+            for entry in accounts.iter()? {
+                let (account_id, account) = entry?;
+                all.insert(account_id.value().to_string(), decode(account.value()));
+            }
+            Ok(all)
+        })
+    }
+
+    fn snapshot_height(&self) -> Option<i64> {
+        self.read(|transaction| {
+            let metadata = transaction.open_table(METADATA)?;
+            let height = metadata.get(SNAPSHOT_HEIGHT)?;
+            Ok(height.map(|height| height.value()))
+        })
+    }
+
+    fn set_snapshot_height(&mut self, height: i64) {
+        self.write(|transaction| {
+            transaction
+                .open_table(METADATA)?
+                .insert(SNAPSHOT_HEIGHT, height)?;
+            Ok(())
+        })
+    }
+
+    fn insert_block(&mut self, block: &Block, total_work: u128) {
+        self.write(|transaction| write_block(transaction, block, total_work))
+    }
+
+    fn set_best_chain(&mut self, blocks: &[Block], changes: AccountChanges) {
+        self.write(|transaction| write_best_chain(transaction, blocks, changes))
+    }
+
+    fn insert_best_chain(&mut self, blocks: &[Block], total_work: u128, changes: AccountChanges) {
+        self.write(|transaction| {
+            if let Some(block) = blocks.last() {
+                write_block(transaction, block, total_work)?;
+            }
+            write_best_chain(transaction, blocks, changes)
+        })
+    }
+}
+
+fn write_block(
+    transaction: &WriteTransaction,
+    block: &Block,
+    total_work: u128,
+) -> Result<(), RedbStoreError> {
+    transaction
+        .open_table(BLOCKS)?
+        .insert(block.hash.as_str(), block.to_bytes().as_slice())?;
+    transaction
+        .open_table(TOTAL_WORK)?
+        .insert(block.hash.as_str(), total_work)?;
+    Ok(())
+}
+
+fn write_best_chain(
+    transaction: &WriteTransaction,
+    blocks: &[Block],
+    changes: AccountChanges,
+) -> Result<(), RedbStoreError> {
+    let mut best_chain = transaction.open_table(BEST_CHAIN)?;
+    if let Some(first) = blocks.first() {
+        // Remove the replaced blocks, the new branch can be shorter with more work
+        loop {
+            let height = match best_chain.last()? {
+                Some((height, _)) if height.value() >= first.header.id => height.value(),
+                _ => break,
+            };
+            best_chain.remove(height)?;
+        }
+        for (height, block) in (first.header.id..).zip(blocks) {
+            best_chain.insert(height, block.hash.as_str())?;
+        }
+    }
+
+    let mut accounts = transaction.open_table(ACCOUNTS)?;
+    for (account_id, account) in changes {
+        match account {
+            Some(account) => {
+                accounts.insert(account_id.as_str(), account.to_bytes().as_slice())?;
+            }
+            None => {
+                accounts.remove(account_id.as_str())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decode a value written by the store
+fn decode<T: Decode>(data: &[u8]) -> T {
+    T::from_bytes(data).expect("Stored data should be decodable")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::store::test::check_store;
+
+    #[test]
+    fn test_redb_store() {
+        let path = std::env::temp_dir().join(format!("b-redb-store-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        check_store(&mut RedbStore::open(&path).unwrap());
+
+        // The data is still there once reopened
+        let store = RedbStore::open(&path).unwrap();
+        assert_eq!(store.tip().unwrap().header.id, 2);
+        assert!(store.block_by_height(0).is_some());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+use crate::amount::Amount;
+
+/// Represents the reward minted by the coinbase of every block of a chain
+///
+/// The blocks following genesis mint `initial_reward` each, and the reward is halved, rounding
+/// down, every `halving_interval` blocks until it reaches 0. The total supply minted by the
+/// blocks is therefore capped, see `max_supply`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockReward {
+    /// Reward of the first blocks, until the first halving
+    pub initial_reward: Amount,
+    /// Number of blocks between two halvings
+    pub halving_interval: i64,
+}
+
+impl BlockReward {
+    /// Get the reward the coinbase of the block at the given height must mint
+    ///
+    /// The genesis block has no coinbase, its accounts come from the allocations.
+    pub fn reward(&self, block_id: i64) -> Amount {
+        if block_id <= 0 {
+            return Amount::ZERO;
+        }
+        let halvings = (block_id - 1) / self.halving_interval;
+        if halvings >= u64::BITS as i64 {
+            return Amount::ZERO;
+        }
+        Amount::new(self.initial_reward.value() >> halvings)
+    }
+
+    /// Get the total reward minted by the blocks up to the given height
+    ///
+    /// It stops at the maximum amount when the blocks mint more.
+    pub fn supply(&self, height: i64) -> Amount {
+        let mut supply = Amount::ZERO;
+        let mut reward = self.initial_reward.value();
+        let mut era_start: i64 = 1;
+        while era_start <= height && reward > 0 {
+            let era_end = height.min(era_start.saturating_add(self.halving_interval - 1));
+            let era_blocks = (era_end - era_start + 1) as u64;
+            supply = supply.saturating_add(Amount::new(reward).saturating_mul(era_blocks));
+            let Some(next_start) = era_start.checked_add(self.halving_interval) else {
+                break;
+            };
+            era_start = next_start;
+            reward >>= 1;
+        }
+        supply
+    }
+
+    /// Get the total reward the chain will ever mint
+    pub fn max_supply(&self) -> Amount {
+        self.supply(i64::MAX)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn block_reward() -> BlockReward {
+        BlockReward {
+            initial_reward: Amount::new(50),
+            halving_interval: 10,
+        }
+    }
+
+    #[test]
+    fn test_reward() {
+        let block_reward = block_reward();
+
+        assert_eq!(block_reward.reward(0), Amount::ZERO);
+        assert_eq!(block_reward.reward(1), Amount::new(50));
+        assert_eq!(block_reward.reward(10), Amount::new(50));
+        assert_eq!(block_reward.reward(11), Amount::new(25));
+        assert_eq!(block_reward.reward(21), Amount::new(12));
+        assert_eq!(block_reward.reward(61), Amount::ZERO);
+        assert_eq!(block_reward.reward(i64::MAX), Amount::ZERO);
+    }
+
+    #[test]
+    fn test_supply() {
+        let block_reward = block_reward();
+
+        assert_eq!(block_reward.supply(0), Amount::ZERO);
+        assert_eq!(block_reward.supply(1), Amount::new(50));
+        assert_eq!(block_reward.supply(12), Amount::new(550));
+        assert_eq!(
+            block_reward.supply(100),
+            Amount::checked_sum((1..=100).map(|block_id| block_reward.reward(block_id))).unwrap()
+        );
+        // 50, 25, 12, 6, 3 and 1 for 10 blocks each
+        assert_eq!(block_reward.max_supply(), Amount::new(970));
+
+        let block_reward = BlockReward {
+            initial_reward: Amount::MAX,
+            halving_interval: 2,
+        };
+        assert_eq!(block_reward.max_supply(), Amount::MAX);
+    }
+
+    proptest! {
+        #[test]
+        fn test_supply_matches_rewards(
+            initial_reward in any::<u64>(),
+            halving_interval in 1..50i64,
+            height in 0..500i64,
+        ) {
+            let block_reward = BlockReward {
+                initial_reward: Amount::new(initial_reward),
+                halving_interval,
+            };
+            let rewards = (1..=height).map(|block_id| block_reward.reward(block_id));
+            let expected = Amount::checked_sum(rewards).unwrap_or(Amount::MAX);
+            prop_assert_eq!(block_reward.supply(height), expected);
+            prop_assert!(block_reward.supply(height) <= block_reward.max_supply());
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+use crate::{
+    account_state::Account, block::Block, encoding::Encode, genesis::GenesisError,
+    merkle::merkle_root, validation::BlockError,
+};
+
+/// Represents the accounts of a blockchain after the block at a given height
+///
+/// A node can bootstrap from a snapshot and the blocks following it, without replaying the
+/// blocks before it. It can't check the accounts, so it must get the snapshot hash from a
+/// source it trusts, such as the operator of a node it already knows.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Hash of the genesis block of the chain
+    pub network_id: String,
+    /// Blocks ending with the block at the snapshot height, as many as needed to check the
+    /// difficulty of the next blocks
+    pub blocks: Vec<Block>,
+    /// Total work of the chain, up to the block at the snapshot height
+    pub total_work: u128,
+    /// Accounts after the block at the snapshot height, by id
+    pub accounts: BTreeMap<String, Account>,
+}
+
+/// Represents the reason a blockchain can't be bootstrapped from a snapshot
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    /// The genesis configuration is not valid
+    #[error("invalid genesis configuration: {0}")]
+    InvalidGenesis(#[from] GenesisError),
+    /// The store already has a chain
+    #[error("store already has a chain")]
+    StoreNotEmpty,
+    /// The snapshot was taken on a chain with another genesis block
+    #[error("snapshot was taken on a chain with another genesis block")]
+    NetworkMismatch,
+    /// The snapshot doesn't have the hash it is trusted to have
+    #[error("snapshot hash is {actual}, expected {expected}")]
+    HashMismatch { expected: String, actual: String },
+    /// The snapshot has too few blocks to check the difficulty of the next blocks
+    #[error("snapshot has {count} blocks, expected at least {expected}")]
+    MissingBlocks { count: usize, expected: usize },
+    /// A snapshot block doesn't follow the previous one
+    #[error("snapshot block is not valid: {0}")]
+    InvalidBlock(#[from] BlockError),
+    /// The total work is lower than the work of the snapshot blocks
+    #[error("snapshot total work is lower than the work of its blocks")]
+    InvalidTotalWork,
+}
+
+impl Snapshot {
+    /// Get the block at the snapshot height
+    pub fn block(&self) -> Option<&Block> {
+        self.blocks.last()
+    }
+
+    /// Calculate the root of the Merkle tree over the accounts, in id order
+    pub fn state_root(&self) -> String {
+        let leaves: Vec<String> = self
+            .accounts
+            .iter()
+            .map(|(account_id, account)| {
+                let mut bytes = account_id.to_bytes();
+                account.encode(&mut bytes);
+                hex::encode(Sha256::digest(bytes))
+            })
+            .collect();
+        merkle_root(&leaves)
+    }
+
+    /// Calculate the hash committing to the snapshot
+    ///
+    /// It covers the network, the block at the snapshot height, which is linked to the other
+    /// blocks, the total work and the accounts.
+    pub fn hash(&self) -> String {
+        let mut bytes = self.network_id.to_bytes();
+        self.block()
+            .map_or("", |block| block.hash.as_str())
+            .encode(&mut bytes);
+        self.total_work.encode(&mut bytes);
+        self.state_root().encode(&mut bytes);
+        hex::encode(Sha256::digest(bytes))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        amount::Amount, blockchain::Blockchain, keys::test_utils::keypair, transaction::Transaction,
+    };
+
+    fn snapshot() -> Snapshot {
+        let mut blockchain = Blockchain::default();
+        blockchain
+            .add_block(vec![Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            )])
+            .unwrap();
+        blockchain.snapshot(1).unwrap()
+    }
+
+    #[test]
+    fn test_hash() {
+        let snapshot = snapshot();
+        assert_eq!(snapshot.hash(), snapshot.clone().hash());
+
+        // Every committed part changes the hash
+        let mut other = snapshot.clone();
+        other.network_id = "other".to_string();
+        assert_ne!(other.hash(), snapshot.hash());
+
+        let mut other = snapshot.clone();
+        other.total_work += 1;
+        assert_ne!(other.hash(), snapshot.hash());
+
+        let mut other = snapshot.clone();
+        let account = other.accounts.get_mut("account_1").unwrap();
+        account.balance = account.balance.checked_add(Amount::new(1)).unwrap();
+        assert_ne!(other.state_root(), snapshot.state_root());
+        assert_ne!(other.hash(), snapshot.hash());
+
+        let mut other = snapshot.clone();
+        other.blocks.pop();
+        assert_ne!(other.hash(), snapshot.hash());
+    }
+}
@@ -0,0 +1,408 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::path::Path;
+
+use crate::{
+    account_state::{Account, AccountChanges, Accounts},
+    block::Block,
+    encoding::{Decode, DecodeError, Decoder, Encode},
+    store::{ChainStore, MemoryStore},
+};
+
+/// Represents a store kept in an append-only file, for nodes that restart
+///
+/// Every write to the store is appended to the file as a record: its encoded length, a big-endian
+/// `u32`, followed by its canonical encoding. The file is replayed into memory when it is opened.
+/// A record is only appended once the previous one is fully written, so a crash can only leave a
+/// partial record at the end of the file, which is dropped on the next open. A new best chain and
+/// its account changes are a single record, with the new block ending it if any, so the accounts
+/// always match the best chain.
+#[derive(Debug)]
+pub struct FileStorage {
+    file: File,
+    store: MemoryStore,
+}
+
+/// Represents a write to the store, as appended to the file
+#[derive(Debug)]
+enum Record {
+    /// A block stored with the total work of its branch
+    Block { block: Block, total_work: u128 },
+    /// The hashes of the new end of the best chain, with the account changes
+    BestChain {
+        hashes: Vec<String>,
+        changes: BTreeMap<String, Option<Account>>,
+    },
+    /// The height of the snapshot the store was bootstrapped from
+    SnapshotHeight(i64),
+    /// A block stored with the total work of its branch, ending the new best chain
+    BestBlock {
+        block: Block,
+        total_work: u128,
+        hashes: Vec<String>,
+        changes: BTreeMap<String, Option<Account>>,
+    },
+}
+
+impl FileStorage {
+    /// Open the file at the given path, creating it and its directory if needed, and reload it
+    pub fn open(path: impl AsRef<Path>) -> io::Result<FileStorage> {
+        let path = path.as_ref();
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let mut storage = FileStorage {
+            file,
+            store: MemoryStore::new(),
+        };
+        storage.load()?;
+        Ok(storage)
+    }
+
+    /// Replay every complete record of the file
+    fn load(&mut self) -> io::Result<()> {
+        let mut data = Vec::new();
+        (&self.file).read_to_end(&mut data)?;
+
+        let mut offset = 0;
+        while let Some(len_bytes) = data.get(offset..offset + mem::size_of::<u32>()) {
+            let len = u32::from_be_bytes(len_bytes.try_into().expect("Slice should have 4 bytes"));
+            let start = offset + len_bytes.len();
+            let Some(bytes) = data.get(start..start + len as usize) else {
+                break;
+            };
+
+            let record = Record::from_bytes(bytes).map_err(invalid_data)?;
+            self.replay(record)?;
+            offset = start + bytes.len();
+        }
+
+        // Drop the partial record left by an interrupted write, so the next records
+        // are appended after the last complete one
+        if offset < data.len() {
+            self.file.set_len(offset as u64)?;
+        }
+        Ok(())
+    }
+
+    fn replay(&mut self, record: Record) -> io::Result<()> {
+        match record {
+            Record::Block { block, total_work } => self.store.insert_block(&block, total_work),
+            Record::BestChain { hashes, changes } => {
+                let blocks = self.stored_blocks(&hashes)?;
+                self.store
+                    .set_best_chain(&blocks, changes.into_iter().collect());
+            }
+            Record::SnapshotHeight(height) => self.store.set_snapshot_height(height),
+            Record::BestBlock {
+                block,
+                total_work,
+                hashes,
+                changes,
+            } => {
+                self.store.insert_block(&block, total_work);
+                let blocks = self.stored_blocks(&hashes)?;
+                self.store
+                    .set_best_chain(&blocks, changes.into_iter().collect());
+            }
+        }
+        Ok(())
+    }
+
+    fn stored_blocks(&self, hashes: &[String]) -> io::Result<Vec<Block>> {
+        hashes
+            .iter()
+            .map(|hash| {
+                self.store
+                    .block_by_hash(hash)
+                    .ok_or_else(|| invalid_data(format!("unknown stored block {hash}")))
+            })
+            .collect()
+    }
+
+    fn append(&mut self, record: &Record) {
+        let data = record.to_bytes();
+        let len = u32::try_from(data.len()).expect("Stored record should fit in 4 GiB");
+
+        let mut bytes = Vec::with_capacity(mem::size_of::<u32>() + data.len());
+        bytes.extend_from_slice(&len.to_be_bytes());
+        bytes.extend_from_slice(&data);
+        self.file
+            .write_all(&bytes)
+            .and_then(|()| self.file.sync_data())
+            .expect("Chain store should be writable");
+    }
+}
+
+impl Accounts for FileStorage {
+    fn account(&self, account_id: &str) -> Option<Account> {
+        self.store.account(account_id)
+    }
+}
+
+impl ChainStore for FileStorage {
+    fn block_by_hash(&self, hash: &str) -> Option<Block> {
+        self.store.block_by_hash(hash)
+    }
+
+    fn block_by_height(&self, height: i64) -> Option<Block> {
+        self.store.block_by_height(height)
+    }
+
+    fn tip(&self) -> Option<Block> {
+        self.store.tip()
+    }
+
+    fn total_work(&self, hash: &str) -> Option<u128> {
+        self.store.total_work(hash)
+    }
+
+    fn accounts(&self) -> BTreeMap<String, Account> {
+        self.store.accounts()
+    }
+
+    fn snapshot_height(&self) -> Option<i64> {
+        self.store.snapshot_height()
+    }
+
+    fn set_snapshot_height(&mut self, height: i64) {
+        self.append(&Record::SnapshotHeight(height));
+        self.store.set_snapshot_height(height);
+    }
+
+    fn insert_block(&mut self, block: &Block, total_work: u128) {
+        self.append(&Record::Block {
+            block: block.clone(),
+            total_work,
+        });
+        self.store.insert_block(block, total_work);
+    }
+
+    fn set_best_chain(&mut self, blocks: &[Block], changes: AccountChanges) {
+        self.append(&Record::BestChain {
+            hashes: hashes(blocks),
+            changes: changes.clone().into_iter().collect(),
+        });
+        self.store.set_best_chain(blocks, changes);
+    }
+
+    fn insert_best_chain(&mut self, blocks: &[Block], total_work: u128, changes: AccountChanges) {
+        let Some(block) = blocks.last() else {
+            return self.set_best_chain(blocks, changes);
+        };
+        self.append(&Record::BestBlock {
+            block: block.clone(),
+            total_work,
+            hashes: hashes(blocks),
+            changes: changes.clone().into_iter().collect(),
+        });
+        self.store.insert_best_chain(blocks, total_work, changes);
+    }
+}
+
+impl Encode for Record {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        match self {
+            Record::Block { block, total_work } => {
+                0u8.encode(buffer);
+                block.encode(buffer);
+                total_work.encode(buffer);
+            }
+            Record::BestChain { hashes, changes } => {
+                1u8.encode(buffer);
+                hashes.encode(buffer);
+                changes.encode(buffer);
+            }
+            Record::SnapshotHeight(height) => {
+                2u8.encode(buffer);
+                height.encode(buffer);
+            }
+            Record::BestBlock {
+                block,
+                total_work,
+                hashes,
+                changes,
+            } => {
+                3u8.encode(buffer);
+                block.encode(buffer);
+                total_work.encode(buffer);
+                hashes.encode(buffer);
+                changes.encode(buffer);
+            }
+        }
+    }
+}
+
+impl Decode for Record {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        match u8::decode(decoder)? {
+            0 => Ok(Record::Block {
+                block: Block::decode(decoder)?,
+                total_work: u128::decode(decoder)?,
+            }),
+            1 => Ok(Record::BestChain {
+                hashes: Vec::decode(decoder)?,
+                changes: BTreeMap::decode(decoder)?,
+            }),
+            2 => Ok(Record::SnapshotHeight(i64::decode(decoder)?)),
+            3 => Ok(Record::BestBlock {
+                block: Block::decode(decoder)?,
+                total_work: u128::decode(decoder)?,
+                hashes: Vec::decode(decoder)?,
+                changes: BTreeMap::decode(decoder)?,
+            }),
+            variant => Err(DecodeError::InvalidVariant(variant)),
+        }
+    }
+}
+
+fn hashes(blocks: &[Block]) -> Vec<String> {
+    blocks.iter().map(|block| block.hash.clone()).collect()
+}
+
+fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        amount::Amount, blockchain::Blockchain, genesis::GenesisConfig, keys::test_utils::keypair,
+        store::test::check_store, transaction::Transaction,
+    };
+
+    fn path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("b-storage-{name}-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_file_storage() {
+        let path = path("store");
+        check_store(&mut FileStorage::open(&path).unwrap());
+
+        // The data is still there once reopened
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(storage.tip().unwrap().header.id, 2);
+        assert!(storage.block_by_height(0).is_some());
+        assert_eq!(storage.snapshot_height(), Some(2));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_tail() {
+        let path = path("truncated");
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let block_1 = Block::new(&genesis, vec![], 0);
+        let block_2 = Block::new(&block_1, vec![], 0);
+
+        let mut storage = FileStorage::open(&path).unwrap();
+        storage.insert_block(&genesis, 1);
+        storage.insert_block(&block_1, 2);
+        storage.set_best_chain(&[genesis.clone(), block_1.clone()], AccountChanges::new());
+        let len = fs::metadata(&path).unwrap().len();
+
+        // Partial length, then partial record of a new block ending the best chain
+        let data = Record::BestBlock {
+            block: block_2.clone(),
+            total_work: 3,
+            hashes: vec![block_2.hash.clone()],
+            changes: BTreeMap::new(),
+        }
+        .to_bytes();
+        let record = [&(data.len() as u32).to_be_bytes(), data.as_slice()].concat();
+        for partial in [2, record.len() - 1] {
+            let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+            file.write_all(&record[..partial]).unwrap();
+
+            let storage = FileStorage::open(&path).unwrap();
+            assert_eq!(storage.tip(), Some(block_1.clone()));
+            assert!(!storage.contains_block(&block_2.hash));
+            assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        }
+
+        // Records appended after the recovery are read back
+        let mut storage = FileStorage::open(&path).unwrap();
+        storage.insert_best_chain(std::slice::from_ref(&block_2), 3, AccountChanges::new());
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(storage.tip(), Some(block_2));
+        assert_eq!(storage.block_by_height(1), Some(block_1));
+        assert_eq!(storage.block_by_height(0), Some(genesis));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_interrupted_block() {
+        let path = path("interrupted");
+        let genesis = GenesisConfig::default();
+        let open = || Blockchain::open(&genesis, Box::new(FileStorage::open(&path).unwrap()));
+
+        let mut blockchain = open().unwrap();
+        blockchain
+            .add_block(vec![Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            )])
+            .unwrap();
+        let tip = blockchain.tip().clone();
+        let len = fs::metadata(&path).unwrap().len();
+        let block = blockchain.add_block(vec![]).unwrap().clone();
+        drop(blockchain);
+
+        // The new block and the best chain ending with it are lost together
+        let file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.set_len(fs::metadata(&path).unwrap().len() - 1)
+            .unwrap();
+        let mut blockchain = open().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        assert_eq!(blockchain.tip(), &tip);
+        assert_eq!(blockchain.block_by_hash(&block.hash), None);
+        assert_eq!(
+            blockchain.get_account_balance("account_1"),
+            Amount::new(100)
+        );
+
+        blockchain.append_block(block.clone()).unwrap();
+        drop(blockchain);
+        assert_eq!(open().unwrap().tip(), &block);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupted_record() {
+        let path = path("corrupted");
+        fs::write(&path, [0, 0, 0, 1, 9]).unwrap();
+        assert_eq!(
+            FileStorage::open(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        // A new best chain can only name stored blocks
+        let record = Record::BestChain {
+            hashes: vec!["unknown".to_string()],
+            changes: BTreeMap::new(),
+        }
+        .to_bytes();
+        fs::write(
+            &path,
+            [&(record.len() as u32).to_be_bytes(), record.as_slice()].concat(),
+        )
+        .unwrap();
+        assert_eq!(
+            FileStorage::open(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        fs::remove_file(path).unwrap();
+    }
+}
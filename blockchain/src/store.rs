@@ -0,0 +1,259 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use thiserror::Error;
+
+use crate::{
+    account_state::{Account, AccountChanges, AccountState, Accounts},
+    block::Block,
+    genesis::GenesisError,
+    validation::ChainError,
+};
+
+/// Represents where a blockchain keeps its blocks and the accounts of its best chain
+///
+/// Every valid block is stored with the total work of the branch it ends, including the blocks
+/// of competing branches. The best chain is the branch the blockchain follows, indexed by height.
+///
+/// A store that can't access its data panics: a node can't go on without its chain.
+pub trait ChainStore: Accounts + fmt::Debug + Send {
+    /// Get a block by hash, whether it is on the best chain or on a competing branch
+    fn block_by_hash(&self, hash: &str) -> Option<Block>;
+
+    /// Get the block of the best chain at the given height, which is its id in a valid chain
+    fn block_by_height(&self, height: i64) -> Option<Block>;
+
+    /// Get the last block of the best chain, `None` until the genesis block is stored
+    fn tip(&self) -> Option<Block>;
+
+    /// Get the total work of the branch ending with the given block
+    fn total_work(&self, hash: &str) -> Option<u128>;
+
+    /// Check if a block is stored
+    fn contains_block(&self, hash: &str) -> bool {
+        self.total_work(hash).is_some()
+    }
+
+    /// Get every account of the best chain, by id
+    fn accounts(&self) -> BTreeMap<String, Account>;
+
+    /// Get the height of the snapshot the store was bootstrapped from, `None` if it has every block
+    ///
+    /// The blocks of the best chain between the genesis block and the snapshot are missing.
+    fn snapshot_height(&self) -> Option<i64>;
+
+    /// Record that the store is bootstrapped from a snapshot at the given height
+    fn set_snapshot_height(&mut self, height: i64);
+
+    /// Store a valid block with the total work of its branch, without adding it to the best chain
+    fn insert_block(&mut self, block: &Block, total_work: u128);
+
+    /// Make the given stored blocks the end of the best chain, and apply the account changes
+    ///
+    /// The blocks replace the ones of the best chain from the id of the first one, and are
+    /// stored at the following heights in order, whatever their own ids. Both are written at
+    /// once, so the accounts always match the best chain.
+    fn set_best_chain(&mut self, blocks: &[Block], changes: AccountChanges);
+
+    /// Store the last of the given blocks with the total work of its branch, and make the blocks
+    /// the end of the best chain as `set_best_chain` does
+    ///
+    /// The other blocks must be stored already. Everything is written at once, so a new block is
+    /// never left stored out of the best chain it extends.
+    fn insert_best_chain(&mut self, blocks: &[Block], total_work: u128, changes: AccountChanges);
+}
+
+/// Represents the reason a blockchain can't be opened from its store
+#[derive(Debug, Error)]
+pub enum StoreError {
+    /// The genesis configuration is not valid
+    #[error("invalid genesis configuration: {0}")]
+    InvalidGenesis(#[from] GenesisError),
+    /// The stored chain was built from another genesis block
+    #[error("stored chain has another genesis block")]
+    GenesisMismatch,
+    /// The stored chain is not valid
+    #[error("stored chain is not valid: {0}")]
+    InvalidChain(#[from] ChainError),
+}
+
+/// Represents a store keeping everything in memory, lost when the node stops
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    blocks: HashMap<String, StoredBlock>,
+    /// Hashes of the blocks of the best chain, by height
+    best_chain: BTreeMap<i64, String>,
+    accounts: AccountState,
+    snapshot_height: Option<i64>,
+}
+
+#[derive(Debug)]
+struct StoredBlock {
+    block: Block,
+    total_work: u128,
+}
+
+impl MemoryStore {
+    /// Create an empty store
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl Accounts for MemoryStore {
+    fn account(&self, account_id: &str) -> Option<Account> {
+        self.accounts.account(account_id)
+    }
+}
+
+impl ChainStore for MemoryStore {
+    fn block_by_hash(&self, hash: &str) -> Option<Block> {
+        self.blocks.get(hash).map(|stored| stored.block.clone())
+    }
+
+    fn block_by_height(&self, height: i64) -> Option<Block> {
+        self.block_by_hash(self.best_chain.get(&height)?)
+    }
+
+    fn tip(&self) -> Option<Block> {
+        let (_, hash) = self.best_chain.last_key_value()?;
+        self.block_by_hash(hash)
+    }
+
+    fn total_work(&self, hash: &str) -> Option<u128> {
+        self.blocks.get(hash).map(|stored| stored.total_work)
+    }
+
+    fn accounts(&self) -> BTreeMap<String, Account> {
+        self.accounts.accounts().collect()
+    }
+
+    fn snapshot_height(&self) -> Option<i64> {
+        self.snapshot_height
+    }
+
+    fn set_snapshot_height(&mut self, height: i64) {
+        self.snapshot_height = Some(height);
+    }
+
+    fn insert_block(&mut self, block: &Block, total_work: u128) {
+        self.blocks.insert(
+            block.hash.clone(),
+            StoredBlock {
+                block: block.clone(),
+                total_work,
+            },
+        );
+    }
+
+    fn set_best_chain(&mut self, blocks: &[Block], changes: AccountChanges) {
+        if let Some(first) = blocks.first() {
+            self.best_chain.split_off(&first.header.id);
+            self.best_chain
+                .extend((first.header.id..).zip(blocks.iter().map(|block| block.hash.clone())));
+        }
+        self.accounts.apply_changes(changes);
+    }
+
+    fn insert_best_chain(&mut self, blocks: &[Block], total_work: u128, changes: AccountChanges) {
+        if let Some(block) = blocks.last() {
+            self.insert_block(block, total_work);
+        }
+        self.set_best_chain(blocks, changes);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::{
+        account_state::AccountOverlay, amount::Amount, genesis::GenesisConfig,
+        keys::test_utils::keypair, transaction::Transaction,
+    };
+
+    /// Check the behaviour every store must have
+    pub(crate) fn check_store(store: &mut dyn ChainStore) {
+        let genesis = Block::new_genesis(&GenesisConfig::default());
+        let block_a1 = Block::new(
+            &genesis,
+            vec![Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            )],
+            0,
+        );
+        let block_b1 = Block::new(&genesis, vec![], 0);
+        let block_b2 = Block::new(&block_b1, vec![], 0);
+        assert_eq!(store.tip(), None);
+
+        store.insert_block(&genesis, 1);
+        store.insert_block(&block_a1, 2);
+        let mut overlay = AccountOverlay::new(store);
+        overlay.apply_block(&block_a1);
+        let changes = overlay.into_changes();
+        store.set_best_chain(&[genesis.clone(), block_a1.clone()], changes);
+
+        assert_eq!(store.tip(), Some(block_a1.clone()));
+        assert_eq!(store.block_by_height(1), Some(block_a1.clone()));
+        assert_eq!(store.block_by_height(2), None);
+        assert_eq!(store.block_by_height(-1), None);
+        assert_eq!(store.balance("account_1"), Amount::new(100));
+        assert_eq!(
+            store.accounts().into_keys().collect::<Vec<_>>(),
+            vec!["account_1".to_string()]
+        );
+
+        // A competing branch is stored without joining the best chain
+        store.insert_block(&block_b1, 2);
+        assert_eq!(store.block_by_hash(&block_b1.hash), Some(block_b1.clone()));
+        assert_eq!(store.total_work(&block_b1.hash), Some(2));
+        assert!(store.contains_block(&block_b1.hash));
+        assert!(!store.contains_block("unknown"));
+        assert_eq!(store.tip(), Some(block_a1.clone()));
+
+        // Then replaces the blocks of the best chain
+        store.insert_block(&block_b2, 3);
+        let mut overlay = AccountOverlay::new(store);
+        overlay.revert_block(&block_a1);
+        let changes = overlay.into_changes();
+        store.set_best_chain(&[block_b1.clone(), block_b2.clone()], changes);
+
+        assert_eq!(store.tip(), Some(block_b2.clone()));
+        assert_eq!(store.block_by_height(1), Some(block_b1));
+        assert_eq!(store.block_by_hash(&block_a1.hash), Some(block_a1));
+        assert!(!store.account_exists("account_1"));
+        assert_eq!(store.block_by_height(0), Some(genesis));
+        assert!(store.accounts().is_empty());
+
+        // Blocks are stored at the heights following the first one, whatever their ids
+        let mut block_b3 = Block::new(&block_b2, vec![], 0);
+        block_b3.header.id = 5;
+        store.insert_block(&block_b3, 4);
+        store.set_best_chain(&[block_b2.clone(), block_b3.clone()], AccountChanges::new());
+        assert_eq!(store.block_by_height(3), Some(block_b3));
+        assert_eq!(store.block_by_height(5), None);
+        store.set_best_chain(std::slice::from_ref(&block_b2), AccountChanges::new());
+        assert_eq!(store.tip(), Some(block_b2.clone()));
+
+        // A new block is stored and joins the best chain at once
+        let block_b3 = Block::new(&block_b2, vec![], 0);
+        store.insert_best_chain(
+            &[block_b2.clone(), block_b3.clone()],
+            4,
+            AccountChanges::new(),
+        );
+        assert_eq!(store.tip(), Some(block_b3.clone()));
+        assert_eq!(store.total_work(&block_b3.hash), Some(4));
+        assert_eq!(store.block_by_height(2), Some(block_b2.clone()));
+        store.set_best_chain(std::slice::from_ref(&block_b2), AccountChanges::new());
+
+        assert_eq!(store.snapshot_height(), None);
+        store.set_snapshot_height(2);
+        assert_eq!(store.snapshot_height(), Some(2));
+    }
+
+    #[test]
+    fn test_memory_store() {
+        check_store(&mut MemoryStore::new());
+    }
+}
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+
+/// Represents who can fund the initial balance of the accounts created on a chain
+///
+/// With the open policy, every account creation mints its initial balance. With the
+/// restricted policy, the money supply only grows through the genesis allocations and the
+/// block rewards: new accounts start at zero unless one of the faucet accounts pays their
+/// initial balance, see `Transaction::funded_account_creation`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum SupplyPolicy {
+    /// Account creations mint their initial balance, or have it paid by any account
+    #[default]
+    Open,
+    /// Account creations start at zero, or have their initial balance paid by a faucet
+    Restricted {
+        /// Ids of the accounts allowed to fund account creations
+        #[serde(default)]
+        faucets: Vec<String>,
+    },
+}
+
+impl SupplyPolicy {
+    /// Check if account creations can mint their initial balance
+    pub fn allows_minting(&self) -> bool {
+        matches!(self, SupplyPolicy::Open)
+    }
+
+    /// Check if an account can pay the initial balance of the accounts it creates
+    pub fn is_faucet(&self, account_id: &str) -> bool {
+        match self {
+            SupplyPolicy::Open => true,
+            SupplyPolicy::Restricted { faucets } => {
+                faucets.iter().any(|faucet| faucet == account_id)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_supply_policy() {
+        let open = SupplyPolicy::Open;
+        assert!(open.allows_minting());
+        assert!(open.is_faucet("account_id"));
+
+        let restricted = SupplyPolicy::Restricted {
+            faucets: vec!["faucet".to_string()],
+        };
+        assert!(!restricted.allows_minting());
+        assert!(restricted.is_faucet("faucet"));
+        assert!(!restricted.is_faucet("account_id"));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        assert_eq!(
+            serde_json::from_str::<SupplyPolicy>(r#"{"mode":"open"}"#).unwrap(),
+            SupplyPolicy::Open
+        );
+        assert_eq!(
+            serde_json::from_str::<SupplyPolicy>(r#"{"mode":"restricted","faucets":["faucet"]}"#)
+                .unwrap(),
+            SupplyPolicy::Restricted {
+                faucets: vec!["faucet".to_string()]
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<SupplyPolicy>(r#"{"mode":"restricted"}"#).unwrap(),
+            SupplyPolicy::Restricted { faucets: vec![] }
+        );
+    }
+}
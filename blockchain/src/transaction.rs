@@ -1,66 +1,293 @@
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    amount::Amount,
+    encoding::Encode,
+    keys::{self, Keypair},
+};
 
 /// Represents an account creation transaction
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// The account is bound to the public key, whose private key signs the transfers from the account.
+/// Its initial balance is minted, unless a faucet account pays it, see `SupplyPolicy`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AccountCreation {
     pub id: String,
     pub created_at: i64,
     pub account_id: String,
-    pub initial_balance: i64,
+    pub initial_balance: Amount,
+    pub public_key: String,
+    /// Account paying the initial balance, `None` when it is minted
+    pub faucet: Option<String>,
+    /// Signature of the network id and the other fields by the private key of the faucet, empty
+    /// without a faucet
+    pub signature: String,
 }
 
 // Represents a transfer transaction
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Transfer {
     pub id: String,
     pub created_at: i64,
     pub from_account: String,
     pub to_account: String,
-    pub amount: i64,
+    pub amount: Amount,
+    /// Fee paid by `from_account` on top of the amount, credited to the producer of the block
+    /// including the transfer
+    pub fee: Amount,
+    /// Sequence number of the transfer among the ones sent by `from_account`, starting at 0
+    pub nonce: u64,
+    /// Signature of the network id and the other fields by the private key of `from_account`
+    pub signature: String,
+}
+
+/// Represents the transaction minting the reward of a block, credited to the block producer
+///
+/// It is the first transaction of every block of a chain with a block reward, and can't be
+/// submitted on its own. Like the fees, the reward is burned when the block has no producer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Coinbase {
+    pub id: String,
+    pub created_at: i64,
+    pub amount: Amount,
 }
 
 /// Represents a transaction that can be added to a block
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Transaction {
     AccountCreation(AccountCreation),
     Transfer(Transfer),
+    Coinbase(Coinbase),
 }
 
 impl Transaction {
-    /// Create a new account creation transaction
-    pub fn account_creation(account_id: String, initial_balance: i64) -> Transaction {
+    /// Create a new account creation transaction, binding the account to the public key
+    pub fn account_creation(
+        account_id: String,
+        initial_balance: Amount,
+        public_key: String,
+    ) -> Transaction {
         Transaction::AccountCreation(AccountCreation {
             id: uuid::Uuid::new_v4().to_string(),
             created_at: chrono::Utc::now().timestamp_millis(),
             account_id,
             initial_balance,
+            public_key,
+            faucet: None,
+            signature: String::new(),
         })
     }
 
-    /// Create a new transfer transaction
-    pub fn transfer(from_account: String, to_account: String, amount: i64) -> Transaction {
-        Transaction::Transfer(Transfer {
+    /// Create a new account creation transaction whose initial balance is paid by a faucet
+    /// account, signed by the key pair of the faucet for the given network
+    pub fn funded_account_creation(
+        network_id: &str,
+        account_id: String,
+        initial_balance: Amount,
+        public_key: String,
+        faucet: String,
+        keypair: &Keypair,
+    ) -> Transaction {
+        let mut account_creation = AccountCreation {
+            id: uuid::Uuid::new_v4().to_string(),
+            created_at: chrono::Utc::now().timestamp_millis(),
+            account_id,
+            initial_balance,
+            public_key,
+            faucet: Some(faucet),
+            signature: String::new(),
+        };
+        account_creation.signature = keypair.sign(&account_creation.signed_data(network_id));
+
+        Transaction::AccountCreation(account_creation)
+    }
+
+    /// Create a new transfer transaction, signed by the key pair of the sender for the given
+    /// network, see `Blockchain::network_id`
+    ///
+    /// The nonce must be the next one of the sender, see `AccountState::next_nonce`.
+    /// The fee is paid by the sender on top of the amount: transactions paying higher fees
+    /// are included first.
+    pub fn transfer(
+        network_id: &str,
+        from_account: String,
+        to_account: String,
+        amount: Amount,
+        fee: Amount,
+        nonce: u64,
+        keypair: &Keypair,
+    ) -> Transaction {
+        let mut transfer = Transfer {
             id: uuid::Uuid::new_v4().to_string(),
             created_at: chrono::Utc::now().timestamp_millis(),
             from_account,
             to_account,
             amount,
+            fee,
+            nonce,
+            signature: String::new(),
+        };
+        transfer.signature = keypair.sign(&transfer.signed_data(network_id));
+
+        Transaction::Transfer(transfer)
+    }
+
+    /// Create a new coinbase minting the reward of a block, see `BlockReward::reward`
+    pub fn coinbase(amount: Amount) -> Transaction {
+        Transaction::Coinbase(Coinbase {
+            id: uuid::Uuid::new_v4().to_string(),
+            created_at: chrono::Utc::now().timestamp_millis(),
+            amount,
         })
     }
+
+    /// Calculate the hash of the transaction as a string, used as a leaf of the block Merkle tree
+    ///
+    /// It is the SHA-256 of the canonical encoding of the transaction, see the `encoding` module.
+    pub fn hash(&self) -> String {
+        hex::encode(Sha256::digest(self.to_bytes()))
+    }
+
+    /// Get the id of the transaction
+    pub fn id(&self) -> &str {
+        match self {
+            Transaction::AccountCreation(account_creation) => &account_creation.id,
+            Transaction::Transfer(transfer) => &transfer.id,
+            Transaction::Coinbase(coinbase) => &coinbase.id,
+        }
+    }
+
+    /// Get the creation time of the transaction, in milliseconds since the Unix epoch
+    pub fn created_at(&self) -> i64 {
+        match self {
+            Transaction::AccountCreation(account_creation) => account_creation.created_at,
+            Transaction::Transfer(transfer) => transfer.created_at,
+            Transaction::Coinbase(coinbase) => coinbase.created_at,
+        }
+    }
+
+    /// Get the fee paid by the transaction, account creations and coinbases are free
+    pub fn fee(&self) -> Amount {
+        match self {
+            Transaction::AccountCreation(_) | Transaction::Coinbase(_) => Amount::ZERO,
+            Transaction::Transfer(transfer) => transfer.fee,
+        }
+    }
+
+    /// Get the ids of the accounts whose balance the transaction changes
+    ///
+    /// A coinbase credits the producer of its block, which it doesn't name.
+    pub fn account_ids(&self) -> Vec<&str> {
+        match self {
+            Transaction::AccountCreation(account_creation) => match &account_creation.faucet {
+                Some(faucet) => vec![faucet, &account_creation.account_id],
+                None => vec![&account_creation.account_id],
+            },
+            Transaction::Transfer(transfer) => vec![&transfer.from_account, &transfer.to_account],
+            Transaction::Coinbase(_) => vec![],
+        }
+    }
+}
+
+impl AccountCreation {
+    /// Get the data covered by the signature: the network id, followed by the canonical encoding
+    /// of the account creation without its signature
+    pub fn signed_data(&self, network_id: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        network_id.encode(&mut data);
+        self.id.encode(&mut data);
+        self.created_at.encode(&mut data);
+        self.account_id.encode(&mut data);
+        self.initial_balance.encode(&mut data);
+        self.public_key.encode(&mut data);
+        self.faucet.encode(&mut data);
+        data
+    }
+
+    /// Check the signature against the public key of the faucet, on the given network
+    pub fn verify_signature(&self, network_id: &str, public_key: &str) -> bool {
+        keys::verify(public_key, &self.signed_data(network_id), &self.signature)
+    }
+}
+
+impl Transfer {
+    /// Get the data covered by the signature: the network id, followed by the canonical encoding
+    /// of the transfer without its signature
+    ///
+    /// Signing the network id keeps a transfer from being replayed on another chain where the
+    /// sender has the same key and nonce.
+    pub fn signed_data(&self, network_id: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        network_id.encode(&mut data);
+        self.id.encode(&mut data);
+        self.created_at.encode(&mut data);
+        self.from_account.encode(&mut data);
+        self.to_account.encode(&mut data);
+        self.amount.encode(&mut data);
+        self.fee.encode(&mut data);
+        self.nonce.encode(&mut data);
+        data
+    }
+
+    /// Check the signature against the public key of the sender, on the given network
+    pub fn verify_signature(&self, network_id: &str, public_key: &str) -> bool {
+        keys::verify(public_key, &self.signed_data(network_id), &self.signature)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::keys::test_utils::network_id;
 
     #[test]
     fn test_account_creation() {
-        let transaction = Transaction::account_creation("account_id".to_string(), 100);
+        let keypair = Keypair::generate();
+        let transaction = Transaction::account_creation(
+            "account_id".to_string(),
+            Amount::new(100),
+            keypair.public_key(),
+        );
 
         match transaction {
             Transaction::AccountCreation(account_creation) => {
                 assert_eq!(account_creation.account_id, "account_id");
-                assert_eq!(account_creation.initial_balance, 100);
+                assert_eq!(account_creation.initial_balance, Amount::new(100));
+                assert_eq!(account_creation.public_key, keypair.public_key());
+            }
+            _ => panic!("Invalid transaction type"),
+        }
+    }
+
+    #[test]
+    fn test_funded_account_creation() {
+        let keypair = Keypair::generate();
+        let faucet_keypair = Keypair::generate();
+        let transaction = Transaction::funded_account_creation(
+            &network_id(),
+            "account_id".to_string(),
+            Amount::new(100),
+            keypair.public_key(),
+            "faucet".to_string(),
+            &faucet_keypair,
+        );
+
+        match transaction {
+            Transaction::AccountCreation(mut account_creation) => {
+                assert_eq!(account_creation.faucet.as_deref(), Some("faucet"));
+                assert!(
+                    account_creation.verify_signature(&network_id(), &faucet_keypair.public_key())
+                );
+                assert!(!account_creation.verify_signature(&network_id(), &keypair.public_key()));
+                assert!(!account_creation
+                    .verify_signature("other_network", &faucet_keypair.public_key()));
+
+                // Any change invalidates the signature
+                account_creation.initial_balance = Amount::new(1_000);
+                assert!(
+                    !account_creation.verify_signature(&network_id(), &faucet_keypair.public_key())
+                );
             }
             _ => panic!("Invalid transaction type"),
         }
@@ -68,16 +295,88 @@ mod test {
 
     #[test]
     fn test_transfer() {
-        let transaction =
-            Transaction::transfer("from_account".to_string(), "to_account".to_string(), 100);
+        let keypair = Keypair::generate();
+        let transaction = Transaction::transfer(
+            &network_id(),
+            "from_account".to_string(),
+            "to_account".to_string(),
+            Amount::new(100),
+            Amount::ZERO,
+            0,
+            &keypair,
+        );
 
         match transaction {
-            Transaction::Transfer(transfer) => {
+            Transaction::Transfer(mut transfer) => {
                 assert_eq!(transfer.from_account, "from_account");
                 assert_eq!(transfer.to_account, "to_account");
-                assert_eq!(transfer.amount, 100);
+                assert_eq!(transfer.amount, Amount::new(100));
+                assert!(transfer.verify_signature(&network_id(), &keypair.public_key()));
+                assert!(
+                    !transfer.verify_signature(&network_id(), &Keypair::generate().public_key())
+                );
+                assert!(!transfer.verify_signature("other_network", &keypair.public_key()));
+
+                // Any change invalidates the signature
+                transfer.amount = Amount::new(1_000);
+                assert!(!transfer.verify_signature(&network_id(), &keypair.public_key()));
+            }
+            _ => panic!("Invalid transaction type"),
+        }
+    }
+
+    #[test]
+    fn test_id() {
+        let transaction = Transaction::account_creation(
+            "account_id".to_string(),
+            Amount::new(100),
+            Keypair::generate().public_key(),
+        );
+
+        match &transaction {
+            Transaction::AccountCreation(account_creation) => {
+                assert_eq!(transaction.id(), account_creation.id);
             }
             _ => panic!("Invalid transaction type"),
         }
     }
+
+    #[test]
+    fn test_account_ids() {
+        let keypair = Keypair::generate();
+        let transaction = Transaction::account_creation(
+            "account_id".to_string(),
+            Amount::new(100),
+            keypair.public_key(),
+        );
+        assert_eq!(transaction.account_ids(), vec!["account_id"]);
+
+        let transaction = Transaction::transfer(
+            &network_id(),
+            "from_account".to_string(),
+            "to_account".to_string(),
+            Amount::new(100),
+            Amount::ZERO,
+            0,
+            &keypair,
+        );
+        assert_eq!(
+            transaction.account_ids(),
+            vec!["from_account", "to_account"]
+        );
+
+        let transaction = Transaction::funded_account_creation(
+            &network_id(),
+            "account_id".to_string(),
+            Amount::new(100),
+            keypair.public_key(),
+            "faucet".to_string(),
+            &keypair,
+        );
+        assert_eq!(transaction.account_ids(), vec!["faucet", "account_id"]);
+
+        assert!(Transaction::coinbase(Amount::new(50))
+            .account_ids()
+            .is_empty());
+    }
 }
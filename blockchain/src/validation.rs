@@ -0,0 +1,961 @@
+use std::collections::HashMap;
+use thiserror::Error;
+
+use crate::{
+    account_state::Accounts,
+    amount::{Amount, AmountError},
+    keys,
+    supply::SupplyPolicy,
+    transaction::Transaction,
+};
+
+/// Policy of the validators created without one, see `TransactionValidator::new`
+static OPEN_SUPPLY: SupplyPolicy = SupplyPolicy::Open;
+
+/// Represents the rule a transaction breaks
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TransactionError {
+    /// An account with the same id was already created
+    #[error("account '{0}' already exists")]
+    AccountAlreadyExists(String),
+    /// The account used by a transfer was never created
+    #[error("account '{0}' does not exist")]
+    AccountNotFound(String),
+    /// An account must be bound to a valid Ed25519 public key
+    #[error("account '{0}' has an invalid public key")]
+    InvalidPublicKey(String),
+    /// A transfer must be signed by the private key of its sender, and an account creation
+    /// funded by a faucet by the private key of the faucet
+    #[error("transaction {0} is not signed by the key of the paying account")]
+    InvalidSignature(String),
+    /// An account creation can only mint its initial balance on a chain with an open supply
+    #[error(
+        "account '{account_id}' can't be created with a balance of {initial_balance} without a faucet paying it"
+    )]
+    UnfundedInitialBalance {
+        account_id: String,
+        initial_balance: Amount,
+    },
+    /// The account paying for an account creation is not one of the faucets of the chain
+    #[error("account '{0}' is not an authorized faucet")]
+    UnauthorizedFaucet(String),
+    /// A transfer must have the next nonce of its sender, it can't reuse or skip one
+    #[error("transfer from account '{account_id}' has nonce {nonce}, expected {expected}")]
+    InvalidNonce {
+        account_id: String,
+        nonce: u64,
+        expected: u64,
+    },
+    /// A transfer must move a strictly positive amount
+    #[error("transfer amount is zero")]
+    ZeroAmount,
+    /// A transfer can't send funds to its sender
+    #[error("account '{0}' can't transfer funds to itself")]
+    SelfTransfer(String),
+    /// The sender doesn't have enough funds for the amount and the fee of a transfer, or the
+    /// faucet for the initial balance of an account creation
+    #[error(
+        "account '{account_id}' has a balance of {balance}, which is not enough to transfer {amount} with a fee of {fee}"
+    )]
+    InsufficientFunds {
+        account_id: String,
+        balance: Amount,
+        amount: Amount,
+        fee: Amount,
+    },
+    /// The balance of the recipient of a transfer can't exceed the maximum amount
+    #[error("balance of account '{0}' would overflow")]
+    BalanceOverflow(String),
+    /// A coinbase is only valid as the first transaction of a block, on a chain with a reward
+    #[error("coinbase {0} is not the first transaction of a block with a reward")]
+    UnexpectedCoinbase(String),
+}
+
+/// Represents the rule a block breaks against the block it follows
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BlockRuleError {
+    /// The block id must be the one of the previous block plus one
+    #[error("id is {id}, expected {expected}")]
+    IdMismatch { id: i64, expected: i64 },
+    /// The block must reference the hash of the previous block
+    #[error("previous hash is {previous_hash}, expected {expected}")]
+    PreviousHashMismatch {
+        previous_hash: String,
+        expected: String,
+    },
+    /// The block hash must be the hash of its header
+    #[error("hash is {hash}, expected {expected}")]
+    HashMismatch { hash: String, expected: String },
+    /// The Merkle root of the header must commit to the block transactions
+    #[error("merkle root is {merkle_root}, expected {expected}")]
+    MerkleRootMismatch {
+        merkle_root: String,
+        expected: String,
+    },
+    /// The block hash must have as many leading zero bits as its difficulty
+    #[error("hash does not meet difficulty {0}")]
+    InsufficientWork(u32),
+    /// The block must be created after the previous block
+    #[error("created at {created_at}, not after the previous block created at {previous}")]
+    TimestampNotIncreasing { created_at: i64, previous: i64 },
+    /// The block can't be created too far past the current time, see `MAX_FUTURE_DRIFT`
+    #[error("created at {created_at}, after the latest accepted time {latest}")]
+    TimestampInFuture { created_at: i64, latest: i64 },
+    /// The block can't encode to more than `MAX_BLOCK_SIZE` bytes
+    #[error("size is {size} bytes, more than the maximum of {max}")]
+    TooLarge { size: usize, max: usize },
+}
+
+/// Represents the reason a block can't be appended to a blockchain
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BlockError {
+    /// The block doesn't follow its previous block, or its hash is wrong
+    #[error("block #{block_id} does not extend the blockchain: {rule}")]
+    InvalidBlock { block_id: i64, rule: BlockRuleError },
+    /// The block is already in the blockchain, or in one of its branches
+    #[error("block #{block_id} is already known")]
+    AlreadyKnown { block_id: i64 },
+    /// The previous block is unknown, it must be downloaded first
+    #[error("block #{block_id} follows an unknown block")]
+    UnknownParent { block_id: i64 },
+    /// The block was mined with another difficulty than the one the chain expects
+    #[error("block #{block_id} has a difficulty of {difficulty}, expected {expected}")]
+    InvalidDifficulty {
+        block_id: i64,
+        difficulty: u32,
+        expected: u32,
+    },
+    /// A block of a chain with a reward must start with a coinbase
+    #[error("block #{block_id} does not start with a coinbase")]
+    MissingCoinbase { block_id: i64 },
+    /// The coinbase must mint the reward of the block height, see `BlockReward::reward`
+    #[error("block #{block_id} mints a reward of {reward}, expected {expected}")]
+    InvalidReward {
+        block_id: i64,
+        reward: Amount,
+        expected: Amount,
+    },
+    /// The producer credited with the fees is not an account once the transactions are applied
+    #[error("block #{block_id} is produced by account '{producer}', which does not exist")]
+    UnknownProducer { block_id: i64, producer: String },
+    /// The fees and the reward of the block can't be credited without overflowing the balance
+    /// of the producer
+    #[error("block #{block_id} overflows the balance of its producer '{producer}'")]
+    ProducerBalanceOverflow { block_id: i64, producer: String },
+    /// One of the block transactions can't be applied
+    #[error("block #{block_id} contains an invalid transaction: {error}")]
+    InvalidTransaction {
+        block_id: i64,
+        error: TransactionError,
+    },
+}
+
+/// Represents the first problem found when validating a whole blockchain
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ChainError {
+    /// The first block is not the genesis block of the network
+    #[error("genesis block hash is {hash}, expected the network id {network_id}")]
+    GenesisMismatch { hash: String, network_id: String },
+    /// A block of the best chain is not stored
+    #[error("block #{block_id} is missing")]
+    MissingBlock { block_id: i64 },
+    /// A block of the best chain doesn't follow the previous one
+    #[error(transparent)]
+    InvalidBlock(#[from] BlockError),
+    /// The last block of the best chain is not its tip
+    #[error("best chain ends with block {hash}, expected the tip {tip}")]
+    TipMismatch { hash: String, tip: String },
+    /// A stored account is not the one obtained by replaying the best chain
+    #[error("stored account '{account_id}' does not match the blocks of the best chain")]
+    AccountMismatch { account_id: String },
+}
+
+/// Validates transactions against the state of the accounts at a block
+///
+/// The effects of every accepted transaction are recorded, so a sequence of transactions
+/// (a transaction pool, or the content of a new block) is checked as if it was applied in order.
+pub struct TransactionValidator<'a> {
+    /// Network the signatures are checked for
+    network_id: &'a str,
+    accounts: &'a dyn Accounts,
+    supply_policy: &'a SupplyPolicy,
+    /// Public keys of the accounts created by accepted transactions
+    created_accounts: HashMap<String, String>,
+    /// Balances of the accounts changed by accepted transactions
+    balances: HashMap<String, Amount>,
+    /// Number of accepted transfers sent by every account
+    sent_transfers: HashMap<String, u64>,
+}
+
+impl<'a> TransactionValidator<'a> {
+    /// Create a validator starting from the given accounts of the network, on a chain with an
+    /// open supply
+    pub fn new(network_id: &'a str, accounts: &'a dyn Accounts) -> Self {
+        TransactionValidator::with_supply_policy(network_id, accounts, &OPEN_SUPPLY)
+    }
+
+    /// Create a validator starting from the given accounts of the network, enforcing the supply
+    /// policy of the chain on the account creations
+    pub fn with_supply_policy(
+        network_id: &'a str,
+        accounts: &'a dyn Accounts,
+        supply_policy: &'a SupplyPolicy,
+    ) -> Self {
+        TransactionValidator {
+            network_id,
+            accounts,
+            supply_policy,
+            created_accounts: HashMap::new(),
+            balances: HashMap::new(),
+            sent_transfers: HashMap::new(),
+        }
+    }
+
+    /// Check a transaction, and record its effects if it is valid
+    ///
+    /// Coinbases are always refused: they are checked by the blockchain with the block they start.
+    pub fn validate(&mut self, transaction: &Transaction) -> Result<(), TransactionError> {
+        match transaction {
+            Transaction::AccountCreation(account_creation) => {
+                if self.account_exists(&account_creation.account_id) {
+                    return Err(TransactionError::AccountAlreadyExists(
+                        account_creation.account_id.clone(),
+                    ));
+                }
+                if !keys::is_valid_public_key(&account_creation.public_key) {
+                    return Err(TransactionError::InvalidPublicKey(
+                        account_creation.account_id.clone(),
+                    ));
+                }
+                match &account_creation.faucet {
+                    None if !account_creation.initial_balance.is_zero()
+                        && !self.supply_policy.allows_minting() =>
+                    {
+                        return Err(TransactionError::UnfundedInitialBalance {
+                            account_id: account_creation.account_id.clone(),
+                            initial_balance: account_creation.initial_balance,
+                        });
+                    }
+                    None => {}
+                    Some(faucet) => {
+                        if !self.supply_policy.is_faucet(faucet) {
+                            return Err(TransactionError::UnauthorizedFaucet(faucet.clone()));
+                        }
+                        if !self.account_exists(faucet) {
+                            return Err(TransactionError::AccountNotFound(faucet.clone()));
+                        }
+                        let public_key = self
+                            .public_key(faucet)
+                            .expect("Existing account should have a public key");
+                        if !account_creation.verify_signature(self.network_id, &public_key) {
+                            return Err(TransactionError::InvalidSignature(
+                                account_creation.id.clone(),
+                            ));
+                        }
+                        let balance = self.balance(faucet);
+                        let remaining = balance
+                            .checked_sub(account_creation.initial_balance)
+                            .map_err(|_| TransactionError::InsufficientFunds {
+                                account_id: faucet.clone(),
+                                balance,
+                                amount: account_creation.initial_balance,
+                                fee: Amount::ZERO,
+                            })?;
+
+                        self.balances.insert(faucet.clone(), remaining);
+                    }
+                }
+
+                self.created_accounts.insert(
+                    account_creation.account_id.clone(),
+                    account_creation.public_key.clone(),
+                );
+                self.balances.insert(
+                    account_creation.account_id.clone(),
+                    account_creation.initial_balance,
+                );
+            }
+            Transaction::Transfer(transfer) => {
+                if transfer.amount.is_zero() {
+                    return Err(TransactionError::ZeroAmount);
+                }
+                if transfer.from_account == transfer.to_account {
+                    return Err(TransactionError::SelfTransfer(
+                        transfer.from_account.clone(),
+                    ));
+                }
+                for account_id in [&transfer.from_account, &transfer.to_account] {
+                    if !self.account_exists(account_id) {
+                        return Err(TransactionError::AccountNotFound(account_id.clone()));
+                    }
+                }
+                let public_key = self
+                    .public_key(&transfer.from_account)
+                    .expect("Existing account should have a public key");
+                if !transfer.verify_signature(self.network_id, &public_key) {
+                    return Err(TransactionError::InvalidSignature(transfer.id.clone()));
+                }
+                let expected = self.next_nonce(&transfer.from_account);
+                if transfer.nonce != expected {
+                    return Err(TransactionError::InvalidNonce {
+                        account_id: transfer.from_account.clone(),
+                        nonce: transfer.nonce,
+                        expected,
+                    });
+                }
+
+                let balance = self.balance(&transfer.from_account);
+                let remaining = transfer
+                    .amount
+                    .checked_add(transfer.fee)
+                    .and_then(|total| balance.checked_sub(total))
+                    .map_err(|_| TransactionError::InsufficientFunds {
+                        account_id: transfer.from_account.clone(),
+                        balance,
+                        amount: transfer.amount,
+                        fee: transfer.fee,
+                    })?;
+                let received = self
+                    .balance(&transfer.to_account)
+                    .checked_add(transfer.amount)
+                    .map_err(|_| TransactionError::BalanceOverflow(transfer.to_account.clone()))?;
+
+                *self
+                    .sent_transfers
+                    .entry(transfer.from_account.clone())
+                    .or_default() += 1;
+                self.balances
+                    .insert(transfer.from_account.clone(), remaining);
+                self.balances.insert(transfer.to_account.clone(), received);
+            }
+            Transaction::Coinbase(coinbase) => {
+                return Err(TransactionError::UnexpectedCoinbase(coinbase.id.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Credit an account outside of a transaction, like the producer of a block with its fees
+    pub fn credit(&mut self, account_id: &str, amount: Amount) -> Result<(), AmountError> {
+        let balance = self.balance(account_id).checked_add(amount)?;
+        self.balances.insert(account_id.to_string(), balance);
+        Ok(())
+    }
+
+    /// Check if an account exists, including the ones created by accepted transactions
+    pub fn account_exists(&self, account_id: &str) -> bool {
+        self.created_accounts.contains_key(account_id) || self.accounts.account_exists(account_id)
+    }
+
+    /// Get the public key of an account, including the ones created by accepted transactions
+    fn public_key(&self, account_id: &str) -> Option<String> {
+        self.created_accounts
+            .get(account_id)
+            .cloned()
+            .or_else(|| self.accounts.public_key(account_id))
+    }
+
+    /// Get the nonce the next transfer of an account must have, including accepted transactions
+    pub fn next_nonce(&self, account_id: &str) -> u64 {
+        self.accounts.next_nonce(account_id)
+            + self.sent_transfers.get(account_id).copied().unwrap_or(0)
+    }
+
+    /// Get the balance of an account, including the effects of accepted transactions
+    fn balance(&self, account_id: &str) -> Amount {
+        self.balances
+            .get(account_id)
+            .copied()
+            .unwrap_or_else(|| self.accounts.balance(account_id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::keys::test_utils::{keypair, network_id};
+    use proptest::prelude::*;
+
+    fn blockchain() -> Blockchain {
+        let mut blockchain = Blockchain::default();
+        blockchain
+            .add_block(vec![
+                Transaction::account_creation(
+                    "account_1".to_string(),
+                    Amount::new(100),
+                    keypair("account_1").public_key(),
+                ),
+                Transaction::account_creation(
+                    "account_2".to_string(),
+                    Amount::new(500),
+                    keypair("account_2").public_key(),
+                ),
+            ])
+            .unwrap();
+        blockchain
+    }
+
+    #[test]
+    fn test_account_creation() {
+        let blockchain = blockchain();
+        let mut validator =
+            TransactionValidator::new(blockchain.network_id(), blockchain.account_state());
+
+        // Valid
+        assert_eq!(
+            validator.validate(&Transaction::account_creation(
+                "account_3".to_string(),
+                Amount::ZERO,
+                keypair("account_3").public_key()
+            )),
+            Ok(())
+        );
+
+        // Account created in the blockchain
+        assert_eq!(
+            validator.validate(&Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(10),
+                keypair("account_1").public_key()
+            )),
+            Err(TransactionError::AccountAlreadyExists(
+                "account_1".to_string()
+            ))
+        );
+
+        // Account created by an accepted transaction
+        assert_eq!(
+            validator.validate(&Transaction::account_creation(
+                "account_3".to_string(),
+                Amount::new(10),
+                keypair("account_3").public_key()
+            )),
+            Err(TransactionError::AccountAlreadyExists(
+                "account_3".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_funded_account_creation() {
+        let blockchain = blockchain();
+        let supply_policy = SupplyPolicy::Restricted {
+            faucets: vec!["account_1".to_string(), "account_3".to_string()],
+        };
+        let mut validator = TransactionValidator::with_supply_policy(
+            blockchain.network_id(),
+            blockchain.account_state(),
+            &supply_policy,
+        );
+        let funded_account_creation = |account_id: &str, initial_balance, faucet: &str| {
+            Transaction::funded_account_creation(
+                &network_id(),
+                account_id.to_string(),
+                Amount::new(initial_balance),
+                keypair(account_id).public_key(),
+                faucet.to_string(),
+                &keypair(faucet),
+            )
+        };
+
+        // Valid
+        assert_eq!(
+            validator.validate(&funded_account_creation("account_4", 60, "account_1")),
+            Ok(())
+        );
+
+        // Balance of the faucet reduced by the accepted creation
+        assert_eq!(
+            validator.validate(&funded_account_creation("account_5", 60, "account_1")),
+            Err(TransactionError::InsufficientFunds {
+                account_id: "account_1".to_string(),
+                balance: Amount::new(40),
+                amount: Amount::new(60),
+                fee: Amount::new(0)
+            })
+        );
+
+        // Initial balance without a faucet
+        assert_eq!(
+            validator.validate(&Transaction::account_creation(
+                "account_5".to_string(),
+                Amount::new(10),
+                keypair("account_5").public_key()
+            )),
+            Err(TransactionError::UnfundedInitialBalance {
+                account_id: "account_5".to_string(),
+                initial_balance: Amount::new(10)
+            })
+        );
+
+        // Not a faucet of the chain
+        assert_eq!(
+            validator.validate(&funded_account_creation("account_5", 10, "account_2")),
+            Err(TransactionError::UnauthorizedFaucet(
+                "account_2".to_string()
+            ))
+        );
+
+        // Faucet that was never created
+        assert_eq!(
+            validator.validate(&funded_account_creation("account_5", 10, "account_3")),
+            Err(TransactionError::AccountNotFound("account_3".to_string()))
+        );
+
+        // Not signed by the faucet
+        let transaction = Transaction::funded_account_creation(
+            &network_id(),
+            "account_5".to_string(),
+            Amount::new(10),
+            keypair("account_5").public_key(),
+            "account_1".to_string(),
+            &keypair("account_2"),
+        );
+        assert_eq!(
+            validator.validate(&transaction),
+            Err(TransactionError::InvalidSignature(
+                transaction.id().to_string()
+            ))
+        );
+
+        // Zero balance accounts need no faucet
+        assert_eq!(
+            validator.validate(&Transaction::account_creation(
+                "account_5".to_string(),
+                Amount::ZERO,
+                keypair("account_5").public_key()
+            )),
+            Ok(())
+        );
+
+        // Any account can fund account creations on a chain with an open supply
+        let mut validator =
+            TransactionValidator::new(blockchain.network_id(), blockchain.account_state());
+        assert_eq!(
+            validator.validate(&funded_account_creation("account_4", 60, "account_2")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_transfer() {
+        let blockchain = blockchain();
+        let mut validator =
+            TransactionValidator::new(blockchain.network_id(), blockchain.account_state());
+
+        // Valid
+        assert_eq!(
+            validator.validate(&Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(60),
+                Amount::ZERO,
+                0,
+                &keypair("account_1")
+            )),
+            Ok(())
+        );
+
+        // Balance reduced by the accepted transfer
+        assert_eq!(
+            validator.validate(&Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(60),
+                Amount::ZERO,
+                1,
+                &keypair("account_1")
+            )),
+            Err(TransactionError::InsufficientFunds {
+                account_id: "account_1".to_string(),
+                balance: Amount::new(40),
+                amount: Amount::new(60),
+                fee: Amount::new(0)
+            })
+        );
+
+        // The fee is paid on top of the amount
+        assert_eq!(
+            validator.validate(&Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(30),
+                Amount::new(20),
+                1,
+                &keypair("account_1")
+            )),
+            Err(TransactionError::InsufficientFunds {
+                account_id: "account_1".to_string(),
+                balance: Amount::new(40),
+                amount: Amount::new(30),
+                fee: Amount::new(20)
+            })
+        );
+        assert_eq!(
+            validator.validate(&Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(30),
+                Amount::MAX,
+                1,
+                &keypair("account_1")
+            )),
+            Err(TransactionError::InsufficientFunds {
+                account_id: "account_1".to_string(),
+                balance: Amount::new(40),
+                amount: Amount::new(30),
+                fee: Amount::MAX
+            })
+        );
+
+        // Unknown accounts
+        assert_eq!(
+            validator.validate(&Transaction::transfer(
+                &network_id(),
+                "unknown".to_string(),
+                "account_2".to_string(),
+                Amount::new(10),
+                Amount::ZERO,
+                0,
+                &keypair("unknown")
+            )),
+            Err(TransactionError::AccountNotFound("unknown".to_string()))
+        );
+        assert_eq!(
+            validator.validate(&Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "unknown".to_string(),
+                Amount::new(10),
+                Amount::ZERO,
+                0,
+                &keypair("account_1")
+            )),
+            Err(TransactionError::AccountNotFound("unknown".to_string()))
+        );
+
+        // Invalid amount
+        assert_eq!(
+            validator.validate(&Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::ZERO,
+                Amount::ZERO,
+                0,
+                &keypair("account_1")
+            )),
+            Err(TransactionError::ZeroAmount)
+        );
+
+        // Self transfer
+        assert_eq!(
+            validator.validate(&Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_1".to_string(),
+                Amount::new(10),
+                Amount::ZERO,
+                0,
+                &keypair("account_1")
+            )),
+            Err(TransactionError::SelfTransfer("account_1".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_nonces() {
+        let mut blockchain = blockchain();
+        let transfer = Transaction::transfer(
+            &network_id(),
+            "account_1".to_string(),
+            "account_2".to_string(),
+            Amount::new(10),
+            Amount::ZERO,
+            0,
+            &keypair("account_1"),
+        );
+        blockchain.add_block(vec![transfer.clone()]).unwrap();
+        let mut validator =
+            TransactionValidator::new(blockchain.network_id(), blockchain.account_state());
+
+        // Replayed transfer
+        assert_eq!(
+            validator.validate(&transfer),
+            Err(TransactionError::InvalidNonce {
+                account_id: "account_1".to_string(),
+                nonce: 0,
+                expected: 1
+            })
+        );
+
+        // Skipped nonce
+        assert_eq!(
+            validator.validate(&Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(10),
+                Amount::ZERO,
+                2,
+                &keypair("account_1")
+            )),
+            Err(TransactionError::InvalidNonce {
+                account_id: "account_1".to_string(),
+                nonce: 2,
+                expected: 1
+            })
+        );
+
+        // Next nonces, including the accepted transfers
+        for nonce in [1, 2] {
+            assert_eq!(
+                validator.validate(&Transaction::transfer(
+                    &network_id(),
+                    "account_1".to_string(),
+                    "account_2".to_string(),
+                    Amount::new(10),
+                    Amount::ZERO,
+                    nonce,
+                    &keypair("account_1")
+                )),
+                Ok(())
+            );
+        }
+        assert_eq!(validator.next_nonce("account_1"), 3);
+        assert_eq!(validator.next_nonce("account_2"), 0);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_transfers_conserve_supply(
+            transfers in prop::collection::vec(
+                (0..3usize, 0..3usize, prop_oneof![0..150u64, Just(u64::MAX)], 0..20u64),
+                0..20,
+            )
+        ) {
+            let mut blockchain = blockchain();
+            let accounts = ["account_1", "account_2", "unknown"];
+            let mut validator = TransactionValidator::new(blockchain.network_id(), blockchain.account_state());
+            let mut accepted = Vec::new();
+            for (from, to, amount, fee) in transfers {
+                let transfer = Transaction::transfer(&network_id(),
+                    accounts[from].to_string(),
+                    accounts[to].to_string(),
+                    Amount::new(amount),
+                    Amount::new(fee),
+                    validator.next_nonce(accounts[from]),
+                    &keypair(accounts[from]),
+                );
+                if validator.validate(&transfer).is_ok() {
+                    accepted.push(transfer);
+                }
+            }
+            let balances = accounts.map(|account_id| validator.balance(account_id));
+
+            // The accepted transfers apply as validated, and only move or burn funds
+            blockchain.add_block(accepted).unwrap();
+            let fees = blockchain.tip().fees().unwrap();
+            prop_assert_eq!(
+                balances,
+                accounts.map(|account_id| blockchain.get_account_balance(account_id))
+            );
+            prop_assert_eq!(
+                Amount::checked_sum(balances.into_iter().chain([fees])),
+                Ok(Amount::new(600))
+            );
+        }
+    }
+
+    #[test]
+    fn test_keys() {
+        let blockchain = blockchain();
+        let mut validator =
+            TransactionValidator::new(blockchain.network_id(), blockchain.account_state());
+
+        // Invalid public key
+        assert_eq!(
+            validator.validate(&Transaction::account_creation(
+                "account_3".to_string(),
+                Amount::ZERO,
+                "invalid".to_string()
+            )),
+            Err(TransactionError::InvalidPublicKey("account_3".to_string()))
+        );
+
+        // Transfer signed by another key
+        let transfer = Transaction::transfer(
+            &network_id(),
+            "account_1".to_string(),
+            "account_2".to_string(),
+            Amount::new(10),
+            Amount::ZERO,
+            0,
+            &keypair("account_2"),
+        );
+        assert_eq!(
+            validator.validate(&transfer),
+            Err(TransactionError::InvalidSignature(
+                transfer.id().to_string()
+            ))
+        );
+
+        // Transfer altered after being signed
+        let Transaction::Transfer(mut transfer) = Transaction::transfer(
+            &network_id(),
+            "account_1".to_string(),
+            "account_2".to_string(),
+            Amount::new(10),
+            Amount::ZERO,
+            0,
+            &keypair("account_1"),
+        ) else {
+            unreachable!()
+        };
+        transfer.amount = Amount::new(20);
+        assert_eq!(
+            validator.validate(&Transaction::Transfer(transfer.clone())),
+            Err(TransactionError::InvalidSignature(transfer.id))
+        );
+
+        // Transfer signed for another network
+        let transfer = Transaction::transfer(
+            "other_network",
+            "account_1".to_string(),
+            "account_2".to_string(),
+            Amount::new(10),
+            Amount::ZERO,
+            0,
+            &keypair("account_1"),
+        );
+        assert_eq!(
+            validator.validate(&transfer),
+            Err(TransactionError::InvalidSignature(
+                transfer.id().to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_transfer_to_pending_account() {
+        let blockchain = Blockchain::default();
+        let mut validator =
+            TransactionValidator::new(blockchain.network_id(), blockchain.account_state());
+
+        assert_eq!(
+            validator.validate(&Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key()
+            )),
+            Ok(())
+        );
+        assert_eq!(
+            validator.validate(&Transaction::account_creation(
+                "account_2".to_string(),
+                Amount::ZERO,
+                keypair("account_2").public_key()
+            )),
+            Ok(())
+        );
+        assert_eq!(
+            validator.validate(&Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(100),
+                Amount::ZERO,
+                0,
+                &keypair("account_1")
+            )),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let rule = BlockRuleError::IdMismatch { id: 2, expected: 1 };
+        assert_eq!(rule.to_string(), "id is 2, expected 1");
+        assert_eq!(
+            BlockRuleError::PreviousHashMismatch {
+                previous_hash: "ab".to_string(),
+                expected: "cd".to_string()
+            }
+            .to_string(),
+            "previous hash is ab, expected cd"
+        );
+        assert_eq!(
+            BlockRuleError::InsufficientWork(8).to_string(),
+            "hash does not meet difficulty 8"
+        );
+        assert_eq!(
+            BlockRuleError::TimestampNotIncreasing {
+                created_at: 1,
+                previous: 2
+            }
+            .to_string(),
+            "created at 1, not after the previous block created at 2"
+        );
+        assert_eq!(
+            BlockRuleError::TooLarge { size: 3, max: 2 }.to_string(),
+            "size is 3 bytes, more than the maximum of 2"
+        );
+
+        let error = BlockError::InvalidBlock { block_id: 2, rule };
+        assert_eq!(
+            error.to_string(),
+            "block #2 does not extend the blockchain: id is 2, expected 1"
+        );
+        assert_eq!(
+            BlockError::InvalidDifficulty {
+                block_id: 2,
+                difficulty: 4,
+                expected: 5
+            }
+            .to_string(),
+            "block #2 has a difficulty of 4, expected 5"
+        );
+        assert_eq!(
+            BlockError::InvalidTransaction {
+                block_id: 2,
+                error: TransactionError::AccountNotFound("account_1".to_string())
+            }
+            .to_string(),
+            "block #2 contains an invalid transaction: account 'account_1' does not exist"
+        );
+
+        // Chain errors name the block and the rule it breaks
+        assert_eq!(
+            ChainError::from(error).to_string(),
+            "block #2 does not extend the blockchain: id is 2, expected 1"
+        );
+        assert_eq!(
+            ChainError::MissingBlock { block_id: 3 }.to_string(),
+            "block #3 is missing"
+        );
+        assert_eq!(
+            ChainError::GenesisMismatch {
+                hash: "ab".to_string(),
+                network_id: "cd".to_string()
+            }
+            .to_string(),
+            "genesis block hash is ab, expected the network id cd"
+        );
+        assert_eq!(
+            ChainError::TipMismatch {
+                hash: "ab".to_string(),
+                tip: "cd".to_string()
+            }
+            .to_string(),
+            "best chain ends with block ab, expected the tip cd"
+        );
+    }
+}
@@ -0,0 +1,446 @@
+use blockchain::{amount::Amount, snapshot::Snapshot, transaction::Transaction};
+use node::node::Node;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+/// Default address of the control socket opened by `b start-node`
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+
+/// Represents a request sent by a cli command to the running node
+///
+/// Requests and responses are exchanged as one JSON document per line.
+/// Transactions are built and signed by the cli, so private keys never reach the node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Request {
+    Submit {
+        transaction: Transaction,
+    },
+    Balance {
+        account_id: String,
+    },
+    /// Get the nonce the next transfer from the account must be signed with
+    Nonce {
+        account_id: String,
+    },
+    /// Get a snapshot of the accounts at a height of the best chain, at its tip by default
+    Snapshot {
+        height: Option<i64>,
+    },
+    /// Get the network id the transactions must be signed for
+    NetworkId,
+}
+
+/// Represents the answer of the running node to a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Response {
+    Submitted { transaction_id: String },
+    Balance { account_id: String, balance: Amount },
+    Nonce { account_id: String, nonce: u64 },
+    Snapshot { snapshot: Snapshot },
+    NetworkId { network_id: String },
+    Error { message: String },
+}
+
+/// Apply a request to the node
+///
+/// Transactions are added to the mempool and sent on `outgoing` to be published
+/// to the peers, they will be confirmed in the next block.
+/// Balance, nonce, snapshot and network id queries are answered right away from the blockchain.
+pub fn handle_request(
+    node: &mut Node,
+    outgoing: &mpsc::UnboundedSender<Transaction>,
+    request: Request,
+) -> Response {
+    match request {
+        Request::Submit { transaction } => submit(node, outgoing, transaction),
+        Request::Balance { account_id } => {
+            if !node.blockchain.account_exists(&account_id) {
+                return Response::Error {
+                    message: format!("account '{account_id}' does not exist"),
+                };
+            }
+
+            let balance = node.blockchain.get_account_balance(&account_id);
+            Response::Balance {
+                account_id,
+                balance,
+            }
+        }
+        Request::Nonce { account_id } => {
+            // Count the pooled transfers, so several transfers can be sent in the same block
+            let nonce = node.next_nonce(&account_id);
+            Response::Nonce { account_id, nonce }
+        }
+        Request::Snapshot { height } => {
+            let height = height.unwrap_or(node.blockchain.height());
+            match node.blockchain.snapshot(height) {
+                Some(snapshot) => Response::Snapshot { snapshot },
+                None => Response::Error {
+                    message: format!("no snapshot at height {height}"),
+                },
+            }
+        }
+        Request::NetworkId => Response::NetworkId {
+            network_id: node.blockchain.network_id().to_string(),
+        },
+    }
+}
+
+/// Add a transaction to the node mempool and publish it
+fn submit(
+    node: &mut Node,
+    outgoing: &mpsc::UnboundedSender<Transaction>,
+    transaction: Transaction,
+) -> Response {
+    let transaction_id = transaction.id().to_string();
+
+    match node.submit_transaction(transaction.clone()) {
+        Ok(()) => {
+            println!("Received transaction {transaction_id}");
+            // The network task only stops with the node
+            let _ = outgoing.send(transaction);
+            Response::Submitted { transaction_id }
+        }
+        Err(e) => Response::Error {
+            message: e.to_string(),
+        },
+    }
+}
+
+/// Answer the requests received on the listener until an I/O error occurs
+pub async fn serve(
+    listener: TcpListener,
+    node: Arc<Mutex<Node>>,
+    outgoing: mpsc::UnboundedSender<Transaction>,
+) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let node = node.clone();
+        let outgoing = outgoing.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, node, outgoing).await {
+                println!("Control connection error: {e}");
+            }
+        });
+    }
+}
+
+/// Answer every request sent on a single connection
+async fn handle_connection(
+    stream: TcpStream,
+    node: Arc<Mutex<Node>>,
+    outgoing: mpsc::UnboundedSender<Transaction>,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let mut node = node.lock().expect("Node lock should not be poisoned");
+                handle_request(&mut node, &outgoing, request)
+            }
+            Err(e) => Response::Error {
+                message: format!("Invalid request: {e}"),
+            },
+        };
+
+        let mut data = serde_json::to_vec(&response)?;
+        data.push(b'\n');
+        writer.write_all(&data).await?;
+    }
+
+    Ok(())
+}
+
+/// Send a request to the node listening on the given address and wait for its response
+pub async fn send(address: &str, request: &Request) -> io::Result<Response> {
+    let stream = TcpStream::connect(address).await?;
+    let (reader, mut writer) = stream.into_split();
+
+    let mut data = serde_json::to_vec(request)?;
+    data.push(b'\n');
+    writer.write_all(&data).await?;
+
+    let line = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await?
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "Node closed the connection")
+        })?;
+
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blockchain::keys::test_utils::{keypair, network_id};
+
+    #[test]
+    fn test_handle_create_account() {
+        let mut node = Node::default();
+        let (outgoing, mut published) = mpsc::unbounded_channel();
+        let response = handle_request(
+            &mut node,
+            &outgoing,
+            Request::Submit {
+                transaction: Transaction::account_creation(
+                    "account_id".to_string(),
+                    Amount::new(100),
+                    keypair("account_id").public_key(),
+                ),
+            },
+        );
+
+        assert_eq!(node.mempool.len(), 1);
+        assert_eq!(
+            response,
+            Response::Submitted {
+                transaction_id: node.mempool.transactions()[0].id().to_string()
+            }
+        );
+        assert_eq!(
+            published.try_recv().unwrap().id(),
+            node.mempool.transactions()[0].id()
+        );
+    }
+
+    #[test]
+    fn test_handle_transfer() {
+        let mut node = Node::default();
+        let (outgoing, mut published) = mpsc::unbounded_channel();
+        node.mempool.extend([
+            Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            ),
+            Transaction::account_creation(
+                "account_2".to_string(),
+                Amount::ZERO,
+                keypair("account_2").public_key(),
+            ),
+        ]);
+        node.mine_block().unwrap();
+
+        let response = handle_request(
+            &mut node,
+            &outgoing,
+            Request::Submit {
+                transaction: Transaction::transfer(
+                    &network_id(),
+                    "account_1".to_string(),
+                    "account_2".to_string(),
+                    Amount::new(50),
+                    Amount::ZERO,
+                    0,
+                    &keypair("account_1"),
+                ),
+            },
+        );
+
+        assert_eq!(node.mempool.len(), 1);
+        assert!(matches!(response, Response::Submitted { .. }));
+        assert!(published.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_handle_network_id() {
+        let mut node = Node::default();
+        let (outgoing, _published) = mpsc::unbounded_channel();
+
+        assert_eq!(
+            handle_request(&mut node, &outgoing, Request::NetworkId),
+            Response::NetworkId {
+                network_id: network_id()
+            }
+        );
+    }
+
+    #[test]
+    fn test_handle_invalid_transaction() {
+        let mut node = Node::default();
+        let (outgoing, mut published) = mpsc::unbounded_channel();
+        let response = handle_request(
+            &mut node,
+            &outgoing,
+            Request::Submit {
+                transaction: Transaction::transfer(
+                    &network_id(),
+                    "account_1".to_string(),
+                    "account_2".to_string(),
+                    Amount::new(50),
+                    Amount::ZERO,
+                    0,
+                    &keypair("account_1"),
+                ),
+            },
+        );
+
+        assert_eq!(node.mempool.len(), 0);
+        assert!(published.try_recv().is_err());
+        assert_eq!(
+            response,
+            Response::Error {
+                message: "account 'account_1' does not exist".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_handle_balance() {
+        let mut node = Node::default();
+        let (outgoing, _published) = mpsc::unbounded_channel();
+        node.mempool
+            .insert(Transaction::account_creation(
+                "account_id".to_string(),
+                Amount::new(100),
+                keypair("account_id").public_key(),
+            ))
+            .unwrap();
+
+        // Pending account creation is not visible yet
+        let response = handle_request(
+            &mut node,
+            &outgoing,
+            Request::Balance {
+                account_id: "account_id".to_string(),
+            },
+        );
+        assert_eq!(
+            response,
+            Response::Error {
+                message: "account 'account_id' does not exist".to_string()
+            }
+        );
+
+        // Confirmed account creation
+        node.mine_block().unwrap();
+        let response = handle_request(
+            &mut node,
+            &outgoing,
+            Request::Balance {
+                account_id: "account_id".to_string(),
+            },
+        );
+        assert_eq!(
+            response,
+            Response::Balance {
+                account_id: "account_id".to_string(),
+                balance: Amount::new(100)
+            }
+        );
+    }
+
+    #[test]
+    fn test_handle_nonce() {
+        let mut node = Node::default();
+        let (outgoing, _published) = mpsc::unbounded_channel();
+        node.mempool.extend([
+            Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            ),
+            Transaction::account_creation(
+                "account_2".to_string(),
+                Amount::ZERO,
+                keypair("account_2").public_key(),
+            ),
+        ]);
+        node.mine_block().unwrap();
+        node.mempool
+            .insert(Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(50),
+                Amount::ZERO,
+                0,
+                &keypair("account_1"),
+            ))
+            .unwrap();
+
+        let response = handle_request(
+            &mut node,
+            &outgoing,
+            Request::Nonce {
+                account_id: "account_1".to_string(),
+            },
+        );
+        assert_eq!(
+            response,
+            Response::Nonce {
+                account_id: "account_1".to_string(),
+                nonce: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_handle_snapshot() {
+        let mut node = Node::default();
+        let (outgoing, _published) = mpsc::unbounded_channel();
+        node.mempool
+            .insert(Transaction::account_creation(
+                "account_id".to_string(),
+                Amount::new(100),
+                keypair("account_id").public_key(),
+            ))
+            .unwrap();
+        node.mine_block().unwrap();
+
+        let response = handle_request(&mut node, &outgoing, Request::Snapshot { height: None });
+        let Response::Snapshot { snapshot } = &response else {
+            panic!("unexpected response {response:?}");
+        };
+        assert_eq!(snapshot.block(), Some(node.blockchain.tip()));
+        assert_eq!(snapshot.accounts["account_id"].balance, Amount::new(100));
+        // The total work goes through JSON as well
+        assert_eq!(
+            serde_json::from_str::<Response>(&serde_json::to_string(&response).unwrap()).unwrap(),
+            response
+        );
+
+        let response = handle_request(&mut node, &outgoing, Request::Snapshot { height: Some(2) });
+        assert_eq!(
+            response,
+            Response::Error {
+                message: "no snapshot at height 2".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_serve_and_send() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let node = Arc::new(Mutex::new(Node::default()));
+        let (outgoing, mut published) = mpsc::unbounded_channel();
+        tokio::spawn(serve(listener, node.clone(), outgoing));
+
+        let response = send(
+            &address,
+            &Request::Submit {
+                transaction: Transaction::account_creation(
+                    "account_id".to_string(),
+                    Amount::new(100),
+                    keypair("account_id").public_key(),
+                ),
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(response, Response::Submitted { .. }));
+        assert_eq!(node.lock().unwrap().mempool.len(), 1);
+        assert!(published.recv().await.is_some());
+    }
+}
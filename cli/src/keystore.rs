@@ -0,0 +1,127 @@
+use blockchain::keys::Keypair;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Default directory of the keystore, relative to the working directory
+pub const DEFAULT_DIRECTORY: &str = ".b/keys";
+
+/// Represents the directory storing the private keys of the accounts created by the cli
+///
+/// Each key is stored hex encoded in a `<account_id>.key` file, and never leaves the machine:
+/// only the public key and the signed transfers are sent to the node.
+pub struct Keystore {
+    directory: PathBuf,
+}
+
+impl Keystore {
+    /// Open the keystore stored in the given directory
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Keystore {
+            directory: directory.into(),
+        }
+    }
+
+    /// Get the directory of the keystore
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Generate and store the key pair of a new account
+    ///
+    /// Fails with `io::ErrorKind::AlreadyExists` if the account already has a key.
+    pub fn create(&self, account_id: &str) -> io::Result<Keypair> {
+        let path = self.path(account_id)?;
+        fs::create_dir_all(&self.directory)?;
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let keypair = Keypair::generate();
+        options
+            .open(path)?
+            .write_all(keypair.private_key().as_bytes())?;
+        Ok(keypair)
+    }
+
+    /// Load the key pair of an account
+    pub fn load(&self, account_id: &str) -> io::Result<Keypair> {
+        let private_key = fs::read_to_string(self.path(account_id)?)?;
+        Keypair::from_private_key(&private_key)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Remove the key pair of an account
+    pub fn remove(&self, account_id: &str) -> io::Result<()> {
+        fs::remove_file(self.path(account_id)?)
+    }
+
+    /// Get the path of the key file of an account
+    fn path(&self, account_id: &str) -> io::Result<PathBuf> {
+        let is_valid = !account_id.is_empty()
+            && account_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !is_valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "account id '{account_id}' should only contain letters, digits, '-' and '_'"
+                ),
+            ));
+        }
+
+        Ok(self.directory.join(format!("{account_id}.key")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn keystore(name: &str) -> Keystore {
+        let directory =
+            std::env::temp_dir().join(format!("b-keystore-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        Keystore::new(directory)
+    }
+
+    #[test]
+    fn test_create_and_load() {
+        let keystore = keystore("create");
+        let keypair = keystore.create("account_id").unwrap();
+
+        assert_eq!(
+            keystore.load("account_id").unwrap().public_key(),
+            keypair.public_key()
+        );
+        assert_eq!(
+            keystore.create("account_id").unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+
+        keystore.remove("account_id").unwrap();
+        assert_eq!(
+            keystore.load("account_id").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        fs::remove_dir_all(keystore.directory()).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_account_id() {
+        let keystore = keystore("invalid");
+
+        for account_id in ["", "../account_id", "account/id"] {
+            assert_eq!(
+                keystore.create(account_id).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+    }
+}
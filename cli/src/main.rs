@@ -0,0 +1,351 @@
+use blockchain::{
+    amount::Amount, encoding::Encode, genesis::GenesisConfig, transaction::Transaction,
+};
+use clap::{Parser, Subcommand};
+use control::{Request, Response};
+use keystore::Keystore;
+use node::rpc;
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+mod control;
+mod keystore;
+mod start_node;
+
+/// Command line interface of the B blockchain
+#[derive(Parser)]
+#[command(name = "b", version, about)]
+struct Cli {
+    /// Address of the control socket of the local node
+    #[arg(long, global = true, default_value = control::DEFAULT_ADDRESS)]
+    node: String,
+
+    /// Directory storing the private keys of the accounts created with this cli
+    #[arg(long, global = true, default_value = keystore::DEFAULT_DIRECTORY)]
+    keystore: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Available commands
+#[derive(Subcommand)]
+enum Command {
+    /// Start a local B blockchain node (stop it with Ctrl-C)
+    StartNode {
+        /// JSON genesis configuration shared by the nodes of the chain
+        #[arg(long)]
+        genesis: Option<PathBuf>,
+        /// Directory where the node stores its blocks, reloaded when it restarts
+        #[arg(long, default_value = start_node::DEFAULT_DATA_DIRECTORY)]
+        data_dir: PathBuf,
+        /// How the node keeps its chain in the data directory
+        #[arg(long, value_enum, default_value_t)]
+        store: start_node::StoreKind,
+        /// Snapshot exported by another node, to start without the blocks before it
+        ///
+        /// It is only used when the data directory has no chain yet.
+        #[arg(long, requires = "snapshot_hash")]
+        snapshot: Option<PathBuf>,
+        /// Hash the snapshot must have, obtained from a source you trust
+        #[arg(long, requires = "snapshot")]
+        snapshot_hash: Option<String>,
+        /// Address of the JSON-RPC server, keep it on localhost as it has no authentication
+        #[arg(long, default_value = rpc::DEFAULT_ADDRESS)]
+        rpc: String,
+        /// Account credited with the fees of the blocks mined by the node
+        ///
+        /// The fees are burned while the account doesn't exist.
+        #[arg(long)]
+        producer: Option<String>,
+    },
+    /// Create an account with a starting balance, and store its private key in the keystore
+    CreateAccount {
+        /// Id of the account to create
+        account_id: String,
+        /// Starting balance of the account
+        starting_balance: u64,
+        /// Faucet account paying the starting balance, signed with its key; the balance is
+        /// minted without one, which chains with a restricted supply refuse
+        #[arg(long)]
+        faucet: Option<String>,
+    },
+    /// Transfer funds from one account to another, signed with the key of the sender
+    Transfer {
+        /// Id of the account sending the funds
+        from_account: String,
+        /// Id of the account receiving the funds
+        to_account: String,
+        /// Amount to transfer
+        #[arg(value_parser = clap::value_parser!(u64).range(1..))]
+        amount: u64,
+        /// Fee paid to the block producer on top of the amount, higher fees are included first
+        #[arg(long, default_value_t = 0)]
+        fee: u64,
+    },
+    /// Display the balance of an account
+    Balance {
+        /// Id of the account
+        account_id: String,
+    },
+    /// Export a snapshot of the accounts of the local node, to start other nodes from it
+    ExportSnapshot {
+        /// File to write the snapshot to
+        output: PathBuf,
+        /// Height of the snapshot, the tip of the local node blockchain by default
+        #[arg(long)]
+        height: Option<i64>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let keystore = Keystore::new(&cli.keystore);
+    let mut created_account = None;
+    let mut snapshot_output = None;
+
+    let request = match cli.command {
+        Command::StartNode {
+            genesis,
+            data_dir,
+            store,
+            snapshot,
+            snapshot_hash,
+            rpc,
+            producer,
+        } => {
+            let genesis = match genesis {
+                Some(path) => match GenesisConfig::from_file(&path) {
+                    Ok(genesis) => genesis,
+                    Err(e) => {
+                        eprintln!("Genesis error: {e}");
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => GenesisConfig::default(),
+            };
+
+            let snapshot = snapshot.as_deref().zip(snapshot_hash.as_deref());
+            return match start_node::run(
+                &cli.node, &rpc, producer, &genesis, &data_dir, store, snapshot,
+            )
+            .await
+            {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("Node error: {e}");
+                    ExitCode::FAILURE
+                }
+            };
+        }
+        Command::CreateAccount {
+            account_id,
+            starting_balance,
+            faucet,
+        } => {
+            let faucet = match faucet {
+                Some(faucet) => match keystore.load(&faucet) {
+                    Ok(keypair) => Some((faucet, keypair)),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        eprintln!(
+                            "error: no key for account '{faucet}' in {}, was it created with this cli?",
+                            keystore.directory().display()
+                        );
+                        return ExitCode::FAILURE;
+                    }
+                    Err(e) => {
+                        eprintln!("error: could not load the key of account '{faucet}': {e}");
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => None,
+            };
+            // Only the faucet signs, for the network of the node
+            let network_id = match faucet {
+                Some(_) => match network_id(&cli.node).await {
+                    Ok(network_id) => network_id,
+                    Err(code) => return code,
+                },
+                None => String::new(),
+            };
+            let keypair = match keystore.create(&account_id) {
+                Ok(keypair) => keypair,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    eprintln!(
+                        "error: a key for account '{account_id}' already exists in {}",
+                        keystore.directory().display()
+                    );
+                    return ExitCode::FAILURE;
+                }
+                Err(e) => {
+                    eprintln!("error: could not store the key of account '{account_id}': {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            created_account = Some(account_id.clone());
+
+            let transaction = match faucet {
+                Some((faucet, faucet_keypair)) => Transaction::funded_account_creation(
+                    &network_id,
+                    account_id,
+                    Amount::new(starting_balance),
+                    keypair.public_key(),
+                    faucet,
+                    &faucet_keypair,
+                ),
+                None => Transaction::account_creation(
+                    account_id,
+                    Amount::new(starting_balance),
+                    keypair.public_key(),
+                ),
+            };
+            Request::Submit { transaction }
+        }
+        Command::Transfer {
+            from_account,
+            to_account,
+            amount,
+            fee,
+        } => {
+            let keypair = match keystore.load(&from_account) {
+                Ok(keypair) => keypair,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    eprintln!(
+                        "error: no key for account '{from_account}' in {}, was it created with this cli?",
+                        keystore.directory().display()
+                    );
+                    return ExitCode::FAILURE;
+                }
+                Err(e) => {
+                    eprintln!("error: could not load the key of account '{from_account}': {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let nonce_request = Request::Nonce {
+                account_id: from_account.clone(),
+            };
+            let nonce = match control::send(&cli.node, &nonce_request).await {
+                Ok(Response::Nonce { nonce, .. }) => nonce,
+                Ok(response) => {
+                    eprintln!("error: unexpected response from the node: {response:?}");
+                    return ExitCode::FAILURE;
+                }
+                Err(e) => {
+                    eprintln!(
+                        "error: could not reach a node at {} ({e}), is `b start-node` running?",
+                        cli.node
+                    );
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let network_id = match network_id(&cli.node).await {
+                Ok(network_id) => network_id,
+                Err(code) => return code,
+            };
+
+            Request::Submit {
+                transaction: Transaction::transfer(
+                    &network_id,
+                    from_account,
+                    to_account,
+                    Amount::new(amount),
+                    Amount::new(fee),
+                    nonce,
+                    &keypair,
+                ),
+            }
+        }
+        Command::Balance { account_id } => Request::Balance { account_id },
+        Command::ExportSnapshot { output, height } => {
+            snapshot_output = Some(output);
+            Request::Snapshot { height }
+        }
+    };
+
+    let response = control::send(&cli.node, &request).await;
+    if let Some(account_id) = &created_account {
+        if !matches!(response, Ok(Response::Submitted { .. })) {
+            // The account was not created, its key is useless
+            let _ = keystore.remove(account_id);
+        }
+    }
+
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!(
+                "error: could not reach a node at {} ({e}), is `b start-node` running?",
+                cli.node
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match response {
+        Response::Submitted { transaction_id } => {
+            println!(
+                "Transaction {transaction_id} submitted, it will be confirmed in the next block"
+            );
+            ExitCode::SUCCESS
+        }
+        Response::Balance {
+            account_id,
+            balance,
+        } => {
+            println!("{account_id}: {balance}");
+            ExitCode::SUCCESS
+        }
+        Response::Nonce { account_id, nonce } => {
+            println!("{account_id}: next nonce {nonce}");
+            ExitCode::SUCCESS
+        }
+        Response::NetworkId { network_id } => {
+            println!("Network id {network_id}");
+            ExitCode::SUCCESS
+        }
+        Response::Snapshot { snapshot } => {
+            let Some(output) = snapshot_output else {
+                eprintln!("error: unexpected snapshot from the node");
+                return ExitCode::FAILURE;
+            };
+            if let Err(e) = std::fs::write(&output, snapshot.to_bytes()) {
+                eprintln!(
+                    "error: could not write the snapshot to {}: {e}",
+                    output.display()
+                );
+                return ExitCode::FAILURE;
+            }
+            println!(
+                "Snapshot of block #{} written to {}, start a node from it with `--snapshot {} --snapshot-hash {}`",
+                snapshot.block().map_or(0, |block| block.header.id),
+                output.display(),
+                output.display(),
+                snapshot.hash()
+            );
+            ExitCode::SUCCESS
+        }
+        Response::Error { message } => {
+            eprintln!("error: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Get the network id of the node the transactions are sent to, to sign them for its chain
+async fn network_id(node: &str) -> Result<String, ExitCode> {
+    match control::send(node, &Request::NetworkId).await {
+        Ok(Response::NetworkId { network_id }) => Ok(network_id),
+        Ok(response) => {
+            eprintln!("error: unexpected response from the node: {response:?}");
+            Err(ExitCode::FAILURE)
+        }
+        Err(e) => {
+            eprintln!("error: could not reach a node at {node} ({e}), is `b start-node` running?");
+            Err(ExitCode::FAILURE)
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::control;
+use blockchain::{
+    block::Block,
+    blockchain::Blockchain,
+    encoding::{Decode, DecodeError},
+    genesis::GenesisConfig,
+    redb_store::{RedbStore, RedbStoreError},
+    snapshot::{Snapshot, SnapshotError},
+    storage::FileStorage,
+    store::{ChainStore, StoreError},
+};
+use network::network::{NetworkError, NetworkNode};
+use node::{node::Node, p2p, rpc, scheduler::BlockScheduler};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::{
+    net::TcpListener,
+    select, signal,
+    sync::{broadcast, mpsc},
+};
+
+/// Time between two mined blocks
+const BLOCK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Default directory of the node data, relative to the working directory
+pub const DEFAULT_DATA_DIRECTORY: &str = ".b/chain";
+
+/// Name of the database storing the chain, in the data directory
+const CHAIN_FILE: &str = "chain.redb";
+
+/// Name of the append-only file storing the chain, in the data directory
+const BLOCKS_FILE: &str = "blocks.dat";
+
+/// Represents how the node keeps its chain in the data directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum StoreKind {
+    /// Embedded redb database, written in a transaction per block
+    #[default]
+    Redb,
+    /// Append-only file, replayed into memory when the node starts
+    File,
+}
+
+/// Represents the reason a node can't start, or stopped before Ctrl-C was pressed
+#[derive(Debug, Error)]
+pub enum NodeError {
+    /// The database in the data directory can't be opened
+    #[error(transparent)]
+    Database(#[from] RedbStoreError),
+    /// The append-only file in the data directory can't be read or written
+    #[error("could not open the chain file: {0}")]
+    ChainFile(io::Error),
+    /// The stored chain can't be opened, the report names the block and rule that failed
+    #[error(transparent)]
+    Store(#[from] StoreError),
+    /// The snapshot file can't be read
+    #[error("could not read the snapshot file: {0}")]
+    SnapshotFile(io::Error),
+    /// The snapshot file is not an encoded snapshot
+    #[error("invalid snapshot file: {0}")]
+    SnapshotEncoding(#[from] DecodeError),
+    /// The blockchain can't be bootstrapped from the snapshot
+    #[error(transparent)]
+    Snapshot(#[from] SnapshotError),
+    /// The node can't join the p2p network
+    #[error(transparent)]
+    Network(#[from] NetworkError),
+    /// The control socket can't be bound or accept connections
+    #[error("control socket error: {0}")]
+    Control(#[from] io::Error),
+    /// The JSON-RPC server can't be bound or accept connections
+    #[error("JSON-RPC server error: {0}")]
+    Rpc(io::Error),
+}
+
+/// Start a local node and run it until Ctrl-C is pressed
+///
+/// The node reloads the blockchain stored in the data directory, or builds a new one from
+/// the genesis configuration, or from a snapshot and its trusted hash if one is given. It then
+/// joins the p2p network, mines a block at every interval boundary and answers the cli commands
+/// sent on the control socket bound to `address`, and the JSON-RPC requests posted to the HTTP
+/// server bound to `rpc_address`. The fees of the blocks it mines go to the `producer` account.
+pub async fn run(
+    address: &str,
+    rpc_address: &str,
+    producer: Option<String>,
+    genesis: &GenesisConfig,
+    data_directory: &Path,
+    store: StoreKind,
+    snapshot: Option<(&Path, &str)>,
+) -> Result<(), NodeError> {
+    let mut node =
+        Node::with_blockchain(open_blockchain(genesis, data_directory, store, snapshot)?);
+    node.producer = producer;
+    println!(
+        "Starting node on chain '{}' with network id {} at block #{}",
+        genesis.chain_id,
+        node.blockchain.network_id(),
+        node.blockchain.tip().header.id
+    );
+    let node = Arc::new(Mutex::new(node));
+    let network_node = NetworkNode::new().await?;
+    let (outgoing, outgoing_receiver) = mpsc::unbounded_channel();
+    let listener = TcpListener::bind(address).await?;
+    println!("Node control socket listening on {address}");
+    let rpc_listener = TcpListener::bind(rpc_address)
+        .await
+        .map_err(NodeError::Rpc)?;
+    println!("JSON-RPC server listening on http://{rpc_address}, events on ws://{rpc_address}/ws");
+
+    let scheduler = BlockScheduler::new(BLOCK_INTERVAL).spawn(node.clone());
+    let mined_blocks = scheduler.subscribe();
+
+    let result = select! {
+        result = p2p::run(node.clone(), network_node, outgoing_receiver, scheduler.subscribe()) => Ok(result?),
+        result = control::serve(listener, node.clone(), outgoing.clone()) => Ok(result?),
+        result = rpc::serve(rpc_listener, node, outgoing) => result.map_err(NodeError::Rpc),
+        _ = log_mined_blocks(mined_blocks) => Ok(()),
+        _ = signal::ctrl_c() => {
+            println!("Stopping node");
+            Ok(())
+        }
+    };
+
+    scheduler.shutdown().await;
+    result
+}
+
+/// Open the blockchain stored in the data directory, bootstrapping it from the snapshot if empty
+fn open_blockchain(
+    genesis: &GenesisConfig,
+    data_directory: &Path,
+    store: StoreKind,
+    snapshot: Option<(&Path, &str)>,
+) -> Result<Blockchain, NodeError> {
+    let store: Box<dyn ChainStore> = match store {
+        StoreKind::Redb => Box::new(RedbStore::open(data_directory.join(CHAIN_FILE))?),
+        StoreKind::File => Box::new(
+            FileStorage::open(data_directory.join(BLOCKS_FILE)).map_err(NodeError::ChainFile)?,
+        ),
+    };
+    match snapshot {
+        Some((path, hash)) if store.tip().is_none() => {
+            let snapshot =
+                Snapshot::from_bytes(&std::fs::read(path).map_err(NodeError::SnapshotFile)?)?;
+            let blockchain = Blockchain::from_snapshot(genesis, &snapshot, hash, store)?;
+            println!(
+                "Bootstrapped from the snapshot of block #{}",
+                blockchain.height()
+            );
+            Ok(blockchain)
+        }
+        Some(_) => {
+            println!("The data directory already has a chain, ignoring the snapshot");
+            Ok(Blockchain::open(genesis, store)?)
+        }
+        None => Ok(Blockchain::open(genesis, store)?),
+    }
+}
+
+/// Print the blocks mined by the scheduler
+async fn log_mined_blocks(mut mined_blocks: broadcast::Receiver<Block>) {
+    loop {
+        match mined_blocks.recv().await {
+            Ok(block) => println!(
+                "Mined block #{} with {} transaction(s)",
+                block.header.id,
+                block.transactions.len()
+            ),
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blockchain::validation::ChainError;
+
+    #[test]
+    fn test_error_messages() {
+        assert_eq!(
+            NodeError::from(StoreError::InvalidChain(ChainError::MissingBlock {
+                block_id: 3
+            }))
+            .to_string(),
+            "stored chain is not valid: block #3 is missing"
+        );
+        assert_eq!(
+            NodeError::from(DecodeError::UnexpectedEnd).to_string(),
+            "invalid snapshot file: unexpected end of input"
+        );
+        assert_eq!(
+            NodeError::ChainFile(io::Error::other("permission denied")).to_string(),
+            "could not open the chain file: permission denied"
+        );
+        assert_eq!(
+            NodeError::Rpc(io::Error::other("address in use")).to_string(),
+            "JSON-RPC server error: address in use"
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use blockchain::{amount::Amount, blockchain::Blockchain};
+use libp2p::StreamProtocol;
+use serde::{Deserialize, Serialize};
+
+/// Name of the request-response protocol used for balance queries
+pub const BALANCE_PROTOCOL: StreamProtocol = StreamProtocol::new("/b/balance/1");
+
+/// Represents a balance query sent to a peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceRequest {
+    pub account_id: String,
+}
+
+/// Represents the answer of a peer to a balance query
+///
+/// `balance` is `None` when the account does not exist on the peer blockchain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceResponse {
+    pub account_id: String,
+    pub balance: Option<Amount>,
+}
+
+impl BalanceResponse {
+    /// Answer a balance query from the local blockchain
+    pub fn from_blockchain(blockchain: &Blockchain, request: BalanceRequest) -> BalanceResponse {
+        let balance = blockchain
+            .account_exists(&request.account_id)
+            .then(|| blockchain.get_account_balance(&request.account_id));
+
+        BalanceResponse {
+            account_id: request.account_id,
+            balance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blockchain::{keys::Keypair, transaction::Transaction};
+
+    #[test]
+    fn test_from_blockchain() {
+        let mut blockchain = Blockchain::default();
+        blockchain
+            .add_block(vec![Transaction::account_creation(
+                "account_id".to_string(),
+                Amount::new(100),
+                Keypair::generate().public_key(),
+            )])
+            .unwrap();
+
+        assert_eq!(
+            BalanceResponse::from_blockchain(
+                &blockchain,
+                BalanceRequest {
+                    account_id: "account_id".to_string()
+                }
+            ),
+            BalanceResponse {
+                account_id: "account_id".to_string(),
+                balance: Some(Amount::new(100))
+            }
+        );
+        assert_eq!(
+            BalanceResponse::from_blockchain(
+                &blockchain,
+                BalanceRequest {
+                    account_id: "Does not exist".to_string()
+                }
+            ),
+            BalanceResponse {
+                account_id: "Does not exist".to_string(),
+                balance: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_serialization() {
+        let response = BalanceResponse {
+            account_id: "account_id".to_string(),
+            balance: Some(Amount::new(100)),
+        };
+
+        let data = serde_json::to_string(&response).unwrap();
+
+        assert_eq!(data, r#"{"account_id":"account_id","balance":100}"#);
+        assert_eq!(
+            serde_json::from_str::<BalanceResponse>(&data).unwrap(),
+            response
+        );
+    }
+}
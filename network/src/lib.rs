@@ -1 +1,3 @@
+pub mod balance;
 pub mod network;
+pub mod sync;
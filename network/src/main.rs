@@ -1,24 +1,53 @@
-use libp2p::{gossipsub, mdns, swarm::NetworkBehaviour};
-use network::network::NetworkNode;
-use std::{error::Error, vec};
-
-// We create a custom network behaviour that combines Gossipsub and Mdns.
-#[derive(NetworkBehaviour)]
-struct MyBehaviour {
-    gossipsub: gossipsub::Behaviour,
-    mdns: mdns::tokio::Behaviour,
-}
+use blockchain::blockchain::Blockchain;
+use network::balance::BalanceResponse;
+use network::network::{NetworkEvent, NetworkNode};
+use network::sync::BlocksResponse;
+use std::error::Error;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     // Create a new network node and listen for incoming messages.
+    let blockchain = Blockchain::default();
     let mut network_node = NetworkNode::new().await?;
     network_node.listen().await?;
 
-    // Send a balance query to the network.
-    // TODO: fix this as something is going wrong here.
-    // When running two nodes, the balance query is not being received by the other node.
-    network_node.send_balance_query();
-
-    Ok(())
+    loop {
+        match network_node.next_event().await {
+            // Query the balance of an account on every newly connected peer.
+            NetworkEvent::PeerConnected { peer_id } => {
+                network_node.send_balance_query(&peer_id, "account_id".to_string());
+            }
+            NetworkEvent::Transaction {
+                peer_id,
+                transaction,
+            } => println!("Got transaction: {transaction:?} from peer: {peer_id}"),
+            NetworkEvent::BalanceRequest {
+                peer_id,
+                request,
+                channel,
+            } => {
+                println!("Got balance query: {request:?} from peer: {peer_id}");
+                let response = BalanceResponse::from_blockchain(&blockchain, request);
+                network_node.send_balance_response(channel, response);
+            }
+            NetworkEvent::BalanceResponse {
+                peer_id, response, ..
+            } => println!("Got balance response: {response:?} from peer: {peer_id}"),
+            NetworkEvent::Block { peer_id, block } => {
+                println!("Got block: {block:?} from peer: {peer_id}")
+            }
+            NetworkEvent::BlocksRequest {
+                peer_id,
+                request,
+                channel,
+            } => {
+                println!("Got blocks request: {request:?} from peer: {peer_id}");
+                let response = BlocksResponse::from_blockchain(&blockchain, &request);
+                network_node.send_blocks_response(channel, response);
+            }
+            NetworkEvent::BlocksResponse {
+                peer_id, response, ..
+            } => println!("Got {} blocks from peer: {peer_id}", response.blocks.len()),
+        }
+    }
 }
@@ -1,29 +1,125 @@
 // based on example of libp2p doc: https://github.com/libp2p/rust-libp2p/tree/master/examples/chat
 
+use crate::balance::{BalanceRequest, BalanceResponse, BALANCE_PROTOCOL};
+use crate::sync::{BlocksRequest, BlocksResponse, SyncCodec, SYNC_PROTOCOL};
+use blockchain::{
+    block::{Block, MAX_BLOCK_SIZE},
+    encoding::{Decode, Encode},
+    transaction::Transaction,
+};
 use futures::stream::StreamExt;
 use libp2p::{
-    gossipsub, mdns, noise, swarm::NetworkBehaviour, swarm::SwarmEvent, tcp, yamux, Swarm,
+    gossipsub, mdns, noise,
+    request_response::{self, OutboundRequestId, ProtocolSupport, ResponseChannel},
+    swarm::NetworkBehaviour,
+    swarm::SwarmEvent,
+    tcp, yamux, PeerId, Swarm,
 };
 use once_cell::sync::Lazy;
 use std::collections::hash_map::DefaultHasher;
-use std::error::Error;
 use std::hash::{Hash, Hasher};
 use std::time::Duration;
-use tokio::{io, select};
+use thiserror::Error;
+use tokio::io;
 
 // Topic static variables
 pub static ACCOUNT_CREATION_TOPIC: Lazy<gossipsub::IdentTopic> =
     Lazy::new(|| gossipsub::IdentTopic::new("account_creation"));
 pub static TRANSFER_TOPIC: Lazy<gossipsub::IdentTopic> =
     Lazy::new(|| gossipsub::IdentTopic::new("transfer"));
-pub static BALANCE_QUERY_TOPIC: Lazy<gossipsub::IdentTopic> =
-    Lazy::new(|| gossipsub::IdentTopic::new("balance_query"));
+pub static BLOCK_TOPIC: Lazy<gossipsub::IdentTopic> =
+    Lazy::new(|| gossipsub::IdentTopic::new("block"));
+
+/// Maximum size of a gossiped message, in bytes
+///
+/// It holds a block of `MAX_BLOCK_SIZE`, with room for the envelope added by gossipsub
+/// (topic, sequence number, author and signature).
+pub const MAX_TRANSMIT_SIZE: usize = MAX_BLOCK_SIZE + 64 * 1024;
+
+/// Represents an event of the network the node should react to
+#[derive(Debug)]
+pub enum NetworkEvent {
+    /// A first connection was established with a peer
+    PeerConnected { peer_id: PeerId },
+    /// A transaction was published on the account creation or transfer topic
+    Transaction {
+        peer_id: PeerId,
+        transaction: Transaction,
+    },
+    /// A peer asks for the balance of an account, answer with `NetworkNode::send_balance_response`
+    BalanceRequest {
+        peer_id: PeerId,
+        request: BalanceRequest,
+        channel: ResponseChannel<BalanceResponse>,
+    },
+    /// A peer answered a balance query sent with `NetworkNode::send_balance_query`
+    BalanceResponse {
+        peer_id: PeerId,
+        request_id: OutboundRequestId,
+        response: BalanceResponse,
+    },
+    /// A peer announced a block it just mined on the block topic
+    Block { peer_id: PeerId, block: Block },
+    /// A peer asks for a range of blocks, answer with `NetworkNode::send_blocks_response`
+    BlocksRequest {
+        peer_id: PeerId,
+        request: BlocksRequest,
+        channel: ResponseChannel<BlocksResponse>,
+    },
+    /// A peer answered a blocks request sent with `NetworkNode::send_blocks_request`
+    BlocksResponse {
+        peer_id: PeerId,
+        request_id: OutboundRequestId,
+        response: BlocksResponse,
+    },
+}
+
+/// Represents the reason the network node can't be set up
+#[derive(Debug, Error)]
+pub enum NetworkError {
+    /// The transport can't be secured
+    #[error("could not set up the transport: {0}")]
+    Transport(#[from] noise::Error),
+    /// One of the gossipsub, mDNS or request-response behaviours can't be created
+    #[error("could not set up the network behaviour: {0}")]
+    Behaviour(Box<dyn std::error::Error + Send + Sync>),
+    /// The node can't subscribe to one of the topics
+    #[error("could not subscribe to a topic: {0}")]
+    Subscription(#[from] gossipsub::SubscriptionError),
+    /// A listen address can't be parsed
+    #[error("invalid listen address: {0}")]
+    InvalidAddress(#[from] libp2p::multiaddr::Error),
+    /// The node can't listen on an address
+    #[error("could not listen: {0}")]
+    Listen(#[from] libp2p::TransportError<io::Error>),
+}
 
 /// Custom network behaviour
 #[derive(NetworkBehaviour)]
-struct MyBehaviour {
+pub struct MyBehaviour {
     gossipsub: gossipsub::Behaviour,
     mdns: mdns::tokio::Behaviour,
+    balance: request_response::json::Behaviour<BalanceRequest, BalanceResponse>,
+    sync: request_response::Behaviour<SyncCodec>,
+}
+
+/// Build the gossipsub configuration, allowing messages up to `MAX_TRANSMIT_SIZE`
+fn gossipsub_config() -> io::Result<gossipsub::Config> {
+    // To content-address message, we can take the hash of message and use it as an ID.
+    let message_id_fn = |message: &gossipsub::Message| {
+        let mut s = DefaultHasher::new();
+        message.data.hash(&mut s);
+        gossipsub::MessageId::from(s.finish().to_string())
+    };
+
+    // Set a custom gossipsub configuration
+    gossipsub::ConfigBuilder::default()
+        .heartbeat_interval(Duration::from_secs(10)) // This is set to aid debugging by not cluttering the log space
+        .validation_mode(gossipsub::ValidationMode::Strict) // This sets the kind of message validation. The default is Strict (enforce message signing)
+        .message_id_fn(message_id_fn) // content-address messages. No two messages of the same content will be propagated.
+        .max_transmit_size(MAX_TRANSMIT_SIZE) // Blocks are gossiped whole
+        .build()
+        .map_err(io::Error::other) // Temporary hack because `build` does not return a proper `std::error::Error`.
 }
 
 /// Represent a network node
@@ -36,7 +132,7 @@ pub struct NetworkNode {
 
 impl NetworkNode {
     /// Instanctiate a new network node
-    pub async fn new() -> Result<Self, Box<dyn Error>> {
+    pub async fn new() -> Result<Self, NetworkError> {
         let mut swarm = libp2p::SwarmBuilder::with_new_identity()
             .with_tokio()
             .with_tcp(
@@ -46,33 +142,37 @@ impl NetworkNode {
             )?
             .with_quic()
             .with_behaviour(|key| {
-                // To content-address message, we can take the hash of message and use it as an ID.
-                let message_id_fn = |message: &gossipsub::Message| {
-                    let mut s = DefaultHasher::new();
-                    message.data.hash(&mut s);
-                    gossipsub::MessageId::from(s.finish().to_string())
-                };
-
-                // Set a custom gossipsub configuration
-                let gossipsub_config = gossipsub::ConfigBuilder::default()
-                    .heartbeat_interval(Duration::from_secs(10)) // This is set to aid debugging by not cluttering the log space
-                    .validation_mode(gossipsub::ValidationMode::Strict) // This sets the kind of message validation. The default is Strict (enforce message signing)
-                    .message_id_fn(message_id_fn) // content-address messages. No two messages of the same content will be propagated.
-                    .build()
-                    .map_err(|msg| io::Error::new(io::ErrorKind::Other, msg))?; // Temporary hack because `build` does not return a proper `std::error::Error`.
-
                 // build a gossipsub network behaviour
                 let gossipsub = gossipsub::Behaviour::new(
                     gossipsub::MessageAuthenticity::Signed(key.clone()),
-                    gossipsub_config,
+                    gossipsub_config()?,
                 )?;
 
                 let mdns = mdns::tokio::Behaviour::new(
                     mdns::Config::default(),
                     key.public().to_peer_id(),
                 )?;
-                Ok(MyBehaviour { gossipsub, mdns })
-            })?
+
+                // Balance queries need an answer, so they use a request-response protocol
+                let balance = request_response::json::Behaviour::new(
+                    [(BALANCE_PROTOCOL, ProtocolSupport::Full)],
+                    request_response::Config::default(),
+                );
+
+                // Late joining nodes download the blocks they miss by range
+                let sync = request_response::Behaviour::new(
+                    [(SYNC_PROTOCOL, ProtocolSupport::Full)],
+                    request_response::Config::default(),
+                );
+
+                Ok(MyBehaviour {
+                    gossipsub,
+                    mdns,
+                    balance,
+                    sync,
+                })
+            })
+            .map_err(|e| NetworkError::Behaviour(e.into()))?
             .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
             .build();
 
@@ -80,7 +180,7 @@ impl NetworkNode {
         let topics = [
             ACCOUNT_CREATION_TOPIC.clone(),
             TRANSFER_TOPIC.clone(),
-            BALANCE_QUERY_TOPIC.clone(),
+            BLOCK_TOPIC.clone(),
         ];
 
         for topic in topics {
@@ -91,55 +191,278 @@ impl NetworkNode {
     }
 
     /// Setup the node to listen for incoming messages
-    pub async fn listen(&mut self) -> Result<(), Box<dyn Error>> {
+    pub async fn listen(&mut self) -> Result<(), NetworkError> {
         // Listen on all interfaces and whatever port the OS assigns
         self.swarm
             .listen_on("/ip4/0.0.0.0/udp/0/quic-v1".parse()?)?;
         self.swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
 
-        // Kick it off
+        Ok(())
+    }
+
+    /// Drive the network until a message is received from a peer
+    ///
+    /// Peer discovery is handled here, only the messages meant for the node are returned.
+    pub async fn next_event(&mut self) -> NetworkEvent {
         loop {
-            select! {
-                event = self.swarm.select_next_some() => match event {
-                    SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
-                        for (peer_id, _multiaddr) in list {
-                            println!("mDNS discovered a new peer: {peer_id}");
-                            self.swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
+            match self.swarm.select_next_some().await {
+                SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
+                    for (peer_id, multiaddr) in list {
+                        println!("mDNS discovered a new peer: {peer_id}");
+                        let behaviour = self.swarm.behaviour_mut();
+                        behaviour.gossipsub.add_explicit_peer(&peer_id);
+                        behaviour.balance.add_address(&peer_id, multiaddr.clone());
+                        behaviour.sync.add_address(&peer_id, multiaddr);
+                    }
+                }
+                SwarmEvent::ConnectionEstablished {
+                    peer_id,
+                    num_established,
+                    ..
+                } if num_established.get() == 1 => {
+                    return NetworkEvent::PeerConnected { peer_id };
+                }
+                SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Expired(list))) => {
+                    for (peer_id, multiaddr) in list {
+                        println!("mDNS discover peer has expired: {peer_id}");
+                        let behaviour = self.swarm.behaviour_mut();
+                        behaviour.gossipsub.remove_explicit_peer(&peer_id);
+                        behaviour.balance.remove_address(&peer_id, &multiaddr);
+                        behaviour.sync.remove_address(&peer_id, &multiaddr);
+                    }
+                }
+                SwarmEvent::NewListenAddr { address, .. } => {
+                    println!("Local node is listening on {address}");
+                }
+                SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                    propagation_source: peer_id,
+                    message_id: id,
+                    message,
+                })) => {
+                    if message.topic == ACCOUNT_CREATION_TOPIC.hash()
+                        || message.topic == TRANSFER_TOPIC.hash()
+                    {
+                        match Transaction::from_bytes(&message.data) {
+                            Ok(transaction) => {
+                                return NetworkEvent::Transaction {
+                                    peer_id,
+                                    transaction,
+                                }
+                            }
+                            Err(e) => println!(
+                                "Invalid transaction with id: {id} from peer: {peer_id}: {e}"
+                            ),
                         }
-                    },
-                    SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Expired(list))) => {
-                        for (peer_id, _multiaddr) in list {
-                            println!("mDNS discover peer has expired: {peer_id}");
-                            self.swarm.behaviour_mut().gossipsub.remove_explicit_peer(&peer_id);
+                    } else if message.topic == BLOCK_TOPIC.hash() {
+                        match Block::from_bytes(&message.data) {
+                            Ok(block) => return NetworkEvent::Block { peer_id, block },
+                            Err(e) => {
+                                println!("Invalid block with id: {id} from peer: {peer_id}: {e}")
+                            }
                         }
-                    },
-                    SwarmEvent::NewListenAddr { address, .. } => {
-                        println!("Local node is listening on {address}");
-                    }
-                    SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(gossipsub::Event::Message {
-                        propagation_source: peer_id,
-                        message_id: id,
-                        message,
-                    })) => println!(
+                    } else {
+                        println!(
                             "Got message: {} with id: {id} from peer: {peer_id}",
                             String::from_utf8_lossy(&message.data),
-                        ),
-                    _ => {}
+                        )
+                    }
                 }
+                SwarmEvent::Behaviour(MyBehaviourEvent::Balance(
+                    request_response::Event::Message { peer, message },
+                )) => match message {
+                    request_response::Message::Request {
+                        request, channel, ..
+                    } => {
+                        return NetworkEvent::BalanceRequest {
+                            peer_id: peer,
+                            request,
+                            channel,
+                        }
+                    }
+                    request_response::Message::Response {
+                        request_id,
+                        response,
+                    } => {
+                        return NetworkEvent::BalanceResponse {
+                            peer_id: peer,
+                            request_id,
+                            response,
+                        }
+                    }
+                },
+                SwarmEvent::Behaviour(MyBehaviourEvent::Balance(
+                    request_response::Event::OutboundFailure { peer, error, .. },
+                )) => println!("Balance query to peer: {peer} failed: {error}"),
+                SwarmEvent::Behaviour(MyBehaviourEvent::Sync(
+                    request_response::Event::Message { peer, message },
+                )) => match message {
+                    request_response::Message::Request {
+                        request, channel, ..
+                    } => {
+                        return NetworkEvent::BlocksRequest {
+                            peer_id: peer,
+                            request,
+                            channel,
+                        }
+                    }
+                    request_response::Message::Response {
+                        request_id,
+                        response,
+                    } => {
+                        return NetworkEvent::BlocksResponse {
+                            peer_id: peer,
+                            request_id,
+                            response,
+                        }
+                    }
+                },
+                SwarmEvent::Behaviour(MyBehaviourEvent::Sync(
+                    request_response::Event::OutboundFailure { peer, error, .. },
+                )) => println!("Blocks request to peer: {peer} failed: {error}"),
+                _ => {}
             }
         }
     }
 
-    /// Send a balance query message to the network
-    pub fn send_balance_query(&mut self) {
-        // Publish the message on the account balances topic
-        if let Err(e) = self
-            .swarm
+    /// Publish a transaction on the topic matching its type
+    ///
+    /// Coinbases are not published, they only travel in the block they start.
+    pub fn publish_transaction(
+        &mut self,
+        transaction: &Transaction,
+    ) -> Result<(), gossipsub::PublishError> {
+        let topic = match transaction {
+            Transaction::AccountCreation(_) => ACCOUNT_CREATION_TOPIC.clone(),
+            Transaction::Transfer(_) => TRANSFER_TOPIC.clone(),
+            Transaction::Coinbase(_) => return Ok(()),
+        };
+        let data = transaction.to_bytes();
+
+        self.swarm
+            .behaviour_mut()
+            .gossipsub
+            .publish(topic, data)
+            .map(|_| ())
+    }
+
+    /// Announce a newly mined block to the peers
+    pub fn publish_block(&mut self, block: &Block) -> Result<(), gossipsub::PublishError> {
+        let data = block.to_bytes();
+
+        self.swarm
             .behaviour_mut()
             .gossipsub
-            .publish(BALANCE_QUERY_TOPIC.clone(), "coucou".as_bytes())
-        {
-            println!("Publish error: {e}")
+            .publish(BLOCK_TOPIC.clone(), data)
+            .map(|_| ())
+    }
+
+    /// Ask a peer for a range of blocks
+    ///
+    /// The answer is returned by `next_event` as a `NetworkEvent::BlocksResponse`.
+    pub fn send_blocks_request(
+        &mut self,
+        peer_id: &PeerId,
+        request: BlocksRequest,
+    ) -> OutboundRequestId {
+        self.swarm
+            .behaviour_mut()
+            .sync
+            .send_request(peer_id, request)
+    }
+
+    /// Answer a blocks request received from a peer
+    pub fn send_blocks_response(
+        &mut self,
+        channel: ResponseChannel<BlocksResponse>,
+        response: BlocksResponse,
+    ) {
+        // Fails only if the peer is gone, and there is no one left to answer
+        let _ = self
+            .swarm
+            .behaviour_mut()
+            .sync
+            .send_response(channel, response);
+    }
+
+    /// Ask a peer for the balance of an account
+    ///
+    /// The answer is returned by `next_event` as a `NetworkEvent::BalanceResponse`.
+    pub fn send_balance_query(
+        &mut self,
+        peer_id: &PeerId,
+        account_id: String,
+    ) -> OutboundRequestId {
+        self.swarm
+            .behaviour_mut()
+            .balance
+            .send_request(peer_id, BalanceRequest { account_id })
+    }
+
+    /// Answer a balance query received from a peer
+    pub fn send_balance_response(
+        &mut self,
+        channel: ResponseChannel<BalanceResponse>,
+        response: BalanceResponse,
+    ) {
+        // Fails only if the peer is gone, and there is no one left to answer
+        let _ = self
+            .swarm
+            .behaviour_mut()
+            .balance
+            .send_response(channel, response);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blockchain::{blockchain::Blockchain, keys::test_utils::filler_transaction};
+
+    #[test]
+    fn test_gossip_block_at_size_limit() {
+        let mut blockchain = Blockchain::default();
+        let filler = filler_transaction(&blockchain, MAX_BLOCK_SIZE);
+        let block = blockchain.add_block(vec![filler]).unwrap().clone();
+        assert_eq!(block.to_bytes().len(), MAX_BLOCK_SIZE);
+
+        let publish = |config: gossipsub::Config| {
+            let mut gossipsub: gossipsub::Behaviour = gossipsub::Behaviour::new(
+                gossipsub::MessageAuthenticity::Signed(
+                    libp2p::identity::Keypair::generate_ed25519(),
+                ),
+                config,
+            )
+            .unwrap();
+            gossipsub.publish(BLOCK_TOPIC.clone(), block.to_bytes())
         };
+
+        // The block fits, but there is no peer to send it to
+        assert!(matches!(
+            publish(gossipsub_config().unwrap()),
+            Err(gossipsub::PublishError::InsufficientPeers)
+        ));
+        assert!(matches!(
+            publish(gossipsub::Config::default()),
+            Err(gossipsub::PublishError::MessageTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let error = NetworkError::from("invalid".parse::<libp2p::Multiaddr>().unwrap_err());
+        assert_eq!(
+            error.to_string(),
+            "invalid listen address: invalid multiaddr"
+        );
+        assert_eq!(
+            NetworkError::Behaviour("no mDNS socket".into()).to_string(),
+            "could not set up the network behaviour: no mDNS socket"
+        );
+        let address = "/memory/1".parse().unwrap();
+        let error = NetworkError::from(libp2p::TransportError::MultiaddrNotSupported(address));
+        assert_eq!(
+            error.to_string(),
+            "could not listen: Multiaddr is not supported: /memory/1"
+        );
     }
 }
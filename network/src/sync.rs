@@ -0,0 +1,359 @@
+use async_trait::async_trait;
+use blockchain::{
+    block::{Block, MAX_BLOCK_SIZE},
+    blockchain::Blockchain,
+    encoding::{Decode, DecodeError, Decoder, Encode},
+};
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::{request_response, StreamProtocol};
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Name of the request-response protocol used to download missing blocks
+pub const SYNC_PROTOCOL: StreamProtocol = StreamProtocol::new("/b/sync/2");
+
+/// Maximum number of blocks sent in a single response
+pub const MAX_BLOCKS_PER_REQUEST: usize = 100;
+
+/// Maximum size of an encoded request, in bytes
+const MAX_REQUEST_SIZE: u64 = 1024;
+
+/// Maximum size of an encoded response, in bytes
+///
+/// Responses are cut to fit, holding fewer than `MAX_BLOCKS_PER_REQUEST` blocks when they
+/// are large, but always at least one block of `MAX_BLOCK_SIZE`.
+const MAX_RESPONSE_SIZE: usize = 10 * 1024 * 1024;
+
+/// Size of the length prefix of the list of blocks in a response, in bytes
+const LIST_PREFIX_SIZE: usize = 4;
+
+const _: () = assert!(MAX_RESPONSE_SIZE >= LIST_PREFIX_SIZE + MAX_BLOCK_SIZE);
+
+/// Represents a request for a range of blocks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlocksRequest {
+    /// Id of the first requested block
+    pub from_id: i64,
+    /// Number of requested blocks, capped to `MAX_BLOCKS_PER_REQUEST`
+    pub count: usize,
+}
+
+/// Represents the blocks sent back for a `BlocksRequest`
+///
+/// It contains fewer blocks than requested when the peer blockchain is shorter, or when the
+/// requested blocks don't fit in `MAX_RESPONSE_SIZE`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlocksResponse {
+    pub blocks: Vec<Block>,
+}
+
+impl BlocksRequest {
+    /// Request the blocks following the tip of the local blockchain
+    pub fn after_tip(blockchain: &Blockchain) -> BlocksRequest {
+        BlocksRequest {
+            from_id: blockchain.tip().header.id + 1,
+            count: MAX_BLOCKS_PER_REQUEST,
+        }
+    }
+
+    /// Request the blocks following the given one
+    pub fn after(block: &Block) -> BlocksRequest {
+        BlocksRequest {
+            from_id: block.header.id + 1,
+            count: MAX_BLOCKS_PER_REQUEST,
+        }
+    }
+
+    /// Request the blocks preceding the given one, to find where its branch
+    /// forks from the local blockchain
+    pub fn before(block: &Block) -> BlocksRequest {
+        BlocksRequest {
+            from_id: (block.header.id - MAX_BLOCKS_PER_REQUEST as i64).max(1),
+            count: MAX_BLOCKS_PER_REQUEST,
+        }
+    }
+}
+
+impl BlocksResponse {
+    /// Answer a blocks request from the local blockchain, with as many blocks as fit in a response
+    pub fn from_blockchain(blockchain: &Blockchain, request: &BlocksRequest) -> BlocksResponse {
+        let count = request.count.min(MAX_BLOCKS_PER_REQUEST);
+        let mut size = LIST_PREFIX_SIZE;
+
+        BlocksResponse {
+            blocks: blockchain
+                .get_blocks(request.from_id, count)
+                .into_iter()
+                .take_while(|block| {
+                    size += block.to_bytes().len();
+                    size <= MAX_RESPONSE_SIZE
+                })
+                .collect(),
+        }
+    }
+
+    /// Check if the peer may have more blocks after the ones sent
+    ///
+    /// It is the case when the response is cut by the number of blocks, or may be cut by size.
+    pub fn is_full(&self) -> bool {
+        self.blocks.len() >= MAX_BLOCKS_PER_REQUEST
+            || self.to_bytes().len() + MAX_BLOCK_SIZE > MAX_RESPONSE_SIZE
+    }
+}
+
+impl Encode for BlocksRequest {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.from_id.encode(buffer);
+        (self.count as u64).encode(buffer);
+    }
+}
+
+impl Decode for BlocksRequest {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(BlocksRequest {
+            from_id: i64::decode(decoder)?,
+            count: usize::try_from(u64::decode(decoder)?).unwrap_or(usize::MAX),
+        })
+    }
+}
+
+impl Encode for BlocksResponse {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.blocks.encode(buffer);
+    }
+}
+
+impl Decode for BlocksResponse {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(BlocksResponse {
+            blocks: Vec::decode(decoder)?,
+        })
+    }
+}
+
+/// Represents the codec of the sync protocol
+///
+/// Requests and responses are sent with the canonical binary encoding of the blockchain
+/// crate, each one filling its stream.
+#[derive(Debug, Clone, Default)]
+pub struct SyncCodec;
+
+#[async_trait]
+impl request_response::Codec for SyncCodec {
+    type Protocol = StreamProtocol;
+    type Request = BlocksRequest;
+    type Response = BlocksResponse;
+
+    async fn read_request<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<BlocksRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io, MAX_REQUEST_SIZE).await
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+    ) -> io::Result<BlocksResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io, MAX_RESPONSE_SIZE as u64).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        request: BlocksRequest,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.write_all(&request.to_bytes()).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        response: BlocksResponse,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.write_all(&response.to_bytes()).await
+    }
+}
+
+/// Read a message filling the rest of the stream, up to `max_size` bytes
+async fn read_message<M, T>(io: &mut T, max_size: u64) -> io::Result<M>
+where
+    M: Decode,
+    T: AsyncRead + Unpin + Send,
+{
+    let mut bytes = Vec::new();
+    io.take(max_size).read_to_end(&mut bytes).await?;
+    M::from_bytes(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blockchain::keys::test_utils::filler_transaction;
+    use libp2p::request_response::Codec;
+
+    #[test]
+    fn test_after_tip() {
+        let mut blockchain = Blockchain::default();
+        blockchain.add_block(vec![]).unwrap();
+
+        assert_eq!(
+            BlocksRequest::after_tip(&blockchain),
+            BlocksRequest {
+                from_id: 2,
+                count: MAX_BLOCKS_PER_REQUEST
+            }
+        );
+    }
+
+    #[test]
+    fn test_after_and_before() {
+        let mut blockchain = Blockchain::default();
+        for _ in 0..MAX_BLOCKS_PER_REQUEST + 10 {
+            blockchain.add_block(vec![]).unwrap();
+        }
+
+        assert_eq!(
+            BlocksRequest::after(&blockchain.block_by_height(5).unwrap()).from_id,
+            6
+        );
+        assert_eq!(
+            BlocksRequest::before(&blockchain.block_by_height(5).unwrap()).from_id,
+            1
+        );
+        assert_eq!(
+            BlocksRequest::before(blockchain.tip()).from_id,
+            blockchain.tip().header.id - MAX_BLOCKS_PER_REQUEST as i64
+        );
+    }
+
+    #[test]
+    fn test_codec() {
+        let mut blockchain = Blockchain::default();
+        blockchain.add_block(vec![]).unwrap();
+        let request = BlocksRequest {
+            from_id: 1,
+            count: MAX_BLOCKS_PER_REQUEST,
+        };
+        let response = BlocksResponse::from_blockchain(&blockchain, &request);
+
+        futures::executor::block_on(async {
+            let mut codec = SyncCodec;
+            let mut data = Vec::new();
+            codec
+                .write_request(&SYNC_PROTOCOL, &mut data, request.clone())
+                .await
+                .unwrap();
+            assert_eq!(
+                codec
+                    .read_request(&SYNC_PROTOCOL, &mut data.as_slice())
+                    .await
+                    .unwrap(),
+                request
+            );
+
+            let mut data = Vec::new();
+            codec
+                .write_response(&SYNC_PROTOCOL, &mut data, response.clone())
+                .await
+                .unwrap();
+            assert_eq!(
+                codec
+                    .read_response(&SYNC_PROTOCOL, &mut data.as_slice())
+                    .await
+                    .unwrap(),
+                response
+            );
+
+            // Truncated response
+            assert_eq!(
+                codec
+                    .read_response(&SYNC_PROTOCOL, &mut &data[..data.len() - 1])
+                    .await
+                    .unwrap_err()
+                    .kind(),
+                io::ErrorKind::InvalidData
+            );
+        });
+    }
+
+    #[test]
+    fn test_from_blockchain() {
+        let mut blockchain = Blockchain::default();
+        for _ in 0..MAX_BLOCKS_PER_REQUEST + 10 {
+            blockchain.add_block(vec![]).unwrap();
+        }
+
+        let response = BlocksResponse::from_blockchain(
+            &blockchain,
+            &BlocksRequest {
+                from_id: 1,
+                count: 2,
+            },
+        );
+        assert_eq!(response.blocks.len(), 2);
+        assert_eq!(response.blocks[0].header.id, 1);
+        assert!(!response.is_full());
+
+        // Count is capped
+        let response = BlocksResponse::from_blockchain(
+            &blockchain,
+            &BlocksRequest {
+                from_id: 1,
+                count: usize::MAX,
+            },
+        );
+        assert_eq!(response.blocks.len(), MAX_BLOCKS_PER_REQUEST);
+        assert!(response.is_full());
+    }
+
+    #[test]
+    fn test_from_blockchain_at_size_limit() {
+        let mut blockchain = Blockchain::default();
+        for _ in 0..MAX_RESPONSE_SIZE / MAX_BLOCK_SIZE + 1 {
+            let filler = filler_transaction(&blockchain, MAX_BLOCK_SIZE);
+            blockchain.add_block(vec![filler]).unwrap();
+        }
+        let request = BlocksRequest {
+            from_id: 1,
+            count: MAX_BLOCKS_PER_REQUEST,
+        };
+
+        // The blocks past the size limit are left for the next request
+        let response = BlocksResponse::from_blockchain(&blockchain, &request);
+        assert_eq!(
+            response.blocks.len(),
+            MAX_RESPONSE_SIZE / MAX_BLOCK_SIZE - 1
+        );
+        assert!(response.to_bytes().len() <= MAX_RESPONSE_SIZE);
+        assert!(response.is_full());
+
+        futures::executor::block_on(async {
+            let mut codec = SyncCodec;
+            let mut data = Vec::new();
+            codec
+                .write_response(&SYNC_PROTOCOL, &mut data, response.clone())
+                .await
+                .unwrap();
+            assert_eq!(
+                codec
+                    .read_response(&SYNC_PROTOCOL, &mut data.as_slice())
+                    .await
+                    .unwrap(),
+                response
+            );
+        });
+    }
+}
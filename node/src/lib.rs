@@ -1 +1,5 @@
+pub mod mempool;
 pub mod node;
+pub mod p2p;
+pub mod rpc;
+pub mod scheduler;
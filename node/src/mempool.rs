@@ -0,0 +1,831 @@
+use blockchain::{
+    amount::Amount,
+    block::{Block, MAX_BLOCK_SIZE},
+    encoding::Encode,
+    transaction::Transaction,
+    validation::{TransactionError, TransactionValidator},
+};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use thiserror::Error;
+
+/// Default maximum number of transactions in a mined block
+pub const DEFAULT_MAX_BLOCK_TRANSACTIONS: usize = 1_000;
+
+/// Default maximum size of the encoded transactions of a mined block, in bytes
+///
+/// It leaves room for the header and the coinbase within `MAX_BLOCK_SIZE`.
+pub const DEFAULT_MAX_BLOCK_BYTES: usize = MAX_BLOCK_SIZE - BLOCK_OVERHEAD;
+
+/// Room left in a block for what is not a pooled transaction, in bytes
+const BLOCK_OVERHEAD: usize = 4 * 1024;
+
+/// Default maximum number of pooled transactions
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Default time a transaction can wait in the mempool after its creation, in milliseconds
+pub const DEFAULT_MAX_AGE: i64 = 60 * 60 * 1000;
+
+/// Represents how many transactions the node puts in the blocks it mines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockLimits {
+    pub max_transactions: usize,
+    /// Maximum size of the canonical encoding of the block transactions
+    pub max_bytes: usize,
+}
+
+impl Default for BlockLimits {
+    fn default() -> Self {
+        BlockLimits {
+            max_transactions: DEFAULT_MAX_BLOCK_TRANSACTIONS,
+            max_bytes: DEFAULT_MAX_BLOCK_BYTES,
+        }
+    }
+}
+
+/// Represents the bounds of the mempool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MempoolConfig {
+    /// Maximum number of pooled transactions, the lowest fees are evicted past it
+    pub capacity: usize,
+    /// Time after `created_at` when a transaction is dropped, in milliseconds
+    pub max_age: i64,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        MempoolConfig {
+            capacity: DEFAULT_CAPACITY,
+            max_age: DEFAULT_MAX_AGE,
+        }
+    }
+}
+
+/// Represents the size of the mempool and the transactions it evicted since it was created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MempoolMetrics {
+    /// Number of pooled transactions
+    pub size: usize,
+    /// Size of the canonical encoding of the pooled transactions, in bytes
+    pub bytes: usize,
+    /// Transactions evicted to make room for ones paying a higher fee
+    pub evicted_for_capacity: u64,
+    /// Transactions dropped after their maximum age
+    pub expired: u64,
+    /// Transactions dropped because they are no longer valid on top of the best chain
+    pub invalidated: u64,
+}
+
+/// Represents the reason the mempool refused a transaction
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MempoolError {
+    /// The transaction is not valid on top of the blockchain and the pooled transactions
+    #[error(transparent)]
+    Transaction(#[from] TransactionError),
+    /// A transaction with the same id or hash is already pooled
+    #[error("transaction {0} is already in the mempool")]
+    Duplicate(String),
+    /// The transaction was created more than the maximum age ago
+    #[error("transaction {transaction_id} expired at {expired_at}")]
+    Expired {
+        transaction_id: String,
+        expired_at: i64,
+    },
+    /// The mempool is full of transactions paying at least the same fee
+    #[error("the mempool is full and transaction {transaction_id} pays a fee of {fee}, no more than the pooled ones")]
+    Full { transaction_id: String, fee: Amount },
+}
+
+/// Represents the transactions waiting to be included in a block
+///
+/// Transactions are kept in an order they can be applied in: the order they arrived in, except
+/// for the transfers of a sender which are ordered by nonce. Blocks are filled by fee instead,
+/// see `select`.
+///
+/// The mempool holds each transaction id and hash once, and at most `capacity` transactions. Transactions
+/// leave it when they are mined, expire, or become invalid on top of the best chain, see
+/// `MempoolMetrics`.
+#[derive(Debug, Clone, Default)]
+pub struct Mempool {
+    transactions: Vec<Transaction>,
+    pooled: PooledSet,
+    config: MempoolConfig,
+    metrics: MempoolMetrics,
+}
+
+/// Represents the ids, hashes and total size of the pooled transactions, kept up to date as
+/// transactions come and go so lookups and metrics don't walk the pool
+#[derive(Debug, Clone, Default)]
+struct PooledSet {
+    ids: HashSet<String>,
+    hashes: HashSet<String>,
+    /// Size of the canonical encoding of the pooled transactions, in bytes
+    bytes: usize,
+}
+
+impl PooledSet {
+    fn contains(&self, transaction: &Transaction) -> bool {
+        self.ids.contains(transaction.id()) || self.hashes.contains(&transaction.hash())
+    }
+
+    fn insert(&mut self, transaction: &Transaction) {
+        self.ids.insert(transaction.id().to_string());
+        self.hashes.insert(transaction.hash());
+        self.bytes += transaction.to_bytes().len();
+    }
+
+    fn remove(&mut self, transaction: &Transaction) {
+        self.ids.remove(transaction.id());
+        self.hashes.remove(&transaction.hash());
+        self.bytes -= transaction.to_bytes().len();
+    }
+}
+
+impl Mempool {
+    /// Create an empty mempool with the default bounds
+    pub fn new() -> Self {
+        Mempool::default()
+    }
+
+    /// Create an empty mempool with the given bounds
+    pub fn with_config(config: MempoolConfig) -> Self {
+        Mempool {
+            config,
+            ..Mempool::default()
+        }
+    }
+
+    pub fn config(&self) -> MempoolConfig {
+        self.config
+    }
+
+    /// Get the current size of the mempool and the number of transactions evicted so far
+    pub fn metrics(&self) -> MempoolMetrics {
+        MempoolMetrics {
+            size: self.transactions.len(),
+            bytes: self.pooled.bytes,
+            ..self.metrics
+        }
+    }
+
+    /// Get the pooled transactions, in an order they can be applied in
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Transaction> {
+        self.transactions.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// Checks if a transaction with that id is pooled
+    pub fn contains(&self, transaction_id: &str) -> bool {
+        self.pooled.ids.contains(transaction_id)
+    }
+
+    /// Checks if a transaction with that hash is pooled
+    pub fn contains_hash(&self, hash: &str) -> bool {
+        self.pooled.hashes.contains(hash)
+    }
+
+    /// Checks that a transaction is neither pooled already nor expired at `now`, in milliseconds
+    ///
+    /// It is done before validating the transaction, as a duplicate is never valid after the
+    /// pooled one.
+    pub fn check(&self, transaction: &Transaction, now: i64) -> Result<(), MempoolError> {
+        if self.pooled.contains(transaction) {
+            return Err(MempoolError::Duplicate(transaction.id().to_string()));
+        }
+        let expired_at = self.expires_at(transaction);
+        if expired_at <= now {
+            return Err(MempoolError::Expired {
+                transaction_id: transaction.id().to_string(),
+                expired_at,
+            });
+        }
+        Ok(())
+    }
+
+    /// Add a transaction, before the pooled transfers of the same sender with a higher nonce
+    ///
+    /// When the mempool is full, the pooled transaction paying the lowest fee that no other
+    /// depends on is evicted, the newest one on ties. The transaction is refused instead if it
+    /// doesn't pay more.
+    ///
+    /// The transaction is not validated, see `Node::submit_transaction`, but coinbases are refused
+    /// as they only belong to the block they start.
+    pub fn insert(&mut self, transaction: Transaction) -> Result<(), MempoolError> {
+        if let Transaction::Coinbase(coinbase) = &transaction {
+            return Err(TransactionError::UnexpectedCoinbase(coinbase.id.clone()).into());
+        }
+        if self.pooled.contains(&transaction) {
+            return Err(MempoolError::Duplicate(transaction.id().to_string()));
+        }
+        if self.transactions.len() >= self.config.capacity {
+            match self.eviction_candidate(&transaction) {
+                Some(index) if self.transactions[index].fee() < transaction.fee() => {
+                    self.remove(index);
+                    self.metrics.evicted_for_capacity += 1;
+                }
+                _ => {
+                    return Err(MempoolError::Full {
+                        transaction_id: transaction.id().to_string(),
+                        fee: transaction.fee(),
+                    })
+                }
+            }
+        }
+
+        let position = match &transaction {
+            Transaction::Transfer(transfer) => self.transactions.iter().position(|pooled| {
+                matches!(pooled, Transaction::Transfer(pooled)
+                    if pooled.from_account == transfer.from_account && pooled.nonce > transfer.nonce)
+            }),
+            Transaction::AccountCreation(_) | Transaction::Coinbase(_) => None,
+        };
+        self.pooled.insert(&transaction);
+        match position {
+            Some(position) => self.transactions.insert(position, transaction),
+            None => self.transactions.push(transaction),
+        }
+        Ok(())
+    }
+
+    /// Find the pooled transaction to evict for a new one: the lowest fee among the account
+    /// creations and the last pooled transfer of every other sender
+    fn eviction_candidate(&self, transaction: &Transaction) -> Option<usize> {
+        let sender = match transaction {
+            Transaction::Transfer(transfer) => Some(transfer.from_account.as_str()),
+            Transaction::AccountCreation(_) | Transaction::Coinbase(_) => None,
+        };
+        let mut last_transfers: HashMap<&str, usize> = HashMap::new();
+        let mut candidates = Vec::new();
+        for (index, pooled) in self.transactions.iter().enumerate() {
+            match pooled {
+                Transaction::Transfer(transfer)
+                    if Some(transfer.from_account.as_str()) != sender =>
+                {
+                    last_transfers.insert(&transfer.from_account, index);
+                }
+                Transaction::Transfer(_) => {}
+                Transaction::AccountCreation(_) | Transaction::Coinbase(_) => {
+                    candidates.push(index)
+                }
+            }
+        }
+        candidates
+            .into_iter()
+            .chain(last_transfers.into_values())
+            .min_by_key(|&index| (self.transactions[index].fee(), Reverse(index)))
+    }
+
+    fn remove(&mut self, index: usize) -> Transaction {
+        let transaction = self.transactions.remove(index);
+        self.pooled.remove(&transaction);
+        transaction
+    }
+
+    /// Keep only the transactions matching the predicate
+    pub fn retain(&mut self, mut predicate: impl FnMut(&Transaction) -> bool) {
+        let pooled = &mut self.pooled;
+        self.transactions.retain(|transaction| {
+            predicate(transaction) || {
+                pooled.remove(transaction);
+                false
+            }
+        });
+    }
+
+    /// Remove every transaction, in an order they can be applied in
+    pub fn take(&mut self) -> Vec<Transaction> {
+        self.pooled = PooledSet::default();
+        std::mem::take(&mut self.transactions)
+    }
+
+    /// Get the time a transaction expires at, in milliseconds
+    fn expires_at(&self, transaction: &Transaction) -> i64 {
+        transaction.created_at().saturating_add(self.config.max_age)
+    }
+
+    /// Drop the transactions expired at `now`, in milliseconds
+    pub fn expire(&mut self, now: i64) {
+        let before = self.transactions.len();
+        let max_age = self.config.max_age;
+        self.retain(|transaction| transaction.created_at().saturating_add(max_age) > now);
+        self.metrics.expired += (before - self.transactions.len()) as u64;
+    }
+
+    /// Remove the transactions included in blocks that joined the best chain
+    pub fn remove_included(&mut self, blocks: &[Block]) {
+        let included: HashSet<&str> = blocks
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .map(Transaction::id)
+            .collect();
+        self.retain(|transaction| !included.contains(transaction.id()));
+    }
+
+    /// Drop the transactions the validator refuses, after blocks changed its accounts
+    ///
+    /// Transfers whose nonce is ahead of their sender are kept, and so are the transactions
+    /// involving an account created by a pooled transaction.
+    pub fn evict_invalid(&mut self, mut validator: TransactionValidator) {
+        let created: HashSet<String> = self
+            .transactions
+            .iter()
+            .filter_map(|transaction| match transaction {
+                Transaction::AccountCreation(account_creation) => {
+                    Some(account_creation.account_id.clone())
+                }
+                Transaction::Transfer(_) | Transaction::Coinbase(_) => None,
+            })
+            .collect();
+        let before = self.transactions.len();
+        self.retain(|transaction| match validator.validate(transaction) {
+            Ok(()) => true,
+            Err(TransactionError::AccountNotFound(account_id)) => created.contains(&account_id),
+            Err(e) => is_future_nonce(&e),
+        });
+        self.metrics.invalidated += (before - self.transactions.len()) as u64;
+    }
+
+    /// Remove the transactions of the next block from the mempool, highest fees first
+    ///
+    /// Transactions are picked by fee among the ones that can be applied next: the account
+    /// creations, and the transfer with the lowest nonce of every sender. Ties go to the oldest
+    /// pooled transaction. Picking stops when the block is full.
+    ///
+    /// Transactions the validator refuses are dropped, except the ones waiting for a transfer
+    /// with a lower nonce or for a pooled account creation. Transactions that don't fit in the
+    /// block stay in the mempool.
+    pub fn select(
+        &mut self,
+        mut validator: TransactionValidator,
+        limits: BlockLimits,
+    ) -> Vec<Transaction> {
+        // The transfers of every sender, in nonce order, behind the candidates
+        let mut queues: HashMap<&str, VecDeque<usize>> = HashMap::new();
+        let mut candidates = BinaryHeap::new();
+        for (index, transaction) in self.transactions.iter().enumerate() {
+            match transaction {
+                Transaction::Transfer(transfer) => queues
+                    .entry(&transfer.from_account)
+                    .or_default()
+                    .push_back(index),
+                Transaction::AccountCreation(_) | Transaction::Coinbase(_) => {
+                    candidates.push((transaction.fee(), Reverse(index)))
+                }
+            }
+        }
+        let senders: Vec<&str> = queues.keys().copied().collect();
+        let mut next_transfer = |sender: &str, candidates: &mut BinaryHeap<_>| {
+            if let Some(index) = queues.get_mut(sender).and_then(VecDeque::pop_front) {
+                candidates.push((self.transactions[index].fee(), Reverse(index)));
+            }
+        };
+        for sender in senders {
+            next_transfer(sender, &mut candidates);
+        }
+
+        let mut selected = Vec::new();
+        let mut dropped = HashSet::new();
+        // Transfers from or to accounts that may be created by a pooled transaction
+        let mut waiting = Vec::new();
+        let mut size = 0;
+        while let Some((fee, Reverse(index))) = candidates.pop() {
+            if selected.len() >= limits.max_transactions {
+                break;
+            }
+            let transaction = &self.transactions[index];
+            let transaction_size = transaction.to_bytes().len();
+            if size + transaction_size > limits.max_bytes {
+                // A smaller transaction may still fit
+                continue;
+            }
+
+            match (validator.validate(transaction), transaction) {
+                (Ok(()), Transaction::Transfer(transfer)) => {
+                    next_transfer(&transfer.from_account, &mut candidates);
+                    selected.push(index);
+                    size += transaction_size;
+                }
+                (Ok(()), Transaction::AccountCreation(_) | Transaction::Coinbase(_)) => {
+                    candidates.extend(waiting.drain(..));
+                    selected.push(index);
+                    size += transaction_size;
+                }
+                (Err(TransactionError::AccountNotFound(_)), _) => {
+                    waiting.push((fee, Reverse(index)))
+                }
+                (Err(e), _) if is_future_nonce(&e) => {}
+                (Err(_), transaction) => {
+                    if let Transaction::Transfer(transfer) = transaction {
+                        next_transfer(&transfer.from_account, &mut candidates);
+                    }
+                    dropped.insert(index);
+                }
+            }
+        }
+
+        // Waiting transfers are only kept while an account creation is left in the pool
+        let selected_set: HashSet<usize> = selected.iter().copied().collect();
+        let creation_left = self.transactions.iter().enumerate().any(|(index, pooled)| {
+            matches!(pooled, Transaction::AccountCreation(_))
+                && !selected_set.contains(&index)
+                && !dropped.contains(&index)
+        });
+        if !creation_left {
+            dropped.extend(waiting.into_iter().map(|(_, Reverse(index))| index));
+        }
+
+        let mut transactions: Vec<Option<Transaction>> =
+            self.take().into_iter().map(Some).collect();
+        let block = selected
+            .iter()
+            .map(|&index| {
+                transactions[index]
+                    .take()
+                    .expect("Transaction should be selected once")
+            })
+            .collect();
+        for (index, transaction) in transactions.into_iter().enumerate() {
+            if let Some(transaction) = transaction.filter(|_| !dropped.contains(&index)) {
+                self.pooled.insert(&transaction);
+                self.transactions.push(transaction);
+            }
+        }
+        self.metrics.invalidated += dropped.len() as u64;
+        block
+    }
+}
+
+/// Checks if a transaction is only invalid because its nonce is ahead of the next nonce of its sender.
+pub(crate) fn is_future_nonce(error: &TransactionError) -> bool {
+    matches!(error, TransactionError::InvalidNonce { nonce, expected, .. } if nonce > expected)
+}
+
+/// Adds the transactions one by one, skipping the ones refused by `Mempool::insert`
+impl Extend<Transaction> for Mempool {
+    fn extend<I: IntoIterator<Item = Transaction>>(&mut self, transactions: I) {
+        for transaction in transactions {
+            let _ = self.insert(transaction);
+        }
+    }
+}
+
+impl FromIterator<Transaction> for Mempool {
+    fn from_iter<I: IntoIterator<Item = Transaction>>(transactions: I) -> Self {
+        let mut mempool = Mempool::new();
+        mempool.extend(transactions);
+        mempool
+    }
+}
+
+impl<'a> IntoIterator for &'a Mempool {
+    type Item = &'a Transaction;
+    type IntoIter = std::slice::Iter<'a, Transaction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blockchain::blockchain::Blockchain;
+    use blockchain::keys::test_utils::{keypair, network_id};
+
+    fn blockchain() -> Blockchain {
+        let mut blockchain = Blockchain::default();
+        blockchain
+            .add_block(
+                ["account_1", "account_2", "account_3"]
+                    .into_iter()
+                    .map(|account_id| {
+                        Transaction::account_creation(
+                            account_id.to_string(),
+                            Amount::new(100),
+                            keypair(account_id).public_key(),
+                        )
+                    })
+                    .collect(),
+            )
+            .unwrap();
+        blockchain
+    }
+
+    fn transfer(from_account: &str, nonce: u64, fee: u64) -> Transaction {
+        Transaction::transfer(
+            &network_id(),
+            from_account.to_string(),
+            "account_3".to_string(),
+            Amount::new(10),
+            Amount::new(fee),
+            nonce,
+            &keypair(from_account),
+        )
+    }
+
+    /// Reference size of the pooled transactions, encoding every one of them
+    fn pooled_bytes(mempool: &Mempool) -> usize {
+        mempool
+            .iter()
+            .map(|transaction| transaction.to_bytes().len())
+            .sum()
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut mempool = Mempool::new();
+        let transfer_0 = transfer("account_1", 0, 0);
+        let transfer_1 = transfer("account_1", 1, 0);
+        let other = transfer("account_2", 0, 0);
+
+        mempool.extend([transfer_1.clone(), other.clone(), transfer_0.clone()]);
+
+        // The transfers of a sender are kept in nonce order
+        assert_eq!(mempool.transactions(), [transfer_0, transfer_1, other]);
+        assert_eq!(mempool.len(), 3);
+    }
+
+    #[test]
+    fn test_select_by_fee() {
+        let blockchain = blockchain();
+        let low_fee = transfer("account_1", 0, 1);
+        let high_fee = transfer("account_1", 1, 10);
+        let medium_fee = transfer("account_2", 0, 5);
+        let mut mempool: Mempool = [high_fee.clone(), low_fee.clone(), medium_fee.clone()]
+            .into_iter()
+            .collect();
+
+        // A transfer only comes after the lower nonces of its sender, whatever its fee
+        assert_eq!(
+            mempool.select(blockchain.transaction_validator(), BlockLimits::default()),
+            vec![medium_fee, low_fee, high_fee]
+        );
+        assert!(mempool.is_empty());
+    }
+
+    #[test]
+    fn test_select_limits() {
+        let blockchain = blockchain();
+        let transactions = [
+            transfer("account_1", 0, 1),
+            transfer("account_2", 0, 5),
+            transfer("account_1", 1, 10),
+        ];
+
+        let mut mempool: Mempool = transactions.iter().cloned().collect();
+        let limits = BlockLimits {
+            max_transactions: 2,
+            ..BlockLimits::default()
+        };
+        assert_eq!(
+            mempool.select(blockchain.transaction_validator(), limits),
+            vec![transactions[1].clone(), transactions[0].clone()]
+        );
+        assert_eq!(mempool.transactions(), [transactions[2].clone()]);
+
+        let mut mempool: Mempool = transactions.iter().cloned().collect();
+        let limits = BlockLimits {
+            max_bytes: transactions[0].to_bytes().len(),
+            ..BlockLimits::default()
+        };
+        assert_eq!(
+            mempool.select(blockchain.transaction_validator(), limits),
+            vec![transactions[1].clone()]
+        );
+        assert_eq!(
+            mempool.transactions(),
+            [transactions[0].clone(), transactions[2].clone()]
+        );
+    }
+
+    #[test]
+    fn test_select_waits_for_account_creation() {
+        let blockchain = blockchain();
+        let account_creation = Transaction::account_creation(
+            "account_4".to_string(),
+            Amount::ZERO,
+            keypair("account_4").public_key(),
+        );
+        let transfer = Transaction::transfer(
+            &network_id(),
+            "account_1".to_string(),
+            "account_4".to_string(),
+            Amount::new(10),
+            Amount::new(3),
+            0,
+            &keypair("account_1"),
+        );
+        let transactions = [account_creation.clone(), transfer.clone()];
+
+        let mut mempool: Mempool = transactions.iter().cloned().collect();
+        assert_eq!(
+            mempool.select(blockchain.transaction_validator(), BlockLimits::default()),
+            vec![account_creation.clone(), transfer.clone()]
+        );
+
+        // The transfer waits for the next block
+        let mut mempool: Mempool = transactions.iter().cloned().collect();
+        let limits = BlockLimits {
+            max_transactions: 1,
+            ..BlockLimits::default()
+        };
+        assert_eq!(
+            mempool.select(blockchain.transaction_validator(), limits),
+            vec![account_creation]
+        );
+        assert_eq!(mempool.transactions(), [transfer]);
+    }
+
+    #[test]
+    fn test_select_drops_invalid_transactions() {
+        let blockchain = blockchain();
+        let unknown_account = transfer("unknown", 0, 0);
+        let insufficient_funds = Transaction::transfer(
+            &network_id(),
+            "account_1".to_string(),
+            "account_3".to_string(),
+            Amount::new(100),
+            Amount::new(1),
+            0,
+            &keypair("account_1"),
+        );
+        let next_nonce = transfer("account_1", 1, 0);
+        let future_nonce = transfer("account_2", 1, 0);
+        let mut mempool: Mempool = [
+            unknown_account,
+            insufficient_funds,
+            next_nonce.clone(),
+            future_nonce.clone(),
+        ]
+        .into_iter()
+        .collect();
+
+        // Transfers with a future nonce are kept for a later block
+        assert_eq!(
+            mempool.select(blockchain.transaction_validator(), BlockLimits::default()),
+            vec![]
+        );
+        assert_eq!(mempool.transactions(), [next_nonce, future_nonce]);
+        assert_eq!(mempool.metrics().bytes, pooled_bytes(&mempool));
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let mut mempool = Mempool::new();
+        let transaction = transfer("account_1", 0, 0);
+        mempool.insert(transaction.clone()).unwrap();
+
+        let duplicate = Err(MempoolError::Duplicate(transaction.id().to_string()));
+        assert_eq!(
+            mempool.check(&transaction, transaction.created_at()),
+            duplicate
+        );
+        assert_eq!(mempool.insert(transaction.clone()), duplicate);
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.contains_hash(&transaction.hash()));
+        assert_eq!(mempool.metrics().bytes, pooled_bytes(&mempool));
+
+        // The id and hash can be pooled again once the transaction left
+        mempool.take();
+        assert!(!mempool.contains_hash(&transaction.hash()));
+        assert_eq!(mempool.metrics().bytes, 0);
+        assert_eq!(mempool.insert(transaction), Ok(()));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut mempool = Mempool::with_config(MempoolConfig {
+            capacity: 3,
+            ..MempoolConfig::default()
+        });
+        let low_fee = transfer("account_1", 0, 1);
+        let pending = transfer("account_2", 0, 1);
+        let last_pending = transfer("account_2", 1, 5);
+        mempool.extend([low_fee.clone(), pending.clone(), last_pending.clone()]);
+
+        // The fee has to be higher than the lowest evictable one
+        let same_fee = transfer("account_3", 0, 1);
+        assert_eq!(
+            mempool.insert(same_fee.clone()),
+            Err(MempoolError::Full {
+                transaction_id: same_fee.id().to_string(),
+                fee: Amount::new(1)
+            })
+        );
+
+        // The first transfer of account_2 is followed by another one, it stays
+        let high_fee = transfer("account_3", 0, 2);
+        assert_eq!(mempool.insert(high_fee.clone()), Ok(()));
+        assert_eq!(mempool.transactions(), [pending, last_pending, high_fee]);
+        assert!(!mempool.contains(low_fee.id()));
+        assert!(!mempool.contains_hash(&low_fee.hash()));
+        assert_eq!(mempool.metrics().evicted_for_capacity, 1);
+        assert_eq!(mempool.metrics().bytes, pooled_bytes(&mempool));
+    }
+
+    #[test]
+    fn test_expire() {
+        let mut mempool = Mempool::with_config(MempoolConfig {
+            max_age: 1_000,
+            ..MempoolConfig::default()
+        });
+        let created_at = |mut transaction: Transaction, created_at| {
+            match &mut transaction {
+                Transaction::AccountCreation(account_creation) => {
+                    account_creation.created_at = created_at
+                }
+                Transaction::Transfer(transfer) => transfer.created_at = created_at,
+                Transaction::Coinbase(coinbase) => coinbase.created_at = created_at,
+            }
+            transaction
+        };
+        let old = created_at(transfer("account_1", 0, 0), 1_000);
+        let recent = created_at(transfer("account_2", 0, 0), 1_500);
+        mempool.extend([old.clone(), recent.clone()]);
+
+        mempool.expire(1_999);
+        assert_eq!(mempool.len(), 2);
+        mempool.expire(2_000);
+        assert_eq!(mempool.transactions(), [recent]);
+        assert_eq!(mempool.metrics().expired, 1);
+        assert_eq!(mempool.metrics().bytes, pooled_bytes(&mempool));
+
+        // Expired transactions are not pooled again
+        assert_eq!(
+            mempool.check(&old, 2_000),
+            Err(MempoolError::Expired {
+                transaction_id: old.id().to_string(),
+                expired_at: 2_000
+            })
+        );
+        assert_eq!(mempool.check(&old, 1_999), Ok(()));
+    }
+
+    #[test]
+    fn test_remove_included_and_evict_invalid() {
+        let mut blockchain = blockchain();
+        let included = transfer("account_1", 0, 0);
+        let next_nonce = transfer("account_1", 1, 0);
+        let conflicting = Transaction::transfer(
+            &network_id(),
+            "account_2".to_string(),
+            "account_3".to_string(),
+            Amount::new(95),
+            Amount::ZERO,
+            0,
+            &keypair("account_2"),
+        );
+        let account_creation = Transaction::account_creation(
+            "account_4".to_string(),
+            Amount::ZERO,
+            keypair("account_4").public_key(),
+        );
+        let waiting = Transaction::transfer(
+            &network_id(),
+            "account_3".to_string(),
+            "account_4".to_string(),
+            Amount::new(10),
+            Amount::ZERO,
+            0,
+            &keypair("account_3"),
+        );
+        let mut mempool: Mempool = [
+            included.clone(),
+            next_nonce.clone(),
+            conflicting,
+            account_creation.clone(),
+            waiting.clone(),
+        ]
+        .into_iter()
+        .collect();
+
+        // Another node mined a transfer spending the funds of account_2
+        blockchain
+            .add_block(vec![included, transfer("account_2", 0, 0)])
+            .unwrap();
+        mempool.remove_included(std::slice::from_ref(blockchain.tip()));
+        assert_eq!(mempool.len(), 4);
+        mempool.evict_invalid(blockchain.transaction_validator());
+
+        assert_eq!(
+            mempool.transactions(),
+            [next_nonce, account_creation, waiting]
+        );
+        let metrics = mempool.metrics();
+        assert_eq!(metrics.invalidated, 1);
+        assert_eq!(metrics.size, 3);
+        assert_eq!(metrics.bytes, pooled_bytes(&mempool));
+    }
+}
@@ -1,51 +1,1069 @@
-use blockchain::{blockchain::Blockchain, transaction::Transaction};
+use crate::mempool::{is_future_nonce, BlockLimits, Mempool, MempoolError};
+use blockchain::{
+    amount::Amount,
+    block::Block,
+    blockchain::{Blockchain, ChainUpdate},
+    genesis::GenesisConfig,
+    transaction::Transaction,
+    validation::{BlockError, TransactionValidator},
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::sync::atomic::AtomicBool;
+use tokio::sync::broadcast;
+
+/// Number of events kept for the subscribers that fall behind
+const EVENTS_CAPACITY: usize = 1024;
 
 /// Represents a node in the blockchain network.
 pub struct Node {
-    pub transaction_pool: Vec<Transaction>,
+    pub mempool: Mempool,
     pub blockchain: Blockchain,
+    /// Account credited with the fees of the mined blocks, they are burned without one
+    pub producer: Option<String>,
+    /// Limits of the mined blocks, filled with the transactions paying the highest fees
+    pub block_limits: BlockLimits,
+    events: broadcast::Sender<NodeEvent>,
+}
+
+/// Represents a change of the node pushed to its subscribers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum NodeEvent {
+    /// A block joined the best chain, mined by the node or received from a peer.
+    Block { block: Block },
+    /// A transaction entered the mempool.
+    Transaction { transaction: Transaction },
+    /// The balance of an account changed, as blocks joined or left the best chain.
+    ///
+    /// An account whose creation left the best chain has a balance of 0.
+    Balance {
+        account_id: String,
+        balance: Amount,
+        /// Height of the best chain after the change
+        block_id: i64,
+    },
+}
+
+impl NodeEvent {
+    /// Checks if the event is about an account: a balance of the account, or a transaction
+    /// changing it. Blocks are not about any account.
+    pub fn involves(&self, account_id: &str) -> bool {
+        match self {
+            NodeEvent::Block { .. } => false,
+            NodeEvent::Transaction { transaction } => {
+                transaction.account_ids().contains(&account_id)
+            }
+            NodeEvent::Balance {
+                account_id: changed,
+                ..
+            } => changed == account_id,
+        }
+    }
 }
 
 impl Node {
-    /// Creates a new node with an empty transaction pool and a fresh blockchain.
-    pub fn new() -> Self {
+    /// Creates a new node with an empty mempool and a fresh blockchain
+    /// built from the genesis configuration.
+    pub fn new(genesis: &GenesisConfig) -> Self {
+        Self::with_blockchain(Blockchain::new(genesis))
+    }
+
+    /// Creates a new node with an empty mempool on top of an existing blockchain,
+    /// such as one reloaded from its store.
+    pub fn with_blockchain(blockchain: Blockchain) -> Self {
         Node {
-            transaction_pool: Vec::new(),
-            blockchain: Blockchain::new(),
+            mempool: Mempool::new(),
+            blockchain,
+            producer: None,
+            block_limits: BlockLimits::default(),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+        }
+    }
+
+    /// Receives the events of the node from now on.
+    ///
+    /// A subscriber falling more than a thousand events behind misses the oldest ones.
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.events.subscribe()
+    }
+
+    /// Adds a transaction to the mempool if it is valid on top of the blockchain
+    /// and the transactions already pooled.
+    ///
+    /// Transfers can arrive out of order: a transfer whose nonce is ahead of the next nonce
+    /// of its sender is kept in the mempool until the missing ones arrive. The pooled transfers
+    /// of a sender are always ordered by nonce.
+    ///
+    /// Transactions already pooled or expired are refused, and so are the ones not paying
+    /// enough to enter a full mempool.
+    pub fn submit_transaction(&mut self, transaction: Transaction) -> Result<(), MempoolError> {
+        self.mempool
+            .check(&transaction, Utc::now().timestamp_millis())?;
+        let mut validator = self.pool_validator();
+        match validator.validate(&transaction) {
+            Ok(()) => {}
+            Err(e) if is_future_nonce(&e) && !self.is_nonce_pooled(&transaction) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        self.mempool.insert(transaction.clone())?;
+        self.publish(|| NodeEvent::Transaction { transaction });
+        Ok(())
+    }
+
+    /// Gets the nonce the next transfer sent by an account must have, including the pooled transfers.
+    pub fn next_nonce(&self, account_id: &str) -> u64 {
+        self.pool_validator().next_nonce(account_id)
+    }
+
+    /// Mines a new block with the transactions of the mempool paying the highest fees, within
+    /// the block limits, and returns it.
+    ///
+    /// Expired transactions are dropped from the mempool first. The other ones are checked again
+    /// against the current blockchain, and the ones that became invalid are dropped instead of
+    /// being included. Transfers whose nonce is still ahead of their sender stay in the mempool.
+    ///
+    /// The fees go to the producer account, or are burned while it doesn't exist or its balance
+    /// can't take them. If the block is refused all the same, its transactions go back to the
+    /// mempool and the error is returned.
+    pub fn mine_block(&mut self) -> Result<&Block, BlockError> {
+        let mut block = self.block_template()?;
+        block.seal(&AtomicBool::new(false));
+        self.add_mined_block(block)?;
+        Ok(self.blockchain.tip())
+    }
+
+    /// Builds the next block from the mempool like `mine_block`, without sealing it.
+    ///
+    /// Its transactions leave the mempool, so once sealed the block must be given to
+    /// `add_mined_block`, or to `abandon_block` to put them back.
+    pub fn block_template(&mut self) -> Result<Block, BlockError> {
+        self.mempool.expire(Utc::now().timestamp_millis());
+        let transactions = self
+            .mempool
+            .select(self.blockchain.transaction_validator(), self.block_limits);
+        let producer = self.producer.clone().filter(|producer| {
+            self.blockchain.account_exists(producer)
+                || transactions.iter().any(|transaction| {
+                    matches!(transaction, Transaction::AccountCreation(account_creation)
+                        if &account_creation.account_id == producer)
+                })
+        });
+
+        let template = match self
+            .blockchain
+            .block_template(producer, transactions.clone())
+        {
+            // The balance of the producer can't take the fees and the reward, they are burned
+            Err(BlockError::ProducerBalanceOverflow { .. }) => {
+                self.blockchain.block_template(None, transactions.clone())
+            }
+            template => template,
+        };
+        if template.is_err() {
+            self.return_selected_transactions(transactions);
+        }
+        template
+    }
+
+    /// Adds a block built with `block_template` and sealed to the blockchain.
+    ///
+    /// If a block received while it was sealed keeps it out of the best chain, or it is refused,
+    /// its transactions go back to the mempool.
+    pub fn add_mined_block(&mut self, block: Block) -> Result<ChainUpdate, BlockError> {
+        let transactions = block.transactions.clone();
+        let update = self.receive_block(block);
+        if matches!(update, Ok(ChainUpdate::SideBranch) | Err(_)) {
+            self.return_transactions(transactions);
+        }
+        update
+    }
+
+    /// Puts the transactions of a block built with `block_template` back in the mempool, when it
+    /// won't be added to the blockchain.
+    pub fn abandon_block(&mut self, block: Block) {
+        self.return_transactions(block.transactions);
+    }
+
+    /// Puts the transactions of a mined block that didn't join the best chain back in the mempool,
+    /// dropping its coinbase and the transactions the blockchain no longer accepts.
+    fn return_transactions(&mut self, transactions: Vec<Transaction>) {
+        self.return_selected_transactions(
+            transactions
+                .into_iter()
+                .filter(|transaction| !matches!(transaction, Transaction::Coinbase(_)))
+                .collect(),
+        );
+        self.mempool
+            .evict_invalid(self.blockchain.transaction_validator());
+    }
+
+    /// Puts transactions selected for a block that was not mined back in the mempool, before
+    /// the pooled ones.
+    fn return_selected_transactions(&mut self, selected: Vec<Transaction>) {
+        let pooled = self.mempool.take();
+        self.mempool.extend(selected.into_iter().chain(pooled));
+    }
+
+    /// Creates a validator that accepted the pooled transactions.
+    fn pool_validator(&self) -> TransactionValidator<'_> {
+        let mut validator = self.blockchain.transaction_validator();
+        for pooled in &self.mempool {
+            // Pooled transactions were valid when submitted, but the chain may have changed since
+            let _ = validator.validate(pooled);
         }
+        validator
     }
 
-    /// Mines a new block with the transactions in the transaction pool.
-    pub fn mine_block(&mut self) {
-        self.blockchain.add_block(self.transaction_pool.clone());
-        self.transaction_pool.clear();
+    /// Checks if the mempool already has a transfer from the same sender with the same nonce.
+    fn is_nonce_pooled(&self, transaction: &Transaction) -> bool {
+        let Transaction::Transfer(transfer) = transaction else {
+            return false;
+        };
+        self.mempool.iter().any(|pooled| {
+            matches!(pooled, Transaction::Transfer(pooled)
+                if pooled.from_account == transfer.from_account && pooled.nonce == transfer.nonce)
+        })
+    }
+
+    /// Appends a block mined by a peer to the blockchain.
+    ///
+    /// The transactions it includes are removed from the mempool, so they are not mined twice,
+    /// along with the pooled transactions it makes invalid. If the blockchain reorganizes, the
+    /// transactions of the orphaned blocks go back to the mempool.
+    pub fn receive_block(&mut self, block: Block) -> Result<ChainUpdate, BlockError> {
+        let update = self.blockchain.append_block(block)?;
+
+        match &update {
+            ChainUpdate::Extended => {
+                self.mempool
+                    .remove_included(std::slice::from_ref(self.blockchain.tip()));
+                self.mempool
+                    .evict_invalid(self.blockchain.transaction_validator());
+                self.publish_chain_change(&[], std::slice::from_ref(self.blockchain.tip()));
+            }
+            ChainUpdate::SideBranch => {}
+            ChainUpdate::Reorganized {
+                orphaned,
+                connected,
+            } => {
+                self.return_orphaned_transactions(orphaned, connected);
+                self.publish_chain_change(orphaned, connected);
+            }
+        }
+        Ok(update)
+    }
+
+    /// Sends an event to the subscribers, only building it if there is one.
+    fn publish(&self, event: impl FnOnce() -> NodeEvent) {
+        if self.events.receiver_count() > 0 {
+            // A subscriber leaving in the meantime is not an error
+            let _ = self.events.send(event());
+        }
+    }
+
+    /// Sends the events of blocks joining the best chain, and of the balances they changed
+    /// along with the blocks leaving it.
+    fn publish_chain_change(&self, orphaned: &[Block], connected: &[Block]) {
+        if self.events.receiver_count() == 0 {
+            return;
+        }
+
+        for block in connected {
+            self.publish(|| NodeEvent::Block {
+                block: block.clone(),
+            });
+        }
+        let account_ids: BTreeSet<&str> = orphaned
+            .iter()
+            .chain(connected)
+            .flat_map(|block| {
+                block
+                    .transactions
+                    .iter()
+                    .flat_map(Transaction::account_ids)
+                    .chain(block.header.producer.as_deref())
+            })
+            .collect();
+        for account_id in account_ids {
+            self.publish(|| NodeEvent::Balance {
+                account_id: account_id.to_string(),
+                balance: self.blockchain.get_account_balance(account_id),
+                block_id: self.blockchain.height(),
+            });
+        }
+    }
+
+    /// Puts the transactions of orphaned blocks back in the mempool, before the pooled ones.
+    ///
+    /// Transactions included in the new best chain, or no longer valid on top of it, are dropped,
+    /// and so are the coinbases of the orphaned blocks. Transfers whose nonce is ahead of their
+    /// sender are kept.
+    fn return_orphaned_transactions(&mut self, orphaned: &[Block], connected: &[Block]) {
+        let included: HashSet<&str> = connected
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .map(Transaction::id)
+            .collect();
+        let pooled = self.mempool.take();
+        let pooled_ids: HashSet<String> = pooled
+            .iter()
+            .map(|transaction| transaction.id().to_string())
+            .collect();
+
+        self.mempool.extend(
+            orphaned
+                .iter()
+                .flat_map(|block| block.transactions.iter().cloned())
+                .chain(pooled)
+                .filter(|transaction| !included.contains(transaction.id())),
+        );
+        self.mempool
+            .evict_invalid(self.blockchain.transaction_validator());
+
+        for transaction in &self.mempool {
+            if !pooled_ids.contains(transaction.id()) {
+                self.publish(|| NodeEvent::Transaction {
+                    transaction: transaction.clone(),
+                });
+            }
+        }
+    }
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self::new(&GenesisConfig::default())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use blockchain::{
+        block::MAX_BLOCK_SIZE,
+        genesis::GenesisAllocation,
+        keys::test_utils::{keypair, network_id},
+        reward::BlockReward,
+        supply::SupplyPolicy,
+        validation::{BlockRuleError, TransactionError},
+    };
 
     #[test]
     fn test_new() {
-        let node = Node::new();
+        let node = Node::default();
 
-        assert_eq!(node.transaction_pool.len(), 0);
-        assert_eq!(node.blockchain.blocks.len(), 1);
-        assert_eq!(node.blockchain.blocks[0].id, 0);
+        assert_eq!(node.mempool.len(), 0);
+        assert_eq!(node.blockchain.height(), 0);
+        assert_eq!(node.blockchain.tip().header.id, 0);
     }
 
     #[test]
     fn test_mine_block() {
-        let mut node = Node::new();
-        node.transaction_pool
-            .push(Transaction::account_creation("account_id".to_string(), 100));
-        assert_eq!(node.transaction_pool.len(), 1);
+        let mut node = Node::default();
+        node.mempool
+            .insert(Transaction::account_creation(
+                "account_id".to_string(),
+                Amount::new(100),
+                keypair("account_id").public_key(),
+            ))
+            .unwrap();
+        assert_eq!(node.mempool.len(), 1);
+
+        node.mine_block().unwrap();
+
+        assert_eq!(node.mempool.len(), 0);
+        assert_eq!(node.blockchain.height(), 1);
+        assert_eq!(node.blockchain.tip().transactions.len(), 1);
+    }
+
+    #[test]
+    fn test_mine_block_refused() {
+        let mut node = Node::default();
+        node.block_limits.max_bytes = usize::MAX;
+        let transaction = Transaction::account_creation(
+            "a".repeat(MAX_BLOCK_SIZE),
+            Amount::new(100),
+            keypair("account_id").public_key(),
+        );
+        node.mempool.insert(transaction.clone()).unwrap();
+
+        assert!(matches!(
+            node.mine_block(),
+            Err(BlockError::InvalidBlock {
+                block_id: 1,
+                rule: BlockRuleError::TooLarge { .. }
+            })
+        ));
+        assert_eq!(node.blockchain.height(), 0);
+        assert_eq!(node.mempool.transactions(), [transaction]);
+    }
+
+    #[test]
+    fn test_submit_transaction() {
+        let mut node = Node::default();
+
+        // Valid transactions, relying on the pooled ones
+        assert_eq!(
+            node.submit_transaction(Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key()
+            )),
+            Ok(())
+        );
+        assert_eq!(
+            node.submit_transaction(Transaction::account_creation(
+                "account_2".to_string(),
+                Amount::ZERO,
+                keypair("account_2").public_key()
+            )),
+            Ok(())
+        );
+        assert_eq!(
+            node.submit_transaction(Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(100),
+                Amount::ZERO,
+                0,
+                &keypair("account_1")
+            )),
+            Ok(())
+        );
+
+        // Invalid transaction
+        assert_eq!(
+            node.submit_transaction(Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(1),
+                Amount::ZERO,
+                1,
+                &keypair("account_1")
+            )),
+            Err(MempoolError::Transaction(
+                TransactionError::InsufficientFunds {
+                    account_id: "account_1".to_string(),
+                    balance: Amount::new(0),
+                    amount: Amount::new(1),
+                    fee: Amount::new(0)
+                }
+            ))
+        );
+        assert_eq!(node.mempool.len(), 3);
+    }
+
+    #[test]
+    fn test_submit_transfers_out_of_order() {
+        let mut node = Node::default();
+        node.mempool.extend([
+            Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            ),
+            Transaction::account_creation(
+                "account_2".to_string(),
+                Amount::ZERO,
+                keypair("account_2").public_key(),
+            ),
+        ]);
+        node.mine_block().unwrap();
+        let transfer = |nonce| {
+            Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "account_2".to_string(),
+                Amount::new(10),
+                Amount::ZERO,
+                nonce,
+                &keypair("account_1"),
+            )
+        };
+
+        // Transfers ahead of the sender nonce wait for the missing ones
+        let transfer_2 = transfer(2);
+        assert_eq!(node.submit_transaction(transfer_2.clone()), Ok(()));
+        assert_eq!(node.next_nonce("account_1"), 0);
+        node.mine_block().unwrap();
+        assert_eq!(node.blockchain.tip().transactions.len(), 0);
+        assert_eq!(node.mempool.transactions(), vec![transfer_2.clone()]);
+
+        // Same nonce as a pooled transfer
+        assert!(matches!(
+            node.submit_transaction(transfer(2)),
+            Err(MempoolError::Transaction(TransactionError::InvalidNonce {
+                nonce: 2,
+                ..
+            }))
+        ));
+
+        // Missing transfers are pooled before the ones following them
+        let transfer_0 = transfer(0);
+        let transfer_1 = transfer(1);
+        assert_eq!(node.submit_transaction(transfer_1.clone()), Ok(()));
+        assert_eq!(node.submit_transaction(transfer_0.clone()), Ok(()));
+        assert_eq!(
+            node.mempool.transactions(),
+            [transfer_0.clone(), transfer_1, transfer_2]
+        );
+        assert_eq!(node.next_nonce("account_1"), 3);
+
+        let block = node.mine_block().unwrap();
+        assert_eq!(block.transactions.len(), 3);
+        assert_eq!(node.mempool.len(), 0);
+        assert_eq!(
+            node.blockchain.get_account_balance("account_2"),
+            Amount::new(30)
+        );
+
+        // Replayed transfer
+        assert_eq!(
+            node.submit_transaction(transfer_0),
+            Err(MempoolError::Transaction(TransactionError::InvalidNonce {
+                account_id: "account_1".to_string(),
+                nonce: 0,
+                expected: 3
+            }))
+        );
+    }
+
+    #[test]
+    fn test_mine_block_drops_invalid_transactions() {
+        let mut node = Node::default();
+        node.mempool.extend([
+            Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            ),
+            Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            ),
+            Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                "unknown".to_string(),
+                Amount::new(10),
+                Amount::ZERO,
+                0,
+                &keypair("account_1"),
+            ),
+        ]);
+
+        let block = node.mine_block().unwrap();
+
+        assert_eq!(block.transactions.len(), 1);
+        assert_eq!(node.mempool.len(), 0);
+        assert_eq!(
+            node.blockchain.get_account_balance("account_1"),
+            Amount::new(100)
+        );
+    }
+
+    #[test]
+    fn test_mine_block_fees() {
+        let mut node = Node {
+            producer: Some("producer".to_string()),
+            ..Node::default()
+        };
+        node.mempool.extend([
+            Transaction::account_creation(
+                "account_1".to_string(),
+                Amount::new(100),
+                keypair("account_1").public_key(),
+            ),
+            Transaction::account_creation(
+                "account_2".to_string(),
+                Amount::ZERO,
+                keypair("account_2").public_key(),
+            ),
+        ]);
+        node.mine_block().unwrap();
+
+        // The producer doesn't exist yet, the fees are burned
+        node.submit_transaction(Transaction::transfer(
+            &network_id(),
+            "account_1".to_string(),
+            "account_2".to_string(),
+            Amount::new(10),
+            Amount::new(5),
+            0,
+            &keypair("account_1"),
+        ))
+        .unwrap();
+        let block = node.mine_block().unwrap();
+        assert_eq!(block.header.producer, None);
+        assert_eq!(
+            node.blockchain.get_account_balance("account_1"),
+            Amount::new(85)
+        );
+        assert_eq!(
+            node.blockchain.get_account_balance("account_2"),
+            Amount::new(10)
+        );
+
+        node.submit_transaction(Transaction::account_creation(
+            "producer".to_string(),
+            Amount::ZERO,
+            keypair("producer").public_key(),
+        ))
+        .unwrap();
+        node.submit_transaction(Transaction::transfer(
+            &network_id(),
+            "account_1".to_string(),
+            "account_2".to_string(),
+            Amount::new(10),
+            Amount::new(3),
+            1,
+            &keypair("account_1"),
+        ))
+        .unwrap();
+        let block = node.mine_block().unwrap();
+        assert_eq!(block.header.producer.as_deref(), Some("producer"));
+        assert_eq!(
+            node.blockchain.get_account_balance("account_1"),
+            Amount::new(72)
+        );
+        assert_eq!(
+            node.blockchain.get_account_balance("producer"),
+            Amount::new(3)
+        );
+
+        // The fees are burned when the producer balance can't take them
+        node.submit_transaction(Transaction::account_creation(
+            "rich".to_string(),
+            Amount::MAX,
+            keypair("rich").public_key(),
+        ))
+        .unwrap();
+        node.mine_block().unwrap();
+        node.producer = Some("rich".to_string());
+        node.submit_transaction(Transaction::transfer(
+            &network_id(),
+            "account_1".to_string(),
+            "account_2".to_string(),
+            Amount::new(10),
+            Amount::new(2),
+            2,
+            &keypair("account_1"),
+        ))
+        .unwrap();
+        let block = node.mine_block().unwrap();
+        assert_eq!(block.header.producer, None);
+        assert_eq!(block.transactions.len(), 1);
+        assert_eq!(
+            node.blockchain.get_account_balance("account_1"),
+            Amount::new(60)
+        );
+        assert_eq!(node.blockchain.get_account_balance("rich"), Amount::MAX);
+    }
+
+    #[test]
+    fn test_mine_block_reward() {
+        let genesis = GenesisConfig {
+            block_reward: Some(BlockReward {
+                initial_reward: Amount::new(50),
+                halving_interval: 100,
+            }),
+            ..GenesisConfig::default()
+        };
+        let mut node = Node {
+            producer: Some("producer".to_string()),
+            ..Node::new(&genesis)
+        };
+        node.submit_transaction(Transaction::account_creation(
+            "producer".to_string(),
+            Amount::ZERO,
+            keypair("producer").public_key(),
+        ))
+        .unwrap();
+
+        let block = node.mine_block().unwrap();
+        assert_eq!(block.transactions.len(), 2);
+        assert_eq!(block.reward(), Amount::new(50));
+        assert_eq!(
+            node.blockchain.get_account_balance("producer"),
+            Amount::new(50)
+        );
+
+        // Coinbases can't be submitted
+        let coinbase = Transaction::coinbase(Amount::new(50));
+        assert_eq!(
+            node.submit_transaction(coinbase.clone()),
+            Err(MempoolError::Transaction(
+                TransactionError::UnexpectedCoinbase(coinbase.id().to_string())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_submit_transaction_supply_policy() {
+        let genesis = GenesisConfig {
+            allocations: vec![GenesisAllocation {
+                account_id: "faucet".to_string(),
+                balance: Amount::new(100),
+                public_key: keypair("faucet").public_key(),
+            }],
+            supply_policy: SupplyPolicy::Restricted {
+                faucets: vec!["faucet".to_string()],
+            },
+            ..GenesisConfig::default()
+        };
+        let mut node = Node::new(&genesis);
+
+        assert_eq!(
+            node.submit_transaction(Transaction::account_creation(
+                "account_id".to_string(),
+                Amount::new(100),
+                keypair("account_id").public_key(),
+            )),
+            Err(MempoolError::Transaction(
+                TransactionError::UnfundedInitialBalance {
+                    account_id: "account_id".to_string(),
+                    initial_balance: Amount::new(100)
+                }
+            ))
+        );
+        node.submit_transaction(Transaction::funded_account_creation(
+            &genesis.network_id(),
+            "account_id".to_string(),
+            Amount::new(100),
+            keypair("account_id").public_key(),
+            "faucet".to_string(),
+            &keypair("faucet"),
+        ))
+        .unwrap();
+
+        node.mine_block().unwrap();
+        assert_eq!(
+            node.blockchain.get_account_balance("faucet"),
+            Amount::new(0)
+        );
+        assert_eq!(
+            node.blockchain.get_account_balance("account_id"),
+            Amount::new(100)
+        );
+    }
+
+    #[test]
+    fn test_receive_block() {
+        let mut node = Node::default();
+        let mut peer = Node::default();
+        let transaction = Transaction::account_creation(
+            "account_id".to_string(),
+            Amount::new(100),
+            keypair("account_id").public_key(),
+        );
+        node.submit_transaction(transaction.clone()).unwrap();
+        peer.submit_transaction(transaction).unwrap();
+
+        let block = peer.mine_block().unwrap().clone();
+
+        assert_eq!(node.receive_block(block.clone()), Ok(ChainUpdate::Extended));
+        assert_eq!(node.mempool.len(), 0);
+        assert_eq!(node.blockchain.height(), 1);
+        assert_eq!(
+            node.receive_block(block),
+            Err(BlockError::AlreadyKnown { block_id: 1 })
+        );
+    }
+
+    #[test]
+    fn test_receive_block_evicts_invalid_transactions() {
+        let mut node = Node::default();
+        let mut peer = Node::default();
+        for account_id in ["account_1", "account_2"] {
+            let transaction = Transaction::account_creation(
+                account_id.to_string(),
+                Amount::new(100),
+                keypair(account_id).public_key(),
+            );
+            node.submit_transaction(transaction.clone()).unwrap();
+            peer.submit_transaction(transaction).unwrap();
+        }
+        let block = peer.mine_block().unwrap().clone();
+        node.receive_block(block).unwrap();
+
+        // The same funds are spent on both nodes
+        let transfer = |to_account: &str| {
+            Transaction::transfer(
+                &network_id(),
+                "account_1".to_string(),
+                to_account.to_string(),
+                Amount::new(80),
+                Amount::ZERO,
+                0,
+                &keypair("account_1"),
+            )
+        };
+        let local = transfer("account_2");
+        node.submit_transaction(local.clone()).unwrap();
+        assert_eq!(
+            node.submit_transaction(local.clone()),
+            Err(MempoolError::Duplicate(local.id().to_string()))
+        );
+        peer.submit_transaction(transfer("account_2")).unwrap();
+        let block = peer.mine_block().unwrap().clone();
+
+        assert_eq!(node.receive_block(block), Ok(ChainUpdate::Extended));
+        assert!(node.mempool.is_empty());
+        assert_eq!(node.mempool.metrics().invalidated, 1);
+    }
+
+    #[test]
+    fn test_add_mined_block() {
+        let mut node = Node::default();
+        let mut peer = Node::default();
+        let local = Transaction::account_creation(
+            "local".to_string(),
+            Amount::new(100),
+            keypair("local").public_key(),
+        );
+        let pooled = Transaction::account_creation(
+            "pooled".to_string(),
+            Amount::new(100),
+            keypair("pooled").public_key(),
+        );
+
+        // The selected transactions leave the mempool while the block is sealed
+        node.submit_transaction(local.clone()).unwrap();
+        let mut block = node.block_template().unwrap();
+        assert_eq!(block.transactions, std::slice::from_ref(&local));
+        assert_eq!(node.mempool.len(), 0);
+        node.submit_transaction(pooled.clone()).unwrap();
+
+        // A peer block at the same height arrives first
+        let peer_block = peer.mine_block().unwrap().clone();
+        assert_eq!(node.receive_block(peer_block), Ok(ChainUpdate::Extended));
+
+        assert!(block.seal(&AtomicBool::new(false)));
+        assert_eq!(node.add_mined_block(block), Ok(ChainUpdate::SideBranch));
+        assert_eq!(node.mempool.transactions(), [local.clone(), pooled.clone()]);
+
+        // An abandoned block gives its transactions back
+        let block = node.block_template().unwrap();
+        assert_eq!(node.mempool.len(), 0);
+        node.abandon_block(block);
+        assert_eq!(node.mempool.transactions(), [local, pooled]);
+        assert_eq!(node.blockchain.height(), 1);
+    }
+
+    #[test]
+    fn test_receive_competing_blocks() {
+        let mut node = Node::default();
+        let mut peer = Node::default();
+        let shared = Transaction::account_creation(
+            "shared".to_string(),
+            Amount::new(100),
+            keypair("shared").public_key(),
+        );
+        let local = Transaction::account_creation(
+            "local".to_string(),
+            Amount::new(100),
+            keypair("local").public_key(),
+        );
+        let conflicting = Transaction::account_creation(
+            "conflicting".to_string(),
+            Amount::new(100),
+            keypair("conflicting").public_key(),
+        );
+        let pooled = Transaction::account_creation(
+            "pooled".to_string(),
+            Amount::new(100),
+            keypair("pooled").public_key(),
+        );
+
+        // Both nodes mine a block at the same height
+        for transaction in [&shared, &local, &conflicting] {
+            node.submit_transaction(transaction.clone()).unwrap();
+        }
+        node.mine_block().unwrap();
+        node.submit_transaction(pooled.clone()).unwrap();
+
+        peer.submit_transaction(shared.clone()).unwrap();
+        peer.submit_transaction(Transaction::account_creation(
+            "conflicting".to_string(),
+            Amount::new(50),
+            keypair("conflicting").public_key(),
+        ))
+        .unwrap();
+        let block_1 = peer.mine_block().unwrap().clone();
+        let block_2 = peer.mine_block().unwrap().clone();
+
+        // The first block seen is kept
+        assert_eq!(node.receive_block(block_1), Ok(ChainUpdate::SideBranch));
+        assert_eq!(node.mempool.transactions(), vec![pooled.clone()]);
+
+        // The peer chain has more work
+        assert!(matches!(
+            node.receive_block(block_2.clone()),
+            Ok(ChainUpdate::Reorganized { .. })
+        ));
+        assert_eq!(node.blockchain.tip().hash, block_2.hash);
+
+        // Only the orphaned transactions missing from the new chain and still valid are pooled again
+        assert_eq!(node.mempool.transactions(), [local, pooled]);
+
+        // Mining the pool on top of the new chain converges with the peer
+        let block_3 = node.mine_block().unwrap().clone();
+        assert_eq!(peer.receive_block(block_3), Ok(ChainUpdate::Extended));
+        assert_eq!(
+            peer.blockchain.get_account_balance("local"),
+            Amount::new(100)
+        );
+        assert_eq!(
+            peer.blockchain.get_account_balance("conflicting"),
+            Amount::new(50)
+        );
+        assert_eq!(peer.blockchain.tip().hash, node.blockchain.tip().hash);
+    }
+
+    /// Receive the events sent so far
+    fn received_events(events: &mut broadcast::Receiver<NodeEvent>) -> Vec<NodeEvent> {
+        std::iter::from_fn(|| events.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn test_events() {
+        let mut node = Node::default();
+        let mut events = node.subscribe();
+        let account_creation = Transaction::account_creation(
+            "account_1".to_string(),
+            Amount::new(100),
+            keypair("account_1").public_key(),
+        );
+        let transfer = Transaction::transfer(
+            &network_id(),
+            "account_1".to_string(),
+            "account_2".to_string(),
+            Amount::new(30),
+            Amount::ZERO,
+            0,
+            &keypair("account_1"),
+        );
+
+        node.submit_transaction(account_creation.clone()).unwrap();
+        node.submit_transaction(Transaction::account_creation(
+            "account_1".to_string(),
+            Amount::new(100),
+            keypair("account_1").public_key(),
+        ))
+        .unwrap_err();
+        assert_eq!(
+            received_events(&mut events),
+            vec![NodeEvent::Transaction {
+                transaction: account_creation
+            }]
+        );
+
+        let block = node.mine_block().unwrap().clone();
+        assert_eq!(
+            received_events(&mut events),
+            vec![
+                NodeEvent::Block { block },
+                NodeEvent::Balance {
+                    account_id: "account_1".to_string(),
+                    balance: Amount::new(100),
+                    block_id: 1
+                }
+            ]
+        );
+
+        // Balances of both sides of a transfer, in id order
+        node.submit_transaction(Transaction::account_creation(
+            "account_2".to_string(),
+            Amount::ZERO,
+            keypair("account_2").public_key(),
+        ))
+        .unwrap();
+        node.mine_block().unwrap();
+        received_events(&mut events);
+        node.submit_transaction(transfer.clone()).unwrap();
+        node.mine_block().unwrap();
+        let balances: Vec<NodeEvent> = received_events(&mut events)
+            .into_iter()
+            .filter(|event| matches!(event, NodeEvent::Balance { .. }))
+            .collect();
+        assert_eq!(
+            balances,
+            vec![
+                NodeEvent::Balance {
+                    account_id: "account_1".to_string(),
+                    balance: Amount::new(70),
+                    block_id: 3
+                },
+                NodeEvent::Balance {
+                    account_id: "account_2".to_string(),
+                    balance: Amount::new(30),
+                    block_id: 3
+                }
+            ]
+        );
+
+        assert!(NodeEvent::Transaction {
+            transaction: transfer.clone()
+        }
+        .involves("account_2"));
+        assert!(!NodeEvent::Transaction {
+            transaction: transfer
+        }
+        .involves("account_3"));
+        assert!(!NodeEvent::Block {
+            block: node.blockchain.tip().clone()
+        }
+        .involves("account_1"));
+    }
+
+    #[test]
+    fn test_reorganization_events() {
+        let mut node = Node::default();
+        let mut peer = Node::default();
+        let local = Transaction::account_creation(
+            "local".to_string(),
+            Amount::new(100),
+            keypair("local").public_key(),
+        );
+        let remote = Transaction::account_creation(
+            "remote".to_string(),
+            Amount::new(100),
+            keypair("remote").public_key(),
+        );
+        node.submit_transaction(local.clone()).unwrap();
+        node.mine_block().unwrap();
+        peer.submit_transaction(remote).unwrap();
+        let block_1 = peer.mine_block().unwrap().clone();
+        let block_2 = peer.mine_block().unwrap().clone();
+        node.receive_block(block_1.clone()).unwrap();
 
-        node.mine_block();
+        let mut events = node.subscribe();
+        node.receive_block(block_2.clone()).unwrap();
 
-        assert_eq!(node.transaction_pool.len(), 0);
-        assert_eq!(node.blockchain.blocks.len(), 2);
-        assert_eq!(node.blockchain.blocks[1].transactions.len(), 1);
+        // The orphaned transaction goes back to the pool, and the balance of its account is reset
+        assert_eq!(
+            received_events(&mut events),
+            vec![
+                NodeEvent::Transaction { transaction: local },
+                NodeEvent::Block { block: block_1 },
+                NodeEvent::Block { block: block_2 },
+                NodeEvent::Balance {
+                    account_id: "local".to_string(),
+                    balance: Amount::new(0),
+                    block_id: 2
+                },
+                NodeEvent::Balance {
+                    account_id: "remote".to_string(),
+                    balance: Amount::new(100),
+                    block_id: 2
+                }
+            ]
+        );
     }
 }
@@ -0,0 +1,375 @@
+use crate::node::Node;
+use blockchain::{
+    block::Block, blockchain::ChainUpdate, transaction::Transaction, validation::BlockError,
+};
+use network::balance::{BalanceRequest, BalanceResponse};
+use network::network::{NetworkError, NetworkEvent, NetworkNode};
+use network::sync::{BlocksRequest, BlocksResponse};
+use std::sync::{Arc, Mutex};
+use tokio::{
+    select,
+    sync::{broadcast, mpsc},
+};
+
+/// Run the node on the p2p network until an error occurs
+///
+/// Transactions received from peers are submitted to the node mempool,
+/// and the transactions sent on `outgoing` are published to the peers.
+/// Blocks mined locally are announced to the peers, and the blocks announced by peers are
+/// appended to the node blockchain, downloading the missing ones first. When a peer branch
+/// gets more work than the local chain, the node switches to it.
+/// Balance and blocks requests from peers are answered from the node blockchain.
+pub async fn run(
+    node: Arc<Mutex<Node>>,
+    mut network_node: NetworkNode,
+    mut outgoing: mpsc::UnboundedReceiver<Transaction>,
+    mut mined_blocks: broadcast::Receiver<Block>,
+) -> Result<(), NetworkError> {
+    network_node.listen().await?;
+
+    loop {
+        select! {
+            event = network_node.next_event() => handle_event(&node, &mut network_node, event),
+            Some(transaction) = outgoing.recv() => {
+                if let Err(e) = network_node.publish_transaction(&transaction) {
+                    println!("Could not publish transaction {}: {e}", transaction.id());
+                }
+            }
+            Ok(block) = mined_blocks.recv() => {
+                if let Err(e) = network_node.publish_block(&block) {
+                    println!("Could not publish block #{}: {e}", block.header.id);
+                }
+            }
+        }
+    }
+}
+
+/// React to an event of the network
+fn handle_event(node: &Mutex<Node>, network_node: &mut NetworkNode, event: NetworkEvent) {
+    match event {
+        NetworkEvent::PeerConnected { peer_id } => {
+            // Catch up with the blocks the peer mined before we joined
+            let request = BlocksRequest::after_tip(&lock(node).blockchain);
+            network_node.send_blocks_request(&peer_id, request);
+        }
+        NetworkEvent::Transaction {
+            peer_id,
+            transaction,
+        } => receive_transaction(node, transaction, &peer_id.to_string()),
+        NetworkEvent::Block { peer_id, block } => {
+            if let Some(request) = receive_announced_block(node, block, &peer_id.to_string()) {
+                network_node.send_blocks_request(&peer_id, request);
+            }
+        }
+        NetworkEvent::BlocksRequest {
+            request, channel, ..
+        } => {
+            let response = BlocksResponse::from_blockchain(&lock(node).blockchain, &request);
+            network_node.send_blocks_response(channel, response);
+        }
+        NetworkEvent::BlocksResponse {
+            peer_id, response, ..
+        } => {
+            if let Some(request) = receive_blocks(node, response, &peer_id.to_string()) {
+                network_node.send_blocks_request(&peer_id, request);
+            }
+        }
+        NetworkEvent::BalanceRequest {
+            peer_id,
+            request,
+            channel,
+        } => {
+            let response = answer_balance_request(node, request, &peer_id.to_string());
+            network_node.send_balance_response(channel, response);
+        }
+        NetworkEvent::BalanceResponse {
+            peer_id, response, ..
+        } => println!(
+            "Received balance {:?} of account '{}' from peer {peer_id}",
+            response.balance, response.account_id
+        ),
+    }
+}
+
+/// Lock the node shared with the other tasks
+pub(crate) fn lock(node: &Mutex<Node>) -> std::sync::MutexGuard<'_, Node> {
+    node.lock().expect("Node lock should not be poisoned")
+}
+
+/// Submit a transaction received from a peer to the node
+fn receive_transaction(node: &Mutex<Node>, transaction: Transaction, peer_id: &str) {
+    let transaction_id = transaction.id().to_string();
+    let result = lock(node).submit_transaction(transaction);
+
+    match result {
+        Ok(()) => println!("Received transaction {transaction_id} from peer {peer_id}"),
+        Err(e) => println!("Rejected transaction {transaction_id} from peer {peer_id}: {e}"),
+    }
+}
+
+/// Append a block announced by a peer
+///
+/// If the blocks it follows are unknown, returns the request to send to the peer to download them.
+fn receive_announced_block(
+    node: &Mutex<Node>,
+    block: Block,
+    peer_id: &str,
+) -> Option<BlocksRequest> {
+    let mut node = lock(node);
+    let block_id = block.header.id;
+    let branch_request = BlocksRequest::before(&block);
+
+    match node.receive_block(block) {
+        Ok(update) => log_update(block_id, &update, "Received", peer_id),
+        Err(BlockError::AlreadyKnown { .. }) => {}
+        Err(BlockError::UnknownParent { .. }) => {
+            println!("Block #{block_id} from peer {peer_id} follows unknown blocks");
+            let tip_id = node.blockchain.tip().header.id;
+            return Some(if block_id > tip_id + 1 {
+                // The peer is ahead of us
+                BlocksRequest::after_tip(&node.blockchain)
+            } else {
+                // The peer is on a competing branch
+                branch_request
+            });
+        }
+        Err(e) => println!("Rejected block #{block_id} from peer {peer_id}: {e}"),
+    }
+    None
+}
+
+/// Append the blocks downloaded from a peer, checking each one against the previous block
+///
+/// If the peer may have more blocks, returns the request to send to continue the download.
+/// If the downloaded blocks belong to a branch forking before them, returns the request to
+/// download the previous blocks of the branch.
+fn receive_blocks(
+    node: &Mutex<Node>,
+    response: BlocksResponse,
+    peer_id: &str,
+) -> Option<BlocksRequest> {
+    let mut node = lock(node);
+    let is_full = response.is_full();
+    let last_block = response.blocks.last().cloned()?;
+
+    for block in response.blocks {
+        let block_id = block.header.id;
+        match node.receive_block(block.clone()) {
+            Ok(update) => log_update(block_id, &update, "Downloaded", peer_id),
+            Err(BlockError::AlreadyKnown { .. }) => {}
+            // Block 1 follows the genesis block, which every peer of the network shares
+            Err(BlockError::UnknownParent { .. }) if block_id > 1 => {
+                return Some(BlocksRequest::before(&block))
+            }
+            Err(e) => {
+                println!("Rejected block #{block_id} from peer {peer_id}: {e}");
+                return None;
+            }
+        }
+    }
+
+    is_full.then(|| BlocksRequest::after(&last_block))
+}
+
+/// Log how the blockchain changed after appending a block from a peer
+fn log_update(block_id: i64, update: &ChainUpdate, action: &str, peer_id: &str) {
+    match update {
+        ChainUpdate::Extended => println!("{action} block #{block_id} from peer {peer_id}"),
+        ChainUpdate::SideBranch => {
+            println!("{action} block #{block_id} of a competing branch from peer {peer_id}")
+        }
+        ChainUpdate::Reorganized { orphaned, .. } => println!(
+            "{action} block #{block_id} from peer {peer_id}, switching to its branch and orphaning {} blocks",
+            orphaned.len()
+        ),
+    }
+}
+
+/// Answer a balance query received from a peer
+fn answer_balance_request(
+    node: &Mutex<Node>,
+    request: BalanceRequest,
+    peer_id: &str,
+) -> BalanceResponse {
+    println!(
+        "Received balance query for account '{}' from peer {peer_id}",
+        request.account_id
+    );
+    BalanceResponse::from_blockchain(&lock(node).blockchain, request)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blockchain::amount::Amount;
+    use blockchain::keys::test_utils::keypair;
+    use network::sync::MAX_BLOCKS_PER_REQUEST;
+
+    /// Create a node and a peer sharing the same genesis block
+    fn node_and_peer() -> (Mutex<Node>, Node) {
+        let node = Node::default();
+        let peer = Node::default();
+        (Mutex::new(node), peer)
+    }
+
+    #[test]
+    fn test_receive_transaction() {
+        let node = Mutex::new(Node::default());
+
+        receive_transaction(
+            &node,
+            Transaction::account_creation(
+                "account_id".to_string(),
+                Amount::new(100),
+                keypair("account_id").public_key(),
+            ),
+            "peer",
+        );
+        receive_transaction(
+            &node,
+            Transaction::account_creation(
+                "account_id".to_string(),
+                Amount::new(100),
+                keypair("account_id").public_key(),
+            ),
+            "peer",
+        );
+
+        // The duplicated account creation is rejected
+        assert_eq!(node.lock().unwrap().mempool.len(), 1);
+    }
+
+    #[test]
+    fn test_receive_announced_block() {
+        let (node, mut peer) = node_and_peer();
+        let block_1 = peer.mine_block().unwrap().clone();
+        let block_2 = peer.mine_block().unwrap().clone();
+
+        // Missing block 1
+        assert_eq!(
+            receive_announced_block(&node, block_2.clone(), "peer"),
+            Some(BlocksRequest {
+                from_id: 1,
+                count: MAX_BLOCKS_PER_REQUEST
+            })
+        );
+
+        // Next blocks
+        assert_eq!(
+            receive_announced_block(&node, block_1.clone(), "peer"),
+            None
+        );
+        assert_eq!(receive_announced_block(&node, block_2, "peer"), None);
+        assert_eq!(node.lock().unwrap().blockchain.height(), 2);
+
+        // Known block
+        assert_eq!(receive_announced_block(&node, block_1, "peer"), None);
+        assert_eq!(node.lock().unwrap().blockchain.height(), 2);
+    }
+
+    #[test]
+    fn test_receive_blocks() {
+        let (node, mut peer) = node_and_peer();
+        for _ in 0..MAX_BLOCKS_PER_REQUEST + 10 {
+            peer.mine_block().unwrap();
+        }
+
+        // First page, more blocks to download
+        let request = BlocksRequest::after_tip(&node.lock().unwrap().blockchain);
+        let response = BlocksResponse::from_blockchain(&peer.blockchain, &request);
+        let request = receive_blocks(&node, response, "peer").unwrap();
+        assert_eq!(request.from_id, MAX_BLOCKS_PER_REQUEST as i64 + 1);
+
+        // Last page
+        let response = BlocksResponse::from_blockchain(&peer.blockchain, &request);
+        assert_eq!(receive_blocks(&node, response, "peer"), None);
+        assert_eq!(
+            node.lock().unwrap().blockchain.tip().hash,
+            peer.blockchain.tip().hash
+        );
+    }
+
+    /// Mine a block only the node knows, even if the peer mines in the same millisecond
+    fn mine_local_block(node: &Mutex<Node>) {
+        let mut node = lock(node);
+        node.submit_transaction(Transaction::account_creation(
+            "local".to_string(),
+            Amount::new(100),
+            keypair("local").public_key(),
+        ))
+        .unwrap();
+        node.mine_block().unwrap();
+    }
+
+    /// Answer the requests of the node from the peer blockchain until the download is over
+    fn download(node: &Mutex<Node>, peer: &Node, mut request: BlocksRequest) {
+        loop {
+            let response = BlocksResponse::from_blockchain(&peer.blockchain, &request);
+            match receive_blocks(node, response, "peer") {
+                Some(next_request) => request = next_request,
+                None => return,
+            }
+        }
+    }
+
+    #[test]
+    fn test_receive_competing_branch() {
+        let (node, mut peer) = node_and_peer();
+        mine_local_block(&node);
+        lock(&node).mine_block().unwrap();
+        for _ in 0..3 {
+            peer.mine_block().unwrap();
+        }
+
+        // The announced block follows blocks of a branch the node doesn't know
+        let request = receive_announced_block(&node, peer.blockchain.tip().clone(), "peer");
+        assert_eq!(
+            request,
+            Some(BlocksRequest {
+                from_id: 1,
+                count: MAX_BLOCKS_PER_REQUEST
+            })
+        );
+
+        // The peer branch has more work
+        download(&node, &peer, request.unwrap());
+        assert_eq!(
+            lock(&node).blockchain.tip().hash,
+            peer.blockchain.tip().hash
+        );
+    }
+
+    #[test]
+    fn test_receive_long_competing_branch() {
+        let (node, mut peer) = node_and_peer();
+        mine_local_block(&node);
+        for _ in 0..MAX_BLOCKS_PER_REQUEST + 50 {
+            peer.mine_block().unwrap();
+        }
+
+        // The peer is ahead, but its blocks don't follow the local tip
+        let request = receive_announced_block(&node, peer.blockchain.tip().clone(), "peer");
+        assert_eq!(request.as_ref().map(|request| request.from_id), Some(2));
+
+        download(&node, &peer, request.unwrap());
+        assert_eq!(
+            lock(&node).blockchain.tip().hash,
+            peer.blockchain.tip().hash
+        );
+        assert_eq!(lock(&node).blockchain.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_receive_invalid_blocks() {
+        let (node, mut peer) = node_and_peer();
+        peer.mine_block().unwrap();
+        peer.mine_block().unwrap();
+        let mut blocks = peer.blockchain.get_blocks(1, 2);
+        blocks[0].hash = "invalid".to_string();
+
+        let response = BlocksResponse { blocks };
+
+        assert_eq!(receive_blocks(&node, response, "peer"), None);
+        assert_eq!(node.lock().unwrap().blockchain.height(), 0);
+    }
+}
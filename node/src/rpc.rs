@@ -0,0 +1,386 @@
+use crate::mempool::MempoolError;
+use crate::node::{Node, NodeEvent};
+use axum::{
+    body::Bytes,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use blockchain::transaction::Transaction;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+use std::io;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::{
+    net::TcpListener,
+    select,
+    sync::{broadcast, mpsc},
+};
+
+/// Default address of the JSON-RPC server opened by `b start-node`
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7879";
+
+/// Version of the JSON-RPC protocol spoken by the server
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// Represents a JSON-RPC 2.0 request, posted alone or in a batch
+///
+/// A request without an id is a notification: it is applied, but never answered.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcRequest {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
+    /// Present even when it is `null`, which is still a request and not a notification
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub id: Option<Value>,
+}
+
+/// Represents the answer to a JSON-RPC request, with the id of the request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    #[serde(flatten)]
+    pub outcome: RpcOutcome,
+    pub id: Value,
+}
+
+/// Represents the result of a successful request, or the reason it failed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcOutcome {
+    Result(Value),
+    Error(ErrorObject),
+}
+
+/// Represents a failed request as sent on the wire
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorObject {
+    pub code: i64,
+    pub message: String,
+}
+
+/// Represents the reason a request failed
+#[derive(Debug, Error)]
+pub enum RpcError {
+    /// The body is not a JSON document
+    #[error("parse error: {0}")]
+    Parse(serde_json::Error),
+    /// The JSON document is not a request
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    /// The server has no method with that name
+    #[error("method '{0}' not found")]
+    MethodNotFound(String),
+    /// The params don't match the ones of the method
+    #[error("invalid params: {0}")]
+    InvalidParams(serde_json::Error),
+    /// The node refused the submitted transaction
+    #[error(transparent)]
+    Transaction(#[from] MempoolError),
+    /// The account of a balance query doesn't exist on the best chain
+    #[error("account '{0}' does not exist")]
+    AccountNotFound(String),
+}
+
+impl RpcError {
+    /// Get the JSON-RPC error code, the server defined errors use the -32000 to -32099 range
+    pub fn code(&self) -> i64 {
+        match self {
+            RpcError::Parse(_) => -32700,
+            RpcError::InvalidRequest(_) => -32600,
+            RpcError::MethodNotFound(_) => -32601,
+            RpcError::InvalidParams(_) => -32602,
+            RpcError::Transaction(_) => -32000,
+            RpcError::AccountNotFound(_) => -32001,
+        }
+    }
+}
+
+impl From<RpcError> for ErrorObject {
+    fn from(error: RpcError) -> Self {
+        ErrorObject {
+            code: error.code(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl RpcResponse {
+    fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        let outcome = match result {
+            Ok(result) => RpcOutcome::Result(result),
+            Err(error) => RpcOutcome::Error(error.into()),
+        };
+        RpcResponse {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            outcome,
+            id,
+        }
+    }
+}
+
+/// Deserialize a field that may be `null`, keeping it apart from a missing one
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+/// Represents the block queried by `get_block`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum BlockRef {
+    Id { id: i64 },
+    Hash { hash: String },
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmitParams {
+    transaction: Transaction,
+}
+
+#[derive(Debug, Deserialize)]
+struct BalanceParams {
+    account_id: String,
+}
+
+/// Call a method of the node
+///
+/// The methods take their params by name:
+/// - `submit_transaction` `{transaction}` adds the transaction to the pool and publishes it on
+///   `outgoing`, it returns `{transaction_id}`
+/// - `get_balance` `{account_id}` returns `{account_id, balance}` from the best chain
+/// - `get_block` `{id}` returns the block of the best chain at that height, `{hash}` returns
+///   any stored block including the ones of competing branches, both `null` if it is unknown
+/// - `get_tip` returns the last block of the best chain
+/// - `get_pool` returns the pooled transactions, in an order they can be applied in
+/// - `get_pool_metrics` returns the size of the mempool and the number of transactions it evicted
+pub fn call(
+    node: &mut Node,
+    outgoing: &mpsc::UnboundedSender<Transaction>,
+    method: &str,
+    params: Value,
+) -> Result<Value, RpcError> {
+    match method {
+        "submit_transaction" => {
+            let SubmitParams { transaction } = parse_params(params)?;
+            let transaction_id = transaction.id().to_string();
+            node.submit_transaction(transaction.clone())?;
+            println!("Received transaction {transaction_id} over RPC");
+            // The network task only stops with the node
+            let _ = outgoing.send(transaction);
+            Ok(json!({ "transaction_id": transaction_id }))
+        }
+        "get_balance" => {
+            let BalanceParams { account_id } = parse_params(params)?;
+            if !node.blockchain.account_exists(&account_id) {
+                return Err(RpcError::AccountNotFound(account_id));
+            }
+
+            let balance = node.blockchain.get_account_balance(&account_id);
+            Ok(json!({ "account_id": account_id, "balance": balance }))
+        }
+        "get_block" => {
+            let block = match parse_params(params)? {
+                BlockRef::Id { id } => node.blockchain.block_by_height(id),
+                BlockRef::Hash { hash } => node.blockchain.block_by_hash(&hash),
+            };
+            Ok(to_value(&block))
+        }
+        "get_tip" => Ok(to_value(node.blockchain.tip())),
+        "get_pool" => Ok(to_value(node.mempool.transactions())),
+        "get_pool_metrics" => Ok(to_value(&node.mempool.metrics())),
+        _ => Err(RpcError::MethodNotFound(method.to_string())),
+    }
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(RpcError::InvalidParams)
+}
+
+fn to_value<T: Serialize + ?Sized>(value: &T) -> Value {
+    serde_json::to_value(value).expect("Blocks, transactions and events should serialize to JSON")
+}
+
+/// Answer a single JSON-RPC request, `None` for a notification
+fn handle_request(
+    node: &Mutex<Node>,
+    outgoing: &mpsc::UnboundedSender<Transaction>,
+    request: Value,
+) -> Option<RpcResponse> {
+    let request = match serde_json::from_value::<RpcRequest>(request.clone()) {
+        Ok(request) if request.jsonrpc == JSONRPC_VERSION => request,
+        Ok(request) => {
+            let error = RpcError::InvalidRequest(format!(
+                "unsupported version '{}', expected '{JSONRPC_VERSION}'",
+                request.jsonrpc
+            ));
+            return Some(RpcResponse::new(
+                request.id.unwrap_or(Value::Null),
+                Err(error),
+            ));
+        }
+        Err(e) => {
+            // Answer with the id when it can be found, as the request may be almost valid
+            let id = request.get("id").cloned().unwrap_or(Value::Null);
+            return Some(RpcResponse::new(
+                id,
+                Err(RpcError::InvalidRequest(e.to_string())),
+            ));
+        }
+    };
+
+    let result = {
+        let mut node = node.lock().expect("Node lock should not be poisoned");
+        call(&mut node, outgoing, &request.method, request.params)
+    };
+    request.id.map(|id| RpcResponse::new(id, result))
+}
+
+/// Answer a posted body, holding a request or a batch of requests
+///
+/// Returns `None` when there is nothing to answer, as every request was a notification.
+pub fn handle_body(
+    node: &Mutex<Node>,
+    outgoing: &mpsc::UnboundedSender<Transaction>,
+    body: &[u8],
+) -> Option<Value> {
+    let document = match serde_json::from_slice::<Value>(body) {
+        Ok(document) => document,
+        Err(e) => {
+            return Some(to_value(&RpcResponse::new(
+                Value::Null,
+                Err(RpcError::Parse(e)),
+            )))
+        }
+    };
+
+    match document {
+        Value::Array(requests) if requests.is_empty() => {
+            let error = RpcError::InvalidRequest("empty batch".to_string());
+            Some(to_value(&RpcResponse::new(Value::Null, Err(error))))
+        }
+        Value::Array(requests) => {
+            let responses: Vec<RpcResponse> = requests
+                .into_iter()
+                .filter_map(|request| handle_request(node, outgoing, request))
+                .collect();
+            (!responses.is_empty()).then(|| to_value(&responses))
+        }
+        request => handle_request(node, outgoing, request).map(|response| to_value(&response)),
+    }
+}
+
+#[derive(Clone)]
+struct RpcState {
+    node: Arc<Mutex<Node>>,
+    outgoing: mpsc::UnboundedSender<Transaction>,
+}
+
+async fn handle_post(State(state): State<RpcState>, body: Bytes) -> Response {
+    match handle_body(&state.node, &state.outgoing, &body) {
+        Some(response) => Json(response).into_response(),
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+/// Represents the accounts watched by a WebSocket subscriber, from the `accounts` query parameter
+#[derive(Debug, Default, Deserialize)]
+pub struct SubscriptionFilter {
+    /// Comma separated account ids, every account is watched when it is missing or empty
+    #[serde(default)]
+    pub accounts: String,
+}
+
+impl SubscriptionFilter {
+    /// Check if an event must be pushed to the subscriber
+    ///
+    /// Blocks are always pushed, transactions and balances only when they involve a watched account.
+    pub fn accepts(&self, event: &NodeEvent) -> bool {
+        let mut account_ids = self
+            .accounts
+            .split(',')
+            .map(str::trim)
+            .filter(|account_id| !account_id.is_empty())
+            .peekable();
+        account_ids.peek().is_none()
+            || matches!(event, NodeEvent::Block { .. })
+            || account_ids.any(|account_id| event.involves(account_id))
+    }
+}
+
+async fn handle_subscribe(
+    State(state): State<RpcState>,
+    Query(filter): Query<SubscriptionFilter>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    // Subscribe before the upgrade, so no event is missed once the client is answered
+    let events = state
+        .node
+        .lock()
+        .expect("Node lock should not be poisoned")
+        .subscribe();
+    upgrade.on_upgrade(move |socket| push_events(socket, events, filter))
+}
+
+/// Push the accepted events as JSON text messages until the subscriber leaves
+///
+/// A subscriber falling behind is told how many events it missed with a `lagged` event.
+async fn push_events(
+    mut socket: WebSocket,
+    mut events: broadcast::Receiver<NodeEvent>,
+    filter: SubscriptionFilter,
+) {
+    loop {
+        let message = select! {
+            event = events.recv() => match event {
+                Ok(event) if filter.accepts(&event) => to_value(&event),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    json!({ "event": "lagged", "missed": missed })
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            // The messages of the subscriber are ignored, only its departure matters
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+
+        if socket
+            .send(Message::Text(message.to_string()))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Answer the JSON-RPC requests posted over HTTP to `/` on the listener until an I/O error occurs
+///
+/// WebSocket clients connecting to `/ws` get the events of the node pushed as JSON messages,
+/// optionally filtered by account with `/ws?accounts=alice,bob`.
+/// The server has no authentication, so the listener should be bound to localhost.
+pub async fn serve(
+    listener: TcpListener,
+    node: Arc<Mutex<Node>>,
+    outgoing: mpsc::UnboundedSender<Transaction>,
+) -> io::Result<()> {
+    let app = Router::new()
+        .route("/", post(handle_post))
+        .route("/ws", get(handle_subscribe))
+        .with_state(RpcState { node, outgoing });
+    axum::serve(listener, app).await
+}
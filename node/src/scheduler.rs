@@ -0,0 +1,324 @@
+use crate::node::Node;
+use crate::p2p::lock;
+use blockchain::{block::Block, blockchain::ChainUpdate};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot, watch};
+use tokio::task::JoinHandle;
+
+/// Number of mined blocks kept for slow subscribers
+const MINED_BLOCKS_CAPACITY: usize = 16;
+
+/// Source of time used by the block scheduler
+///
+/// Times are expressed in milliseconds since the unix epoch, like `Block::created_at`.
+pub trait Clock: Send + Sync + 'static {
+    /// Get the current time
+    fn now_millis(&self) -> i64;
+
+    /// Wait until the clock reaches the given time
+    fn sleep_until(&self, deadline_millis: i64) -> impl Future<Output = ()> + Send;
+}
+
+/// Clock following the system wall-clock time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> i64 {
+        chrono::Utc::now().timestamp_millis()
+    }
+
+    async fn sleep_until(&self, deadline_millis: i64) {
+        let remaining = deadline_millis.saturating_sub(self.now_millis()).max(0);
+        tokio::time::sleep(Duration::from_millis(remaining as u64)).await;
+    }
+}
+
+/// Clock that only moves when told to, allowing deterministic block timing in tests
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<watch::Sender<i64>>,
+}
+
+impl ManualClock {
+    /// Create a new clock starting at the given time
+    pub fn new(now_millis: i64) -> Self {
+        ManualClock {
+            now: Arc::new(watch::Sender::new(now_millis)),
+        }
+    }
+
+    /// Move the clock forward, waking up the sleepers whose deadline is reached
+    pub fn advance(&self, duration: Duration) {
+        self.now
+            .send_modify(|now| *now += duration.as_millis() as i64);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> i64 {
+        *self.now.borrow()
+    }
+
+    async fn sleep_until(&self, deadline_millis: i64) {
+        let mut now = self.now.subscribe();
+        // The sender is owned by the clock, so it can't be dropped while we wait
+        let _ = now.wait_for(|now| *now >= deadline_millis).await;
+    }
+}
+
+/// Produces blocks from the node mempool at fixed wall-clock boundaries
+///
+/// With an interval of 10 seconds, blocks are sealed at T=10, T=20, T=30, etc.
+/// whatever the time the scheduler was started at.
+pub struct BlockScheduler<C: Clock = SystemClock> {
+    interval: Duration,
+    clock: C,
+}
+
+impl BlockScheduler<SystemClock> {
+    /// Create a new scheduler following the system clock
+    pub fn new(interval: Duration) -> Self {
+        Self::with_clock(interval, SystemClock)
+    }
+}
+
+impl<C: Clock> BlockScheduler<C> {
+    /// Create a new scheduler following the given clock
+    pub fn with_clock(interval: Duration, clock: C) -> Self {
+        assert!(
+            interval.as_millis() > 0,
+            "Block interval should be at least one millisecond"
+        );
+
+        BlockScheduler { interval, clock }
+    }
+
+    /// Get the first block boundary strictly after the given time
+    pub fn next_boundary(&self, now_millis: i64) -> i64 {
+        let interval = self.interval.as_millis() as i64;
+        (now_millis.div_euclid(interval) + 1) * interval
+    }
+
+    /// Start producing blocks in a background task
+    pub fn spawn(self, node: Arc<Mutex<Node>>) -> SchedulerHandle {
+        let (shutdown, shutdown_receiver) = oneshot::channel();
+        let (mined_blocks, _) = broadcast::channel(MINED_BLOCKS_CAPACITY);
+        let task = tokio::spawn(self.run(node, mined_blocks.clone(), shutdown_receiver));
+
+        SchedulerHandle {
+            shutdown,
+            mined_blocks,
+            task,
+        }
+    }
+
+    /// Mine a block at every boundary until a shutdown is requested
+    async fn run(
+        self,
+        node: Arc<Mutex<Node>>,
+        mined_blocks: broadcast::Sender<Block>,
+        mut shutdown: oneshot::Receiver<()>,
+    ) {
+        let interval = self.interval.as_millis() as i64;
+        let mut last_boundary = None;
+
+        loop {
+            // Never seal the same boundary twice, even if the clock wakes us up a bit early
+            let now = self.clock.now_millis();
+            let boundary = match last_boundary {
+                Some(last) => self.next_boundary(now).max(last + interval),
+                None => self.next_boundary(now),
+            };
+
+            tokio::select! {
+                _ = self.clock.sleep_until(boundary) => {}
+                _ = &mut shutdown => break,
+            }
+
+            last_boundary = Some(boundary);
+
+            let template = lock(&node).block_template();
+            let block = match template {
+                Ok(block) => block,
+                // The transactions are back in the mempool, the next boundary tries again
+                Err(e) => {
+                    println!("Could not mine a block: {e}");
+                    continue;
+                }
+            };
+
+            // Proof-of-work can take a while, so the block is sealed without holding the node
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let mut sealing = tokio::task::spawn_blocking({
+                let cancelled = cancelled.clone();
+                move || {
+                    let mut block = block;
+                    block.seal(&cancelled);
+                    block
+                }
+            });
+            let block = tokio::select! {
+                block = &mut sealing => block.expect("Sealing a block should not panic"),
+                _ = &mut shutdown => {
+                    cancelled.store(true, Ordering::Relaxed);
+                    let block = sealing.await.expect("Sealing a block should not panic");
+                    lock(&node).abandon_block(block);
+                    break;
+                }
+            };
+
+            let mut node = lock(&node);
+            match node.add_mined_block(block) {
+                Ok(ChainUpdate::SideBranch) => {
+                    println!("Mined block is behind a block received while sealing it")
+                }
+                // Having no subscriber is not an error
+                Ok(_) => {
+                    let _ = mined_blocks.send(node.blockchain.tip().clone());
+                }
+                Err(e) => println!("Could not mine a block: {e}"),
+            }
+        }
+    }
+}
+
+/// Handle on a running block scheduler
+pub struct SchedulerHandle {
+    shutdown: oneshot::Sender<()>,
+    mined_blocks: broadcast::Sender<Block>,
+    task: JoinHandle<()>,
+}
+
+impl SchedulerHandle {
+    /// Receive the blocks mined from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Block> {
+        self.mined_blocks.subscribe()
+    }
+
+    /// Stop producing blocks and wait for the scheduler to finish
+    ///
+    /// A block being sealed when this is called is abandoned, its transactions going back to
+    /// the mempool.
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blockchain::keys::test_utils::keypair;
+    use blockchain::{
+        amount::Amount, genesis::GenesisConfig, proof_of_work::ProofOfWork,
+        transaction::Transaction,
+    };
+
+    const INTERVAL: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn test_next_boundary() {
+        let scheduler = BlockScheduler::with_clock(INTERVAL, ManualClock::new(0));
+
+        assert_eq!(scheduler.next_boundary(0), 10_000);
+        assert_eq!(scheduler.next_boundary(7_000), 10_000);
+        assert_eq!(scheduler.next_boundary(10_000), 20_000);
+        assert_eq!(scheduler.next_boundary(12_000), 20_000);
+    }
+
+    #[tokio::test]
+    async fn test_mines_at_boundaries() {
+        let clock = ManualClock::new(7_000);
+        let node = Arc::new(Mutex::new(Node::default()));
+        let handle = BlockScheduler::with_clock(INTERVAL, clock.clone()).spawn(node.clone());
+        let mut mined_blocks = handle.subscribe();
+
+        node.lock()
+            .unwrap()
+            .mempool
+            .insert(Transaction::account_creation(
+                "account_id".to_string(),
+                Amount::new(100),
+                keypair("account_id").public_key(),
+            ))
+            .unwrap();
+
+        // T=9: no block yet
+        clock.advance(Duration::from_secs(2));
+        tokio::task::yield_now().await;
+        assert!(mined_blocks.try_recv().is_err());
+
+        // T=10: first block with the pooled transaction
+        clock.advance(Duration::from_secs(1));
+        let block = mined_blocks.recv().await.unwrap();
+        assert_eq!(block.header.id, 1);
+        assert_eq!(block.transactions.len(), 1);
+
+        // T=20: second block, empty
+        clock.advance(INTERVAL);
+        let block = mined_blocks.recv().await.unwrap();
+        assert_eq!(block.header.id, 2);
+        assert_eq!(block.transactions.len(), 0);
+
+        handle.shutdown().await;
+        let node = node.lock().unwrap();
+        assert_eq!(node.blockchain.height(), 2);
+        assert_eq!(node.mempool.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let clock = ManualClock::new(0);
+        let node = Arc::new(Mutex::new(Node::default()));
+        let handle = BlockScheduler::with_clock(INTERVAL, clock.clone()).spawn(node.clone());
+
+        handle.shutdown().await;
+        clock.advance(INTERVAL);
+
+        assert_eq!(node.lock().unwrap().blockchain.height(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_while_sealing() {
+        // No hash has more than 256 leading zero bits, so the block is sealed until cancelled
+        let genesis = GenesisConfig {
+            proof_of_work: Some(ProofOfWork {
+                initial_difficulty: 257,
+                target_block_time: 10_000,
+                retarget_interval: 10,
+            }),
+            ..GenesisConfig::default()
+        };
+        let clock = ManualClock::new(0);
+        let node = Arc::new(Mutex::new(Node::new(&genesis)));
+        let handle = BlockScheduler::with_clock(INTERVAL, clock.clone()).spawn(node.clone());
+        let transaction = Transaction::account_creation(
+            "account_id".to_string(),
+            Amount::new(100),
+            keypair("account_id").public_key(),
+        );
+        node.lock()
+            .unwrap()
+            .mempool
+            .insert(transaction.clone())
+            .unwrap();
+
+        // The transaction leaves the mempool once the block is being sealed
+        tokio::task::yield_now().await;
+        clock.advance(INTERVAL);
+        while !node.lock().unwrap().mempool.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        // The node is not locked while sealing
+        assert!(node.try_lock().is_ok());
+
+        handle.shutdown().await;
+        let node = node.lock().unwrap();
+        assert_eq!(node.blockchain.height(), 0);
+        assert_eq!(node.mempool.transactions(), [transaction]);
+    }
+}
@@ -0,0 +1,357 @@
+use blockchain::{
+    amount::Amount,
+    block::Block,
+    keys::test_utils::{keypair, network_id},
+    transaction::Transaction,
+};
+use futures_util::StreamExt;
+use node::{node::Node, rpc};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::{net::TcpListener, net::TcpStream, sync::mpsc};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Subscription = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Represents a node answering JSON-RPC requests on a local port
+struct TestServer {
+    node: Arc<Mutex<Node>>,
+    published: mpsc::UnboundedReceiver<Transaction>,
+    address: String,
+    url: String,
+    client: reqwest::Client,
+}
+
+impl TestServer {
+    async fn start() -> TestServer {
+        let node = Arc::new(Mutex::new(Node::default()));
+        let (outgoing, published) = mpsc::unbounded_channel();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let url = format!("http://{address}/");
+        tokio::spawn(rpc::serve(listener, node.clone(), outgoing));
+
+        TestServer {
+            node,
+            published,
+            address,
+            url,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Post a raw body and get the HTTP status with the decoded response, if any
+    async fn post(&self, body: impl Into<reqwest::Body>) -> (reqwest::StatusCode, Option<Value>) {
+        let response = self
+            .client
+            .post(&self.url)
+            .header("content-type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.bytes().await.unwrap();
+        (
+            status,
+            (!body.is_empty()).then(|| serde_json::from_slice(&body).unwrap()),
+        )
+    }
+
+    /// Call a method and get its response
+    async fn call(&self, method: &str, params: Value) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+        let (status, response) = self.post(request.to_string()).await;
+        assert_eq!(status, reqwest::StatusCode::OK);
+        let response = response.unwrap();
+        assert_eq!(response["jsonrpc"], "2.0");
+        assert_eq!(response["id"], 1);
+        response
+    }
+
+    /// Call a method that must succeed and get its result
+    async fn result(&self, method: &str, params: Value) -> Value {
+        let response = self.call(method, params).await;
+        assert!(response.get("error").is_none(), "unexpected {response}");
+        response["result"].clone()
+    }
+
+    /// Call a method that must fail and get its error code
+    async fn error_code(&self, method: &str, params: Value) -> i64 {
+        let response = self.call(method, params).await;
+        response["error"]["code"].as_i64().unwrap()
+    }
+
+    fn mine_block(&self) -> Block {
+        self.node.lock().unwrap().mine_block().unwrap().clone()
+    }
+
+    /// Subscribe to the events of the node, with the query string of the WebSocket endpoint
+    async fn subscribe(&self, query: &str) -> Subscription {
+        let url = format!("ws://{}/ws{query}", self.address);
+        tokio_tungstenite::connect_async(url).await.unwrap().0
+    }
+}
+
+/// Wait for the next event pushed to a subscription
+async fn next_event(subscription: &mut Subscription) -> Value {
+    let message = tokio::time::timeout(Duration::from_secs(5), subscription.next())
+        .await
+        .expect("An event should be pushed")
+        .unwrap()
+        .unwrap();
+    match message {
+        Message::Text(text) => serde_json::from_str(&text).unwrap(),
+        message => panic!("unexpected message {message:?}"),
+    }
+}
+
+fn account_creation(account_id: &str, balance: u64) -> Transaction {
+    Transaction::account_creation(
+        account_id.to_string(),
+        Amount::new(balance),
+        keypair(account_id).public_key(),
+    )
+}
+
+#[tokio::test]
+async fn test_submit_transaction() {
+    let mut server = TestServer::start().await;
+    let transaction = account_creation("account_1", 100);
+
+    let result = server
+        .result("submit_transaction", json!({ "transaction": transaction }))
+        .await;
+    assert_eq!(result, json!({ "transaction_id": transaction.id() }));
+    assert_eq!(server.published.try_recv().unwrap(), transaction);
+    assert_eq!(
+        server.result("get_pool", Value::Null).await,
+        json!([transaction])
+    );
+
+    // The same account can't be created twice
+    let code = server
+        .error_code("submit_transaction", json!({ "transaction": transaction }))
+        .await;
+    assert_eq!(code, -32000);
+    assert!(server.published.try_recv().is_err());
+    let metrics = server.result("get_pool_metrics", Value::Null).await;
+    assert_eq!(metrics["size"], 1);
+    assert_eq!(metrics["invalidated"], 0);
+
+    server.mine_block();
+    assert_eq!(server.result("get_pool", Value::Null).await, json!([]));
+    let metrics = server.result("get_pool_metrics", Value::Null).await;
+    assert_eq!(metrics["size"], 0);
+    assert_eq!(metrics["bytes"], 0);
+}
+
+#[tokio::test]
+async fn test_get_balance() {
+    let server = TestServer::start().await;
+    server
+        .result(
+            "submit_transaction",
+            json!({ "transaction": account_creation("account_1", 100) }),
+        )
+        .await;
+
+    // Pending account creation is not visible yet
+    let code = server
+        .error_code("get_balance", json!({ "account_id": "account_1" }))
+        .await;
+    assert_eq!(code, -32001);
+
+    server.mine_block();
+    assert_eq!(
+        server
+            .result("get_balance", json!({ "account_id": "account_1" }))
+            .await,
+        json!({ "account_id": "account_1", "balance": 100 })
+    );
+}
+
+#[tokio::test]
+async fn test_get_block_and_tip() {
+    let server = TestServer::start().await;
+    let genesis = server.result("get_tip", Value::Null).await;
+    assert_eq!(genesis["header"]["id"], 0);
+
+    server
+        .result(
+            "submit_transaction",
+            json!({ "transaction": account_creation("account_1", 100) }),
+        )
+        .await;
+    let block = json!(server.mine_block());
+
+    assert_eq!(server.result("get_tip", Value::Null).await, block);
+    assert_eq!(server.result("get_block", json!({ "id": 1 })).await, block);
+    assert_eq!(
+        server.result("get_block", json!({ "id": 0 })).await,
+        genesis
+    );
+    assert_eq!(
+        server
+            .result("get_block", json!({ "hash": block["hash"] }))
+            .await,
+        block
+    );
+
+    // Unknown blocks are null
+    assert_eq!(
+        server.result("get_block", json!({ "id": 2 })).await,
+        Value::Null
+    );
+    assert_eq!(
+        server
+            .result("get_block", json!({ "hash": "unknown" }))
+            .await,
+        Value::Null
+    );
+}
+
+#[tokio::test]
+async fn test_errors() {
+    let server = TestServer::start().await;
+
+    assert_eq!(server.error_code("unknown", Value::Null).await, -32601);
+    assert_eq!(
+        server.error_code("get_balance", json!({ "id": 1 })).await,
+        -32602
+    );
+    assert_eq!(server.error_code("get_block", json!([1])).await, -32602);
+
+    // The body is not JSON
+    let (status, response) = server.post("{").await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let response = response.unwrap();
+    assert_eq!(response["error"]["code"], -32700);
+    assert_eq!(response["id"], Value::Null);
+
+    // Another protocol version
+    let request = json!({ "jsonrpc": "1.0", "method": "get_tip", "id": 7 });
+    let (_, response) = server.post(request.to_string()).await;
+    let response = response.unwrap();
+    assert_eq!(response["error"]["code"], -32600);
+    assert_eq!(response["id"], 7);
+
+    // Not a request
+    let (_, response) = server.post("42").await;
+    assert_eq!(response.unwrap()["error"]["code"], -32600);
+    let (_, response) = server.post("[]").await;
+    assert_eq!(response.unwrap()["error"]["code"], -32600);
+}
+
+#[tokio::test]
+async fn test_batch_and_notifications() {
+    let mut server = TestServer::start().await;
+    let transaction = account_creation("account_1", 100);
+
+    // Notifications are applied without being answered
+    let notification = json!({
+        "jsonrpc": "2.0",
+        "method": "submit_transaction",
+        "params": { "transaction": transaction },
+    });
+    let (status, response) = server.post(notification.to_string()).await;
+    assert_eq!(status, reqwest::StatusCode::NO_CONTENT);
+    assert_eq!(response, None);
+    assert_eq!(server.published.try_recv().unwrap(), transaction);
+
+    // Batches are answered in order, without the notifications
+    let batch = json!([
+        { "jsonrpc": "2.0", "method": "get_pool", "id": "pool" },
+        { "jsonrpc": "2.0", "method": "get_tip" },
+        { "jsonrpc": "2.0", "method": "unknown", "id": null },
+        "invalid",
+    ]);
+    let (status, response) = server.post(batch.to_string()).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let response = response.unwrap();
+    let responses = response.as_array().unwrap();
+    assert_eq!(responses.len(), 3);
+    assert_eq!(responses[0]["id"], "pool");
+    assert_eq!(responses[0]["result"], json!([transaction]));
+    assert_eq!(responses[1]["id"], Value::Null);
+    assert_eq!(responses[1]["error"]["code"], -32601);
+    assert_eq!(responses[2]["error"]["code"], -32600);
+}
+
+#[tokio::test]
+async fn test_subscribe() {
+    let server = TestServer::start().await;
+    let mut subscription = server.subscribe("").await;
+    let transaction = account_creation("account_1", 100);
+
+    server
+        .result("submit_transaction", json!({ "transaction": transaction }))
+        .await;
+    assert_eq!(
+        next_event(&mut subscription).await,
+        json!({ "event": "transaction", "transaction": transaction })
+    );
+
+    let block = server.mine_block();
+    assert_eq!(
+        next_event(&mut subscription).await,
+        json!({ "event": "block", "block": block })
+    );
+    assert_eq!(
+        next_event(&mut subscription).await,
+        json!({ "event": "balance", "account_id": "account_1", "balance": 100, "block_id": 1 })
+    );
+}
+
+#[tokio::test]
+async fn test_subscribe_to_accounts() {
+    let server = TestServer::start().await;
+    let mut subscription = server.subscribe("?accounts=account_2,account_3").await;
+    let account_2 = account_creation("account_2", 0);
+
+    for transaction in [account_creation("account_1", 100), account_2.clone()] {
+        server
+            .result("submit_transaction", json!({ "transaction": transaction }))
+            .await;
+    }
+    assert_eq!(
+        next_event(&mut subscription).await,
+        json!({ "event": "transaction", "transaction": account_2 })
+    );
+
+    // Blocks are pushed to every subscriber
+    let block = server.mine_block();
+    assert_eq!(
+        next_event(&mut subscription).await,
+        json!({ "event": "block", "block": block })
+    );
+    assert_eq!(
+        next_event(&mut subscription).await,
+        json!({ "event": "balance", "account_id": "account_2", "balance": 0, "block_id": 1 })
+    );
+
+    // A transfer to a watched account
+    let transfer = Transaction::transfer(
+        &network_id(),
+        "account_1".to_string(),
+        "account_2".to_string(),
+        Amount::new(30),
+        Amount::ZERO,
+        0,
+        &keypair("account_1"),
+    );
+    server
+        .result("submit_transaction", json!({ "transaction": transfer }))
+        .await;
+    assert_eq!(
+        next_event(&mut subscription).await,
+        json!({ "event": "transaction", "transaction": transfer })
+    );
+    server.mine_block();
+    assert_eq!(next_event(&mut subscription).await["event"], "block");
+    assert_eq!(
+        next_event(&mut subscription).await,
+        json!({ "event": "balance", "account_id": "account_2", "balance": 30, "block_id": 2 })
+    );
+}